@@ -0,0 +1,292 @@
+//! Decoding throughput benchmarks.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use bitcoin::absolute::LockTime;
+use bitcoin::block::{Header as BlockHeader, Version};
+use bitcoin::consensus::Decodable;
+use bitcoin::hashes::{sha256d, Hash};
+use bitcoin::p2p::message::{CommandString, NetworkMessage, RawNetworkMessage};
+use bitcoin::transaction::Version as TxVersion;
+use bitcoin::{
+    Amount, Block, BlockHash, CompactTarget, Network, OutPoint, ScriptBuf, Sequence, Transaction,
+    TxIn, TxMerkleNode, TxOut, Txid, Witness,
+};
+use bitcoin_codecs::{DecodeError, Header, HeaderDecoder, V1MessageDecoder, V1MessageEncoder};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use push_decode::decoders::ByteVecDecoder;
+use push_decode::{Decoder, Encoder};
+
+const PAYLOAD_SIZE: usize = 4 * 1024 * 1024;
+// Typical socket read size.
+const CHUNK_SIZE: usize = 64 * 1024;
+// Most headers a peer sends in one message.
+const HEADERS_PER_MESSAGE: u32 = 2000;
+// Transactions in the block compared against `bitcoin`'s decoding.
+const TRANSACTIONS_PER_BLOCK: u32 = 2000;
+
+/// Allocator which counts allocations, to report them per decoded message.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAllocator = CountingAllocator;
+
+/// The original payload path, which buffers the whole payload and hashes it in `end`.
+enum BufferThenHash {
+    Header(HeaderDecoder),
+    Payload(Header, ByteVecDecoder),
+}
+
+impl Decoder for BufferThenHash {
+    type Value = NetworkMessage;
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        if let BufferThenHash::Header(decoder) = self {
+            decoder.decode_chunk(bytes)?;
+            if bytes.is_empty() {
+                return Ok(());
+            }
+            let decoder = core::mem::replace(decoder, HeaderDecoder::new(Network::Bitcoin));
+            let header = decoder.end()?;
+            let payload = ByteVecDecoder::new(header.length as usize);
+            *self = BufferThenHash::Payload(header, payload);
+        }
+        match self {
+            BufferThenHash::Payload(_, payload) => payload
+                .decode_chunk(bytes)
+                .map_err(|_| DecodeError::IncompletePayload),
+            BufferThenHash::Header(_) => unreachable!("header stage ended above"),
+        }
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        let (header, payload) = match self {
+            BufferThenHash::Payload(header, payload) => (header, payload),
+            BufferThenHash::Header(_) => return Err(DecodeError::IncompleteHeader),
+        };
+        let payload = payload.end().map_err(|_| DecodeError::IncompletePayload)?;
+        let hash = sha256d::Hash::hash(&payload);
+        let mut computed = [0u8; 4];
+        computed.copy_from_slice(&hash[..4]);
+        if computed != header.checksum {
+            return Err(DecodeError::InvalidChecksum {
+                command: header.command,
+                expected: header.checksum,
+                computed,
+            });
+        }
+        Ok(NetworkMessage::Unknown {
+            command: header.command,
+            payload,
+        })
+    }
+}
+
+fn feed<D: Decoder>(mut decoder: D, bytes: &[u8], chunk_size: usize) -> D::Value
+where
+    D::Error: core::fmt::Debug,
+{
+    for chunk in bytes.chunks(chunk_size) {
+        decoder.bytes_received(chunk).unwrap();
+    }
+    decoder.end().unwrap()
+}
+
+/// Compare hashing the buffered payload after the fact with hashing chunks as they arrive.
+fn decode(c: &mut Criterion) {
+    let message = NetworkMessage::Unknown {
+        command: CommandString::try_from_static("bench").unwrap(),
+        payload: vec![0xab; PAYLOAD_SIZE],
+    };
+    let mut bytes = Vec::new();
+    V1MessageEncoder::new(Network::Bitcoin, &message).write_to_vec(&mut bytes);
+
+    let mut group = c.benchmark_group("decode_4mib");
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+    group.bench_function("buffer_then_hash", |b| {
+        b.iter_batched(
+            || BufferThenHash::Header(HeaderDecoder::new(Network::Bitcoin)),
+            |decoder| black_box(feed(decoder, &bytes, CHUNK_SIZE)),
+            BatchSize::SmallInput,
+        )
+    });
+    group.bench_function("incremental", |b| {
+        b.iter_batched(
+            || V1MessageDecoder::new(Network::Bitcoin),
+            |decoder| black_box(feed(decoder, &bytes, CHUNK_SIZE)),
+            BatchSize::SmallInput,
+        )
+    });
+    group.finish();
+}
+
+/// A `headers` message as sent during initial block download, each header
+/// linking to the one before it.
+fn headers_message() -> NetworkMessage {
+    let mut prev_blockhash = BlockHash::all_zeros();
+    let headers = (0..HEADERS_PER_MESSAGE)
+        .map(|i| {
+            let header = BlockHeader {
+                version: Version::from_consensus(0x2000_0000),
+                prev_blockhash,
+                merkle_root: TxMerkleNode::hash(&i.to_le_bytes()),
+                time: 1_700_000_000 + i * 600,
+                bits: CompactTarget::from_consensus(0x1703_4219),
+                nonce: i.wrapping_mul(0x9e37_79b9),
+            };
+            prev_blockhash = header.block_hash();
+            header
+        })
+        .collect();
+    NetworkMessage::Headers(headers)
+}
+
+/// Decode full `headers` messages with a decoder reused across the stream.
+///
+/// Split across socket reads the payload is buffered, growing from 64KiB,
+/// which costs a few allocations per message on top of the headers themselves
+/// but only a few percent of throughput, which is bound by checksumming.
+/// Keeping a scratch buffer in the decoder would save those while pinning the largest
+/// payload seen in memory for the life of every connection, so it is not done.
+fn headers(c: &mut Criterion) {
+    let mut bytes = Vec::new();
+    V1MessageEncoder::new(Network::Bitcoin, &headers_message()).write_to_vec(&mut bytes);
+
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    for (name, chunk_size) in [("one_chunk", bytes.len()), ("socket_reads", CHUNK_SIZE)] {
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let message = feed(&mut decoder, &bytes, chunk_size);
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        drop(message);
+        println!("headers/{name}: {allocations} allocations per message");
+    }
+
+    let mut group = c.benchmark_group("headers_2000");
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+    group.bench_function("one_chunk", |b| {
+        b.iter(|| black_box(feed(&mut decoder, &bytes, bytes.len())))
+    });
+    group.bench_function("socket_reads", |b| {
+        b.iter(|| black_box(feed(&mut decoder, &bytes, CHUNK_SIZE)))
+    });
+    group.finish();
+}
+
+/// A block of segwit transactions with two inputs and two outputs each, about
+/// 800KB serialized.
+fn block_message() -> NetworkMessage {
+    let txdata = (0..TRANSACTIONS_PER_BLOCK)
+        .map(|i| Transaction {
+            version: TxVersion::TWO,
+            lock_time: LockTime::ZERO,
+            input: (0..2)
+                .map(|vout| TxIn {
+                    previous_output: OutPoint::new(Txid::hash(&i.to_le_bytes()), vout),
+                    script_sig: ScriptBuf::new(),
+                    sequence: Sequence::MAX,
+                    witness: Witness::from_slice(&[vec![0x30; 72], vec![0x02; 33]]),
+                })
+                .collect(),
+            output: (0..2)
+                .map(|_| TxOut {
+                    value: Amount::from_sat(50_000),
+                    script_pubkey: ScriptBuf::from_bytes(vec![0x00; 22]),
+                })
+                .collect(),
+        })
+        .collect();
+    let header = BlockHeader {
+        version: Version::from_consensus(0x2000_0000),
+        prev_blockhash: BlockHash::all_zeros(),
+        merkle_root: TxMerkleNode::all_zeros(),
+        time: 1_700_000_000,
+        bits: CompactTarget::from_consensus(0x1703_4219),
+        nonce: 0,
+    };
+    NetworkMessage::Block(Block { header, txdata })
+}
+
+/// Decodes every message of a buffered stream with one reused decoder.
+fn codec_stream(bytes: &[u8]) -> usize {
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    let mut count = 0;
+    for message in decoder.decode_all(bytes) {
+        black_box(message.unwrap());
+        count += 1;
+    }
+    count
+}
+
+/// Decodes every message of a buffered stream with `bitcoin`'s own decoding.
+fn consensus_stream(bytes: &[u8]) -> usize {
+    let mut cursor = bitcoin::io::Cursor::new(bytes);
+    let mut count = 0;
+    while (cursor.position() as usize) < bytes.len() {
+        black_box(RawNetworkMessage::consensus_decode(&mut cursor).unwrap());
+        count += 1;
+    }
+    count
+}
+
+/// Compare decoding against `RawNetworkMessage::consensus_decode` over a `Cursor`
+///
+/// Both read the same buffered stream of small (`ping`) and large (`block`)
+/// messages, so the numbers reflect the decoding alone and not I/O. The
+/// sans-io decoder earns its keep when bytes arrive in pieces, which `bitcoin`
+/// can only handle by buffering whole frames first, so this is the case most
+/// favourable to `bitcoin`. Allocations per message are printed before the
+/// timings, run with `just bench versus_bitcoin`.
+fn versus_bitcoin(c: &mut Criterion) {
+    for (name, message, count) in [
+        ("ping", NetworkMessage::Ping(7), 10_000),
+        ("block", block_message(), 10),
+    ] {
+        let mut bytes = Vec::new();
+        for _ in 0..count {
+            V1MessageEncoder::encode_into(Network::Bitcoin, &message, &mut bytes);
+        }
+        for (decoder, decode) in [
+            ("v1_message_decoder", codec_stream as fn(&[u8]) -> usize),
+            ("raw_network_message", consensus_stream),
+        ] {
+            let before = ALLOCATIONS.load(Ordering::Relaxed);
+            assert_eq!(decode(&bytes), count);
+            let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+            println!(
+                "versus_bitcoin_{name}/{decoder}: {:.1} allocations per message",
+                allocations as f64 / count as f64
+            );
+        }
+
+        let mut group = c.benchmark_group(format!("versus_bitcoin_{name}"));
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_function("v1_message_decoder", |b| b.iter(|| codec_stream(&bytes)));
+        group.bench_function("raw_network_message", |b| {
+            b.iter(|| consensus_stream(&bytes))
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, decode, headers, versus_bitcoin);
+criterion_main!(benches);
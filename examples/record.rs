@@ -0,0 +1,79 @@
+//! Records the messages a peer sends to a capture file
+//!
+//! Usage: `cargo run --example record -- <address> <file>`, which defaults to a
+//! local mainnet node and `capture.bin`. Stop it with Ctrl-C, or wait for the
+//! peer to disconnect, then replay the file with `CaptureReader`.
+
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::Network;
+use bitcoin_codecs::{CaptureWriter, DecodeError, EncoderExt, V1MessageDecoder, V1MessageEncoder};
+use push_decode::ReadError;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::net::TcpStream;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut args = std::env::args().skip(1);
+    let address = args.next().unwrap_or_else(|| "127.0.0.1:8333".to_string());
+    let path = args.next().unwrap_or_else(|| "capture.bin".to_string());
+
+    let stream = TcpStream::connect(&address)?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut writer = stream;
+    let mut capture = CaptureWriter::new(BufWriter::new(File::create(&path)?), Network::Bitcoin)?;
+
+    V1MessageEncoder::new(Network::Bitcoin, &create_version_message()).encode_sync(&mut writer)?;
+    writer.flush()?;
+
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    let mut recorded = 0;
+    loop {
+        // Returns as soon as a message is complete, so replies are not held up.
+        let message = match decoder.read_message(&mut reader) {
+            Ok(message) => message,
+            Err(ReadError::Decode(DecodeError::EndOfStream)) => break,
+            Err(e) => return Err(e.into()),
+        };
+        capture.write(&message)?;
+        recorded += 1;
+        println!("Recorded {} ({recorded} so far)", message.command());
+
+        // Keep the peer talking.
+        let reply = match message {
+            NetworkMessage::Version(_) => NetworkMessage::Verack,
+            NetworkMessage::Ping(nonce) => NetworkMessage::Pong(nonce),
+            _ => continue,
+        };
+        V1MessageEncoder::new(Network::Bitcoin, &reply).encode_sync(&mut writer)?;
+        writer.flush()?;
+    }
+
+    capture.into_inner().flush()?;
+    println!("Peer disconnected, {recorded} messages written to {path}");
+    Ok(())
+}
+
+fn create_version_message() -> NetworkMessage {
+    use bitcoin::p2p::message_network::VersionMessage;
+    use bitcoin::p2p::{Address, ServiceFlags};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+
+    let version = VersionMessage {
+        version: 70015,
+        services: ServiceFlags::NONE,
+        timestamp,
+        receiver: Address::new(&"127.0.0.1:8333".parse().unwrap(), ServiceFlags::NONE),
+        sender: Address::new(&"0.0.0.0:0".parse().unwrap(), ServiceFlags::NONE),
+        nonce: 0x1234567890abcdef,
+        user_agent: "/bitcoin-codecs:0.1.0/".to_string(),
+        start_height: 0,
+        relay: false,
+    };
+
+    NetworkMessage::Version(version)
+}
@@ -0,0 +1,37 @@
+//! Decoding from a fixed capacity ring buffer, as an embedded peer would
+
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::Network;
+use bitcoin_codecs::{RingBuffer, V1MessageDecoder, V1MessageEncoder};
+use push_decode::Encoder;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // Stand in for bytes arriving from a UART or radio, a few at a time.
+    let mut input = Vec::new();
+    for message in [
+        NetworkMessage::Ping(1),
+        NetworkMessage::Unknown {
+            command: bitcoin::p2p::message::CommandString::try_from_static("bigmsg")?,
+            payload: vec![0xab; 500],
+        },
+        NetworkMessage::Pong(1),
+    ] {
+        V1MessageEncoder::new(Network::Bitcoin, &message).write_to_vec(&mut input);
+    }
+
+    // Much smaller than the second message, whose payload wraps many times.
+    let mut ring = RingBuffer::<64>::new();
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    let mut input = &input[..];
+
+    while !input.is_empty() || !ring.is_empty() {
+        let written = ring.write(&input[..input.len().min(7)]);
+        input = &input[written..];
+
+        while let Some(message) = ring.decode(&mut decoder)? {
+            println!("Received: {:?}", message.cmd());
+        }
+    }
+
+    Ok(())
+}
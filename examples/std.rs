@@ -2,8 +2,8 @@
 
 use bitcoin::p2p::message::NetworkMessage;
 use bitcoin::Network;
-use bitcoin_codecs::V1MessageDecoder;
-use push_decode::decode_sync_with;
+use bitcoin_codecs::{DecodeError, DecoderExt, EncoderExt, V1MessageDecoder, V1MessageEncoder};
+use push_decode::ReadError;
 use std::io::{BufReader, Write};
 use std::net::TcpStream;
 
@@ -12,12 +12,21 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut reader = BufReader::new(stream.try_clone()?);
     let mut writer = stream;
 
-    let version_msg = create_version_message();
-    writer.write_all(&version_msg)?;
+    V1MessageEncoder::new(Network::Bitcoin, &create_version_message()).encode_sync(&mut writer)?;
     writer.flush()?;
 
+    // A single decoder is reused for every message on the connection.
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
     loop {
-        let message = decode_sync_with(&mut reader, V1MessageDecoder::new(Network::Bitcoin))?;
+        let message = match (&mut decoder).decode_sync(&mut reader) {
+            Ok(message) => message,
+            // The peer closed the connection between messages.
+            Err(ReadError::Decode(DecodeError::EndOfStream)) => {
+                println!("Peer disconnected");
+                return Ok(());
+            }
+            Err(e) => return Err(e.into()),
+        };
 
         println!("Received: {:?}", message.cmd());
 
@@ -26,14 +35,14 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 println!("  Version: {}", version.version);
                 println!("  User Agent: {}", version.user_agent);
                 println!("  Services: {:?}", version.services);
-                let verack = create_verack_message();
-                writer.write_all(&verack)?;
+                V1MessageEncoder::new(Network::Bitcoin, &NetworkMessage::Verack)
+                    .encode_sync(&mut writer)?;
                 writer.flush()?;
             }
             NetworkMessage::Ping(nonce) => {
                 println!("  Ping nonce: {nonce}");
-                let pong = create_pong_message(nonce);
-                writer.write_all(&pong)?;
+                V1MessageEncoder::new(Network::Bitcoin, &NetworkMessage::Pong(nonce))
+                    .encode_sync(&mut writer)?;
                 writer.flush()?;
             }
             _ => {}
@@ -41,10 +50,9 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     }
 }
 
-fn create_version_message() -> Vec<u8> {
+fn create_version_message() -> NetworkMessage {
     use bitcoin::p2p::message_network::VersionMessage;
     use bitcoin::p2p::{Address, ServiceFlags};
-    use bitcoin::{consensus::encode, p2p::message::RawNetworkMessage};
     use std::time::{SystemTime, UNIX_EPOCH};
 
     let timestamp = SystemTime::now()
@@ -64,23 +72,5 @@ fn create_version_message() -> Vec<u8> {
         relay: false,
     };
 
-    let msg = RawNetworkMessage::new(Network::Bitcoin.magic(), NetworkMessage::Version(version));
-
-    encode::serialize(&msg)
-}
-
-fn create_verack_message() -> Vec<u8> {
-    use bitcoin::{consensus::encode, p2p::message::RawNetworkMessage};
-
-    let msg = RawNetworkMessage::new(Network::Bitcoin.magic(), NetworkMessage::Verack);
-
-    encode::serialize(&msg)
-}
-
-fn create_pong_message(nonce: u64) -> Vec<u8> {
-    use bitcoin::{consensus::encode, p2p::message::RawNetworkMessage};
-
-    let msg = RawNetworkMessage::new(Network::Bitcoin.magic(), NetworkMessage::Pong(nonce));
-
-    encode::serialize(&msg)
+    NetworkMessage::Version(version)
 }
@@ -2,8 +2,8 @@
 
 use bitcoin::p2p::message::NetworkMessage;
 use bitcoin::Network;
-use bitcoin_codecs::V1MessageDecoder;
-use push_decode::decode_tokio_with;
+use bitcoin_codecs::{DecodeError, DecoderExt, EncoderExt, V1MessageDecoder, V1MessageEncoder};
+use push_decode::ReadError;
 use tokio::io::{AsyncWriteExt, BufReader};
 use tokio::net::TcpStream;
 
@@ -13,12 +13,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let (reader, mut writer) = stream.into_split();
     let mut reader = BufReader::new(reader);
 
-    let version_msg = create_version_message();
-    writer.write_all(&version_msg).await?;
+    V1MessageEncoder::new(Network::Bitcoin, &create_version_message())
+        .encode_tokio(&mut writer)
+        .await?;
     writer.flush().await?;
 
+    // A single decoder is reused for every message on the connection.
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
     loop {
-        match decode_tokio_with(&mut reader, V1MessageDecoder::new(Network::Bitcoin)).await {
+        match (&mut decoder).decode_tokio(&mut reader).await {
             Ok(message) => {
                 println!("Received: {:?}", message.cmd());
 
@@ -26,19 +29,26 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                     NetworkMessage::Version(version) => {
                         println!("  Version: {}", version.version);
                         println!("  User Agent: {}", version.user_agent);
-                        let verack = create_verack_message();
-                        writer.write_all(&verack).await?;
+                        V1MessageEncoder::new(Network::Bitcoin, &NetworkMessage::Verack)
+                            .encode_tokio(&mut writer)
+                            .await?;
                         writer.flush().await?;
                     }
                     NetworkMessage::Ping(nonce) => {
                         println!("  Ping nonce: {nonce}");
-                        let pong = create_pong_message(nonce);
-                        writer.write_all(&pong).await?;
+                        V1MessageEncoder::new(Network::Bitcoin, &NetworkMessage::Pong(nonce))
+                            .encode_tokio(&mut writer)
+                            .await?;
                         writer.flush().await?;
                     }
                     _ => {}
                 }
             }
+            // The peer closed the connection between messages.
+            Err(ReadError::Decode(DecodeError::EndOfStream)) => {
+                println!("Peer disconnected");
+                break;
+            }
             Err(e) => {
                 eprintln!("Error: {e:?}");
                 break;
@@ -49,10 +59,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
-fn create_version_message() -> Vec<u8> {
+fn create_version_message() -> NetworkMessage {
     use bitcoin::p2p::message_network::VersionMessage;
     use bitcoin::p2p::{Address, ServiceFlags};
-    use bitcoin::{consensus::encode, p2p::message::RawNetworkMessage};
     use std::time::{SystemTime, UNIX_EPOCH};
 
     let timestamp = SystemTime::now()
@@ -72,23 +81,5 @@ fn create_version_message() -> Vec<u8> {
         relay: false,
     };
 
-    let msg = RawNetworkMessage::new(Network::Bitcoin.magic(), NetworkMessage::Version(version));
-
-    encode::serialize(&msg)
-}
-
-fn create_verack_message() -> Vec<u8> {
-    use bitcoin::{consensus::encode, p2p::message::RawNetworkMessage};
-
-    let msg = RawNetworkMessage::new(Network::Bitcoin.magic(), NetworkMessage::Verack);
-
-    encode::serialize(&msg)
-}
-
-fn create_pong_message(nonce: u64) -> Vec<u8> {
-    use bitcoin::{consensus::encode, p2p::message::RawNetworkMessage};
-
-    let msg = RawNetworkMessage::new(Network::Bitcoin.magic(), NetworkMessage::Pong(nonce));
-
-    encode::serialize(&msg)
+    NetworkMessage::Version(version)
 }
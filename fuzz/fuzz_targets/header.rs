@@ -0,0 +1,46 @@
+//! Headers, and whatever follows them, must be rejected with an error and never panic.
+//!
+//! The first 24 bytes are a header and the rest its payload. The seed corpus in
+//! `corpus/header` holds frames with valid magic so the fuzzer starts from
+//! headers which get past the magic check.
+
+#![no_main]
+
+use bitcoin::p2p::Magic;
+use bitcoin::Network;
+use bitcoin_codecs::{HeaderDecoder, V1MessageDecoder, HEADER_SIZE};
+use libfuzzer_sys::fuzz_target;
+use push_decode::Decoder;
+
+fuzz_target!(|data: &[u8]| {
+    let Some((header, _)) = data.split_first_chunk::<HEADER_SIZE>() else {
+        return;
+    };
+
+    // Expecting the header's own magic puts every command and length through
+    // the checks, and the buffered fast path has to agree with the decoder.
+    let magic = Magic::from_bytes(header[..4].try_into().expect("4 bytes"));
+    let buffered = HeaderDecoder::with_magic(magic).decode_array(header);
+    let mut decoder = HeaderDecoder::with_magic(magic);
+    let pushed = decoder
+        .decode_chunk(&mut &header[..])
+        .and_then(|()| decoder.end());
+    assert_eq!(buffered, pushed);
+
+    // Whole frames in a single chunk and byte by byte, recovering from errors
+    // so the bytes after a bad frame are decoded as well.
+    for network in [Network::Bitcoin, Network::Regtest] {
+        let _ = V1MessageDecoder::builder()
+            .network(network)
+            .recovery(true)
+            .build()
+            .decode_resilient(data);
+
+        let mut decoder = V1MessageDecoder::new(network);
+        for byte in data.chunks(1) {
+            if decoder.feed(&mut &byte[..]).is_err() {
+                decoder.reset();
+            }
+        }
+    }
+});
@@ -0,0 +1,103 @@
+//! Decoding messages ahead of the code which handles them.
+
+use core::future::Future;
+use core::pin::pin;
+use core::task::Poll;
+
+use bitcoin::p2p::message::NetworkMessage;
+use push_decode::ReadError;
+use tokio::io::{AsyncRead, BufReader};
+use tokio::sync::mpsc;
+
+use crate::{DecodeError, V1MessageDecoder};
+
+/// Item of the channel filled by a [`DecodeAhead`] task.
+pub type DecodeAheadItem = Result<NetworkMessage, ReadError<DecodeError>>;
+
+/// Task which keeps decoding messages from a reader in to a bounded channel
+///
+/// Reading and decoding the next messages overlaps with handling the current
+/// one, up to `capacity` messages ahead, after which the task waits for the
+/// consumer to catch up. Created with [`new`](Self::new), which also returns
+/// the receiving end, and driven by spawning [`run`](Self::run) on whatever
+/// runtime the caller uses.
+///
+/// Errors the decoder recovers from, such as [`DecodeError::Skipped`], are
+/// sent along and decoding carries on. Any other error is sent as the last
+/// item, since the framing can not be trusted past it. A peer closing the
+/// connection between messages simply closes the channel, and dropping the
+/// receiver stops the task even while it waits on the reader.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// use bitcoin::Network;
+/// use bitcoin_codecs::{DecodeAhead, V1MessageDecoder};
+/// use tokio::net::TcpStream;
+///
+/// let (reader, _writer) = TcpStream::connect("127.0.0.1:8333").await?.into_split();
+/// let (task, mut messages) = DecodeAhead::new(reader, V1MessageDecoder::new(Network::Bitcoin), 16);
+/// tokio::spawn(task.run());
+/// while let Some(message) = messages.recv().await {
+///     println!("{:?}", message?);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct DecodeAhead<R> {
+    reader: BufReader<R>,
+    decoder: V1MessageDecoder,
+    sender: mpsc::Sender<DecodeAheadItem>,
+}
+
+impl<R: AsyncRead + Unpin> DecodeAhead<R> {
+    /// Creates a task decoding from `reader` up to `capacity` messages ahead
+    ///
+    /// # Panics
+    ///
+    /// If `capacity` is zero, as for [`mpsc::channel`].
+    pub fn new(
+        reader: R,
+        decoder: V1MessageDecoder,
+        capacity: usize,
+    ) -> (Self, mpsc::Receiver<DecodeAheadItem>) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        let task = Self {
+            reader: BufReader::new(reader),
+            decoder,
+            sender,
+        };
+        (task, receiver)
+    }
+
+    /// Decodes until the stream ends, a fatal error is sent or the receiver is dropped
+    ///
+    /// Returns the decoder, which holds any partial message when the receiver
+    /// went away part way through one and needs a
+    /// [`reset`](V1MessageDecoder::reset) after a fatal error.
+    pub async fn run(mut self) -> V1MessageDecoder {
+        while let Some(result) = self.read_unless_closed().await {
+            let fatal = match &result {
+                Ok(_) => false,
+                Err(ReadError::Decode(DecodeError::EndOfStream)) => break,
+                Err(ReadError::Decode(e)) => !self.decoder.inner.is_recoverable(e),
+                Err(ReadError::Read(_)) => true,
+            };
+            if self.sender.send(result).await.is_err() || fatal {
+                break;
+            }
+        }
+        self.decoder
+    }
+
+    /// Reads the next message, or `None` once the receiver is dropped.
+    async fn read_unless_closed(&mut self) -> Option<DecodeAheadItem> {
+        // Reading is cancel safe, so giving up on it loses nothing.
+        let mut read = pin!(self.decoder.read_message_tokio(&mut self.reader));
+        let mut closed = pin!(self.sender.closed());
+        core::future::poll_fn(|cx| match closed.as_mut().poll(cx) {
+            Poll::Ready(()) => Poll::Ready(None),
+            Poll::Pending => read.as_mut().poll(cx).map(Some),
+        })
+        .await
+    }
+}
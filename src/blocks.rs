@@ -0,0 +1,352 @@
+//! Decoding and encoding blocks one transaction at a time.
+
+use bitcoin::block;
+use bitcoin::consensus::{encode, Decodable, Encodable};
+use bitcoin::p2p::message::{CommandString, NetworkMessage};
+use bitcoin::{Block, Network, Transaction, VarInt};
+use push_decode::{Decoder, Encoder};
+
+use crate::checksum::finish;
+use crate::{
+    Checksum, DecodeError, Header, HeaderDecoder, PayloadDecoder, PayloadOptions, Sha256d,
+    HEADER_SIZE,
+};
+
+/// Size in bytes of a serialized block header.
+const BLOCK_HEADER_SIZE: usize = 80;
+
+/// A message decoded by a [`V1BlockDecoder`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BlockMessage {
+    /// A `block` whose transactions were all handed to the sink.
+    Block {
+        /// The block's header.
+        header: block::Header,
+        /// Number of transactions in the block.
+        transactions: u64,
+    },
+    /// Any other message, decoded as by [`V1MessageDecoder`](crate::V1MessageDecoder).
+    Message(NetworkMessage),
+}
+
+/// Decoder for Bitcoin V1 protocol messages which streams block transactions to a sink
+///
+/// A `block` is the largest message by far, and deserializing it whole means
+/// holding every transaction at once. This decoder parses a `block` payload as
+/// it arrives instead, handing each [`Transaction`] to the sink as soon as its
+/// last byte is in, so only the transaction being received is buffered. The
+/// decoded value is then just the block's header and transaction count. All
+/// other commands are decoded in full as usual.
+///
+/// Like [`V1StreamingDecoder`](crate::V1StreamingDecoder) the checksum can only
+/// be verified once the whole payload is in, after the sink has seen every
+/// transaction, so the sink should not act on them until decoding succeeds.
+///
+/// [`Decoder`] is implemented for `&mut V1BlockDecoder`, which resets the
+/// decoder once a message is returned so one instance can decode a whole
+/// stream. After an error it must be [`reset`](Self::reset) before it is used
+/// again.
+pub struct V1BlockDecoder<S> {
+    header: HeaderDecoder,
+    header_received: usize,
+    stage: Stage,
+    sink: S,
+}
+
+impl<S: FnMut(Transaction)> V1BlockDecoder<S> {
+    /// Creates a new V1 block decoder for the specified network
+    pub fn new(network: Network, sink: S) -> Self {
+        Self {
+            header: HeaderDecoder::new(network),
+            header_received: 0,
+            stage: Stage::Header,
+            sink,
+        }
+    }
+
+    /// Discards any partially decoded message, keeping the sink
+    pub fn reset(&mut self) {
+        self.header.reset();
+        self.header_received = 0;
+        self.stage = Stage::Header;
+    }
+
+    /// Returns the sink.
+    pub fn into_sink(self) -> S {
+        self.sink
+    }
+
+    /// Takes the completed header and picks the stage for its payload.
+    fn start_payload(&mut self) -> Result<(), DecodeError> {
+        let fresh = HeaderDecoder::from_magic(self.header.expected_magic, self.header.max_payload);
+        let decoder = core::mem::replace(&mut self.header, fresh);
+        let header = decoder.end()?;
+        self.stage = if header.command.as_ref() == "block" {
+            Stage::Block(BlockPayload::new(header))
+        } else {
+            Stage::Message(PayloadDecoder::new(header, PayloadOptions::default()))
+        };
+        Ok(())
+    }
+}
+
+/// Stages of decoding a message which may be a block.
+enum Stage {
+    Header,
+    Message(PayloadDecoder),
+    Block(BlockPayload),
+}
+
+impl<S: FnMut(Transaction)> Decoder for &mut V1BlockDecoder<S> {
+    type Value = BlockMessage;
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        if let Stage::Header = self.stage {
+            let len = bytes.len();
+            self.header.decode_chunk(bytes)?;
+            self.header_received += len - bytes.len();
+            if self.header_received < HEADER_SIZE {
+                return Ok(());
+            }
+            self.start_payload()?;
+        }
+
+        match &mut self.stage {
+            Stage::Message(decoder) => decoder.decode_chunk(bytes),
+            Stage::Block(decoder) => decoder.decode_chunk(bytes, &mut self.sink),
+            Stage::Header => unreachable!("the header is complete"),
+        }
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        if let Stage::Header = self.stage {
+            if self.header_received == 0 {
+                return Err(DecodeError::EndOfStream);
+            }
+            let result = self.start_payload();
+            self.header_received = 0;
+            result?;
+        }
+
+        self.header_received = 0;
+        match core::mem::replace(&mut self.stage, Stage::Header) {
+            Stage::Message(decoder) => decoder
+                .end()
+                .map(|(_, message)| BlockMessage::Message(message)),
+            Stage::Block(decoder) => decoder.end(&mut self.sink),
+            Stage::Header => unreachable!("the header is complete"),
+        }
+    }
+}
+
+/// Decoder which parses transactions out of a block payload as they arrive.
+struct BlockPayload {
+    header: Header,
+    remaining: usize,
+    engine: <Sha256d as Checksum>::Engine,
+    // Bytes received which are not yet part of a parsed item.
+    buffer: Vec<u8>,
+    block_header: Option<block::Header>,
+    transactions: Option<u64>,
+    parsed: u64,
+    // Buffered length before a partial transaction is parsed again, so one
+    // arriving in many small chunks is not reparsed for each of them.
+    retry_at: usize,
+}
+
+impl BlockPayload {
+    fn new(header: Header) -> Self {
+        Self {
+            remaining: header.length as usize,
+            header,
+            engine: Sha256d::engine(),
+            buffer: Vec::new(),
+            block_header: None,
+            transactions: None,
+            parsed: 0,
+            retry_at: 0,
+        }
+    }
+
+    fn decode_chunk(
+        &mut self,
+        bytes: &mut &[u8],
+        sink: &mut impl FnMut(Transaction),
+    ) -> Result<(), DecodeError> {
+        let (chunk, rest) = bytes.split_at(bytes.len().min(self.remaining));
+        *bytes = rest;
+        Sha256d::input(&mut self.engine, chunk);
+        self.buffer.extend_from_slice(chunk);
+        self.remaining -= chunk.len();
+        if self.buffer.len() >= self.retry_at || self.remaining == 0 {
+            self.parse(sink).map_err(DecodeError::InvalidPayload)?;
+        }
+        Ok(())
+    }
+
+    /// Parses as many whole items out of the buffer as it holds.
+    fn parse(&mut self, sink: &mut impl FnMut(Transaction)) -> Result<(), encode::Error> {
+        let mut reader = &self.buffer[..];
+        let mut partial = false;
+        loop {
+            let before = reader;
+            let result = if self.block_header.is_none() {
+                if reader.len() < BLOCK_HEADER_SIZE {
+                    break;
+                }
+                block::Header::consensus_decode(&mut reader).map(|h| self.block_header = Some(h))
+            } else if self.transactions.is_none() {
+                VarInt::consensus_decode(&mut reader).map(|n| self.transactions = Some(n.0))
+            } else if self.transactions == Some(self.parsed) {
+                break;
+            } else {
+                Transaction::consensus_decode(&mut reader).map(|tx| {
+                    self.parsed += 1;
+                    sink(tx)
+                })
+            };
+            match result {
+                Ok(()) => {}
+                Err(encode::Error::Io(e)) if e.kind() == bitcoin::io::ErrorKind::UnexpectedEof => {
+                    reader = before;
+                    partial = true;
+                    break;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+        let consumed = self.buffer.len() - reader.len();
+        self.buffer.drain(..consumed);
+        self.retry_at = if partial { self.buffer.len() * 2 } else { 0 };
+        Ok(())
+    }
+
+    fn end(mut self, sink: &mut impl FnMut(Transaction)) -> Result<BlockMessage, DecodeError> {
+        if self.remaining > 0 {
+            return Err(DecodeError::IncompletePayload);
+        }
+
+        // Validate checksum
+        let engine = core::mem::replace(&mut self.engine, Sha256d::engine());
+        let computed = finish::<Sha256d>(engine);
+        if computed != self.header.checksum {
+            return Err(DecodeError::InvalidChecksum {
+                command: self.header.command,
+                expected: self.header.checksum,
+                computed,
+            });
+        }
+
+        self.parse(sink).map_err(DecodeError::InvalidPayload)?;
+        match (self.block_header, self.transactions) {
+            (Some(header), Some(transactions)) if transactions == self.parsed => {
+                if !self.buffer.is_empty() {
+                    return Err(DecodeError::TrailingPayloadBytes(self.buffer.len()));
+                }
+                Ok(BlockMessage::Block {
+                    header,
+                    transactions,
+                })
+            }
+            // The payload ran out part way through an item.
+            _ => Err(DecodeError::InvalidPayload(encode::Error::Io(
+                bitcoin::io::ErrorKind::UnexpectedEof.into(),
+            ))),
+        }
+    }
+}
+
+/// Encoder for a `block` message which serializes one transaction at a time
+///
+/// A V1 header carries the payload's checksum, so the whole payload has to be
+/// hashed before the first byte can be sent. [`V1MessageEncoder`] does so by
+/// serializing the payload in to a buffer which is then written out, a second
+/// copy of the block. This encoder takes two passes over the block instead:
+/// the first serializes it in to the hash to find the length and checksum,
+/// the second serializes it again a transaction at a time as the chunks are
+/// pulled. Only the transaction being written is buffered, at the cost of
+/// serializing the block twice.
+///
+/// The V2 transport puts its authentication tag after the packet contents, so
+/// only the length has to be known up front there. Its packets are encrypted
+/// whole by [`V2MessageEncoder`](crate::V2MessageEncoder) though.
+///
+/// [`V1MessageEncoder`]: crate::V1MessageEncoder
+pub struct V1BlockEncoder<'a> {
+    block: &'a Block,
+    // Transaction serialized in the current chunk, none while it is the prefix.
+    transaction: Option<usize>,
+    chunk: Vec<u8>,
+}
+
+impl<'a> V1BlockEncoder<'a> {
+    /// Creates an encoder for `block` framed for `network`
+    pub fn new(network: Network, block: &'a Block) -> Self {
+        let mut hasher = HashWriter {
+            engine: Sha256d::engine(),
+            length: 0,
+        };
+        block
+            .consensus_encode(&mut hasher)
+            .expect("hashing is infallible");
+        let header = Header {
+            magic: network.magic(),
+            command: CommandString::try_from_static("block").expect("block is a valid command"),
+            length: u32::try_from(hasher.length).expect("blocks are far smaller than 4GiB"),
+            checksum: finish::<Sha256d>(hasher.engine),
+        };
+
+        // The first chunk runs up to the first transaction.
+        let mut chunk = header.to_bytes().to_vec();
+        block
+            .header
+            .consensus_encode(&mut chunk)
+            .expect("writing to a vec is infallible");
+        VarInt(block.txdata.len() as u64)
+            .consensus_encode(&mut chunk)
+            .expect("writing to a vec is infallible");
+        Self {
+            block,
+            transaction: None,
+            chunk,
+        }
+    }
+}
+
+impl Encoder for V1BlockEncoder<'_> {
+    fn encoded_chunk(&self) -> &[u8] {
+        &self.chunk
+    }
+
+    fn next(&mut self) -> bool {
+        let next = self.transaction.map_or(0, |i| i + 1);
+        let Some(transaction) = self.block.txdata.get(next) else {
+            return false;
+        };
+        self.chunk.clear();
+        transaction
+            .consensus_encode(&mut self.chunk)
+            .expect("writing to a vec is infallible");
+        self.transaction = Some(next);
+        true
+    }
+}
+
+/// Hashes everything written to it, counting the bytes.
+struct HashWriter {
+    engine: <Sha256d as Checksum>::Engine,
+    length: usize,
+}
+
+impl bitcoin::io::Write for HashWriter {
+    fn write(&mut self, buf: &[u8]) -> bitcoin::io::Result<usize> {
+        Sha256d::input(&mut self.engine, buf);
+        self.length += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> bitcoin::io::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,93 @@
+//! Decoding from and encoding in to `bytes` buffers.
+
+use bitcoin::consensus::Encodable;
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::p2p::Magic;
+use bitcoin::Network;
+use bytes::{Buf, BufMut, BytesMut};
+
+use crate::{
+    write_header, Checksum, DecodeError, HeaderDecoder, Sha256d, Stage, V1MessageDecoder,
+    V1MessageEncoder, HEADER_SIZE,
+};
+
+impl<C: Checksum> V1MessageDecoder<C> {
+    /// Decodes the message at the front of `src` once all of it has arrived
+    ///
+    /// For network buffers which are continuously appended to. A frame is only
+    /// decoded once `src` holds the whole of it, `src` is then advanced past
+    /// it. Until then `Ok(None)` is returned and `src` is left untouched, so the
+    /// next call can retry once more bytes are in. A header which fails the
+    /// checks is reported as soon as it is in rather than waiting on a payload
+    /// which will never be decoded. A frame the decoder drains or skips is
+    /// consumed as it arrives instead, so its announced length can not make
+    /// `src` grow to hold it.
+    ///
+    /// After an error for a complete frame `src` is advanced past it, and the
+    /// decoder is left as by [`feed`](Self::feed). The decoder must not hold a
+    /// partial message from [`feed`](Self::feed) or a [`Decoder`] pass when
+    /// this is called.
+    ///
+    /// [`Decoder`]: push_decode::Decoder
+    pub fn decode_bytes(
+        &mut self,
+        src: &mut BytesMut,
+    ) -> Result<Option<NetworkMessage>, DecodeError> {
+        if self.inner.is_discarding() {
+            return self.feed_bytes(src);
+        }
+        let Some(header) = src.first_chunk::<HEADER_SIZE>() else {
+            return Ok(None);
+        };
+        let length = u32::from_le_bytes(header[16..20].try_into().expect("4 bytes")) as usize;
+        if src.len() - HEADER_SIZE < length {
+            let decoder =
+                HeaderDecoder::from_magic(self.inner.expected_magic, self.inner.max_payload);
+            match decoder.decode_array(header) {
+                Err(e) if !self.inner.drains(&e) => return Err(e),
+                Err(_) => return self.feed_bytes(src),
+                Ok(header) => match self.inner.payload_stage(header)? {
+                    Stage::Skip { .. } => return self.feed_bytes(src),
+                    _ => return Ok(None),
+                },
+            }
+        }
+
+        let frame = HEADER_SIZE + length;
+        let mut bytes = &src[..frame];
+        let result = self.feed(&mut bytes);
+        let consumed = frame - bytes.len();
+        src.advance(consumed);
+        result
+    }
+
+    /// Feeds all of `src` and advances it past what was consumed.
+    fn feed_bytes(&mut self, src: &mut BytesMut) -> Result<Option<NetworkMessage>, DecodeError> {
+        let mut bytes = &src[..];
+        let result = self.feed(&mut bytes);
+        let consumed = src.len() - bytes.len();
+        src.advance(consumed);
+        result
+    }
+}
+
+impl V1MessageEncoder {
+    /// Appends the framed message to `dst`
+    ///
+    /// The [`BytesMut`] counterpart of [`encode_into`](Self::encode_into), the
+    /// message is serialized straight in to `dst`.
+    pub fn encode_bytes(network: Network, message: &NetworkMessage, dst: &mut BytesMut) {
+        frame_into_bytes(network.magic(), message, dst);
+    }
+}
+
+/// Appends `message` framed with `magic` to `dst`.
+pub(crate) fn frame_into_bytes(magic: Magic, message: &NetworkMessage, dst: &mut BytesMut) {
+    let start = dst.len();
+    dst.put_bytes(0, HEADER_SIZE);
+    message
+        .consensus_encode(&mut bitcoin::io::FromStd::new((&mut *dst).writer()))
+        .expect("writing to a buffer is infallible");
+    let (header, payload) = dst[start..].split_at_mut(HEADER_SIZE);
+    write_header::<Sha256d>(header, magic, message, payload);
+}
@@ -0,0 +1,204 @@
+//! Configuring a V1 decoder one option at a time.
+
+use core::marker::PhantomData;
+use std::sync::Arc;
+
+use bitcoin::p2p::Magic;
+use bitcoin::Network;
+
+use crate::{
+    Checksum, HeaderDecoder, LengthLimits, PayloadBufferSource, PayloadOptions, Sha256d,
+    V1DecoderInner, V1MessageDecoder, MAX_PAYLOAD_SIZE,
+};
+
+/// Builder for a [`V1MessageDecoder`] which combines any of its options
+///
+/// Created with [`V1MessageDecoder::builder`]. Each option has the same effect
+/// as the constructor of the same name, for example
+/// [`verify_checksum(false)`](Self::verify_checksum) matches
+/// [`V1MessageDecoder::without_checksum`], and options which are not set keep
+/// the defaults of [`V1MessageDecoder::new`] for mainnet.
+///
+/// ```
+/// use bitcoin::Network;
+/// use bitcoin_codecs::V1MessageDecoder;
+///
+/// let decoder = V1MessageDecoder::builder()
+///     .network(Network::Signet)
+///     .max_payload(4_000_000)
+///     .verify_checksum(false)
+///     .allowed_commands(&["headers", "ping"])
+///     .build();
+/// ```
+#[derive(Clone, Debug)]
+pub struct V1MessageDecoderBuilder<C = Sha256d> {
+    magic: Magic,
+    max_payload: usize,
+    options: PayloadOptions,
+    allowed: Option<Vec<String>>,
+    drain_oversized: bool,
+    recover_invalid: bool,
+    lengths: Option<LengthLimits>,
+    buffers: Option<Arc<dyn PayloadBufferSource>>,
+    detect_foreign: bool,
+    checksum: PhantomData<fn() -> C>,
+}
+
+impl Default for V1MessageDecoderBuilder {
+    fn default() -> Self {
+        Self {
+            magic: Network::Bitcoin.magic(),
+            max_payload: MAX_PAYLOAD_SIZE,
+            options: PayloadOptions::default(),
+            allowed: None,
+            drain_oversized: false,
+            recover_invalid: false,
+            lengths: None,
+            buffers: None,
+            detect_foreign: false,
+            checksum: PhantomData,
+        }
+    }
+}
+
+impl<C: Checksum> V1MessageDecoderBuilder<C> {
+    /// Expects the magic of `network`, mainnet by default
+    pub fn network(self, network: Network) -> Self {
+        self.magic(network.magic())
+    }
+
+    /// Expects `magic`, for networks without a [`Network`] variant
+    pub fn magic(mut self, magic: Magic) -> Self {
+        self.magic = magic;
+        self
+    }
+
+    /// Rejects payloads larger than `max` bytes, [`MAX_PAYLOAD_SIZE`] by default
+    pub fn max_payload(mut self, max: usize) -> Self {
+        self.max_payload = max;
+        self
+    }
+
+    /// Drains payloads over the maximum before rejecting them, off by default
+    pub fn drain_oversized(mut self, drain: bool) -> Self {
+        self.drain_oversized = drain;
+        self
+    }
+
+    /// Verifies payload checksums, on by default
+    pub fn verify_checksum(mut self, verify: bool) -> Self {
+        self.options.skip_checksum = !verify;
+        self
+    }
+
+    /// Returns payloads which fail to deserialize raw, off by default
+    pub fn unknown_fallback(mut self, fallback: bool) -> Self {
+        self.options.unknown_fallback = fallback;
+        self
+    }
+
+    /// Grows payload buffers as bytes arrive, off by default
+    pub fn incremental_allocation(mut self, incremental: bool) -> Self {
+        self.options.incremental_alloc = incremental;
+        self
+    }
+
+    /// Accepts payloads with bytes left over, off by default
+    pub fn ignore_trailing_bytes(mut self, ignore: bool) -> Self {
+        self.options.ignore_trailing = ignore;
+        self
+    }
+
+    /// Only deserializes the `allowed` commands, all of them by default
+    pub fn allowed_commands(mut self, allowed: &[&str]) -> Self {
+        self.allowed = Some(allowed.iter().map(|c| c.to_string()).collect());
+        self
+    }
+
+    /// Checks payload lengths against `lengths`, unchecked by default
+    pub fn length_limits(mut self, lengths: LengthLimits) -> Self {
+        self.lengths = Some(lengths);
+        self
+    }
+
+    /// Decodes payloads at protocol `version`, the latest by default
+    ///
+    /// See [`V1MessageDecoder::set_protocol_version`].
+    pub fn protocol_version(mut self, version: u32) -> Self {
+        self.options.protocol_version = Some(version);
+        self
+    }
+
+    /// Accepts a `version` missing its optional trailing fields, off by default
+    ///
+    /// Some implementations send a minimal `version` which ends early, as
+    /// Bitcoin Core still accepts. Any of the sender's address and nonce, user
+    /// agent, start height and relay flag which are missing from the end are
+    /// then filled in with an unspecified address, a zero nonce, an empty user
+    /// agent, a start height of zero and relaying on. A `version` which ends
+    /// part way through a field is still rejected.
+    pub fn lenient_version(mut self, lenient: bool) -> Self {
+        self.options.lenient_version = lenient;
+        self
+    }
+
+    /// Steps over invalid commands and payloads, off by default
+    pub fn recovery(mut self, recover: bool) -> Self {
+        self.recover_invalid = recover;
+        self
+    }
+
+    /// Takes payload buffers from `source`, allocating each one by default
+    ///
+    /// Payloads split across chunks are then read in to recycled buffers, such
+    /// as those of a [`BufferPool`](crate::BufferPool) shared between
+    /// connections. A parser which keeps the raw payload, as an unknown command
+    /// does, gets a copy rather than the buffer itself.
+    pub fn buffer_source(mut self, source: Arc<dyn PayloadBufferSource>) -> Self {
+        self.buffers = Some(source);
+        self
+    }
+
+    /// Reports a stream which does not start with any network's magic, off by default
+    ///
+    /// A listening server can tell a client of another protocol, which fails
+    /// with [`DecodeError::NotBitcoinProtocol`](crate::DecodeError::NotBitcoinProtocol), from a bitcoin peer on the
+    /// wrong network, which still fails with [`DecodeError::WrongMagic`](crate::DecodeError::WrongMagic), and
+    /// drop the former without logging it as a misconfigured peer. Only the
+    /// first header is checked this way, a bad magic later on means the stream
+    /// lost its framing. Networks without a [`Network`] variant, such as a
+    /// custom signet, count as another protocol.
+    pub fn detect_foreign_protocol(mut self, detect: bool) -> Self {
+        self.detect_foreign = detect;
+        self
+    }
+
+    /// Verifies payloads with checksum `D`, [`Sha256d`] by default
+    pub fn checksum<D: Checksum>(self) -> V1MessageDecoderBuilder<D> {
+        V1MessageDecoderBuilder {
+            magic: self.magic,
+            max_payload: self.max_payload,
+            options: self.options,
+            allowed: self.allowed,
+            drain_oversized: self.drain_oversized,
+            recover_invalid: self.recover_invalid,
+            lengths: self.lengths,
+            buffers: self.buffers,
+            detect_foreign: self.detect_foreign,
+            checksum: PhantomData,
+        }
+    }
+
+    /// Creates the decoder.
+    pub fn build(self) -> V1MessageDecoder<C> {
+        let header = HeaderDecoder::from_magic(self.magic, self.max_payload);
+        let mut inner = V1DecoderInner::new(header, self.options);
+        inner.allowed = self.allowed;
+        inner.drain_oversized = self.drain_oversized;
+        inner.recover_invalid = self.recover_invalid;
+        inner.lengths = self.lengths;
+        inner.buffers = self.buffers;
+        inner.detect_foreign = self.detect_foreign;
+        V1MessageDecoder { inner }
+    }
+}
@@ -0,0 +1,146 @@
+//! Recording messages to a file and replaying them.
+
+use std::io::{self, BufRead, Write};
+
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::p2p::Magic;
+use bitcoin::Network;
+use push_decode::ReadError;
+
+use crate::{DecodeError, EncoderExt, V1MessageDecoder, V1MessageEncoder};
+
+/// Bytes which start every capture, the format's name and version.
+pub const CAPTURE_SIGNATURE: [u8; 8] = *b"BTCCAP\x00\x01";
+
+/// Writer of a message capture, for replaying peer traffic later
+///
+/// A capture is the [`CAPTURE_SIGNATURE`] and the 4 byte network magic,
+/// followed by the messages as V1 frames exactly as they are sent on the wire.
+/// The header makes a capture self-describing, so a [`CaptureReader`] needs no
+/// configuration, and the frames can be cut out and fed to any V1 decoder.
+///
+/// Messages are written straight through, so wrap a file in a
+/// [`BufWriter`](std::io::BufWriter) when recording many small messages.
+pub struct CaptureWriter<W> {
+    writer: W,
+    network: Network,
+}
+
+impl<W: Write> CaptureWriter<W> {
+    /// Starts a capture of messages on `network` by writing its header
+    pub fn new(mut writer: W, network: Network) -> io::Result<Self> {
+        writer.write_all(&CAPTURE_SIGNATURE)?;
+        writer.write_all(&network.magic().to_bytes())?;
+        Ok(Self { writer, network })
+    }
+
+    /// Appends `message` to the capture.
+    pub fn write(&mut self, message: &NetworkMessage) -> io::Result<()> {
+        V1MessageEncoder::new(self.network, message).encode_sync(&mut self.writer)
+    }
+
+    /// Returns the underlying writer.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// Reader which streams the messages of a capture back out
+///
+/// Each message is decoded with a [`V1MessageDecoder`] expecting the magic
+/// from the capture's header. Iteration ends at the end of the capture, or
+/// after the first error since the frames which follow can not be trusted.
+///
+/// Captures compressed as a whole, such as a `.gz` or `.zst` file, are
+/// decompressed as they are read by `from_gzip` behind the `gzip` feature flag
+/// and `from_zstd` behind the `zstd` feature flag. Recording one is a matter of handing
+/// [`CaptureWriter`] a compressing writer.
+pub struct CaptureReader<R> {
+    reader: R,
+    magic: Magic,
+    decoder: V1MessageDecoder,
+    failed: bool,
+}
+
+impl<R: BufRead> CaptureReader<R> {
+    /// Reads the capture's header from `reader`
+    ///
+    /// Fails with [`io::ErrorKind::InvalidData`] if the reader does not start
+    /// with a capture header.
+    pub fn new(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; CAPTURE_SIGNATURE.len() + 4];
+        reader.read_exact(&mut header)?;
+        let (signature, magic) = header.split_at(CAPTURE_SIGNATURE.len());
+        if signature != CAPTURE_SIGNATURE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "not a message capture",
+            ));
+        }
+        let magic = Magic::from_bytes(magic.try_into().expect("split after the signature"));
+        Ok(Self {
+            reader,
+            magic,
+            decoder: V1MessageDecoder::with_magic(magic),
+            failed: false,
+        })
+    }
+
+    /// Returns the magic the messages were captured with.
+    pub fn magic(&self) -> Magic {
+        self.magic
+    }
+
+    /// Returns the network the messages were captured on, if it is a known one.
+    pub fn network(&self) -> Option<Network> {
+        Network::from_magic(self.magic)
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl<R: BufRead> CaptureReader<io::BufReader<flate2::bufread::MultiGzDecoder<R>>> {
+    /// Reads a gzip compressed capture from `reader`
+    ///
+    /// Fails as [`new`](CaptureReader::new) does, or with the error of a
+    /// reader which is not gzip. Concatenated gzip members are read as one
+    /// capture, as `gzip -d` would.
+    pub fn from_gzip(reader: R) -> io::Result<Self> {
+        Self::new(io::BufReader::new(flate2::bufread::MultiGzDecoder::new(
+            reader,
+        )))
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<R: BufRead> CaptureReader<io::BufReader<zstd::Decoder<'static, R>>> {
+    /// Reads a zstd compressed capture from `reader`
+    ///
+    /// Fails as [`new`](CaptureReader::new) does, or with the error of a
+    /// reader which is not zstd.
+    pub fn from_zstd(reader: R) -> io::Result<Self> {
+        Self::new(io::BufReader::new(zstd::Decoder::with_buffer(reader)?))
+    }
+}
+
+impl<R: BufRead> Iterator for CaptureReader<R> {
+    type Item = Result<NetworkMessage, ReadError<DecodeError>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed {
+            return None;
+        }
+        match self.decoder.read_message(&mut self.reader) {
+            Err(ReadError::Decode(DecodeError::EndOfStream)) => None,
+            Ok(message) => Some(Ok(message)),
+            Err(e) => {
+                self.failed = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
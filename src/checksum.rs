@@ -0,0 +1,84 @@
+//! Payload checksums for the V1 transport.
+
+use bitcoin::hashes::{sha256d, Hash, HashEngine};
+
+/// Algorithm which checksums a message payload for the V1 header
+///
+/// Bitcoin uses the first 4 bytes of the payload's double SHA256, which is
+/// [`Sha256d`], but some derived chains hash with something else. Implementing
+/// this for the chain's algorithm and naming it in
+/// [`V1MessageDecoder::with_checksum`](crate::V1MessageDecoder::with_checksum)
+/// and [`V1MessageEncoder::with_checksum`](crate::V1MessageEncoder::with_checksum)
+/// reuses the rest of the framing unchanged.
+///
+/// Payloads are hashed incrementally as they arrive through an [`Engine`], the
+/// finished [`Digest`] is then cut down to the 4 bytes which fit in the header
+/// with [`truncate`].
+///
+/// [`Engine`]: Self::Engine
+/// [`Digest`]: Self::Digest
+/// [`truncate`]: Self::truncate
+pub trait Checksum {
+    /// State of a payload which is being hashed.
+    type Engine;
+    /// Output of the hash, at least 4 bytes long unless [`truncate`](Self::truncate)
+    /// is overridden.
+    type Digest: AsRef<[u8]>;
+
+    /// Returns an engine for a new payload.
+    fn engine() -> Self::Engine;
+
+    /// Feeds the next `bytes` of the payload to `engine`.
+    fn input(engine: &mut Self::Engine, bytes: &[u8]);
+
+    /// Finishes hashing the payload.
+    fn finalize(engine: Self::Engine) -> Self::Digest;
+
+    /// Cuts `digest` down to the header's checksum, by default its first 4 bytes.
+    fn truncate(digest: &Self::Digest) -> [u8; 4] {
+        let mut checksum = [0u8; 4];
+        checksum.copy_from_slice(&digest.as_ref()[..4]);
+        checksum
+    }
+
+    /// Returns the header checksum of a whole payload.
+    fn checksum(payload: &[u8]) -> [u8; 4] {
+        let mut engine = Self::engine();
+        Self::input(&mut engine, payload);
+        finish::<Self>(engine)
+    }
+}
+
+/// The double SHA256 checksum used by bitcoin, the default for every codec.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Sha256d;
+
+impl Checksum for Sha256d {
+    type Engine = <sha256d::Hash as Hash>::Engine;
+    type Digest = sha256d::Hash;
+
+    fn engine() -> Self::Engine {
+        sha256d::Hash::engine()
+    }
+
+    fn input(engine: &mut Self::Engine, bytes: &[u8]) {
+        engine.input(bytes);
+    }
+
+    fn finalize(engine: Self::Engine) -> Self::Digest {
+        sha256d::Hash::from_engine(engine)
+    }
+}
+
+/// Returns bitcoin's header checksum of `data`, the first 4 bytes of its double SHA256
+///
+/// The same [`Sha256d`] the encoders frame with and the decoders verify
+/// against, for callers which build or check frames themselves.
+pub fn checksum(data: &[u8]) -> [u8; 4] {
+    Sha256d::checksum(data)
+}
+
+/// Finishes `engine` in to a header checksum.
+pub(crate) fn finish<C: Checksum + ?Sized>(engine: C::Engine) -> [u8; 4] {
+    C::truncate(&C::finalize(engine))
+}
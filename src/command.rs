@@ -0,0 +1,259 @@
+//! Matching on commands without comparing strings.
+
+use bitcoin::p2p::message::{CommandString, NetworkMessage};
+
+use crate::Header;
+
+/// Command of a V1 message
+///
+/// There is a variant for each command `NetworkMessage` models, named after
+/// its variant, so dispatch code can `match` on the command exhaustively and
+/// the compiler points out the commands it does not handle. Any other command
+/// is kept as received in [`Unknown`](Self::Unknown), unlike
+/// [`NetworkMessage::cmd`] which collapses them to `"unknown"`.
+///
+/// ```
+/// use bitcoin::p2p::message::NetworkMessage;
+/// use bitcoin_codecs::Command;
+///
+/// let message = NetworkMessage::Ping(7);
+/// match Command::from(&message) {
+///     Command::Ping | Command::Pong => println!("keepalive"),
+///     Command::Unknown(command) => println!("unknown command {command}"),
+///     command => println!("{command}"),
+/// }
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// `version`.
+    Version,
+    /// `verack`.
+    Verack,
+    /// `addr`.
+    Addr,
+    /// `inv`.
+    Inv,
+    /// `getdata`.
+    GetData,
+    /// `notfound`.
+    NotFound,
+    /// `getblocks`.
+    GetBlocks,
+    /// `getheaders`.
+    GetHeaders,
+    /// `mempool`.
+    MemPool,
+    /// `block`.
+    Block,
+    /// `headers`.
+    Headers,
+    /// `sendheaders`.
+    SendHeaders,
+    /// `getaddr`.
+    GetAddr,
+    /// `ping`.
+    Ping,
+    /// `pong`.
+    Pong,
+    /// `merkleblock`.
+    MerkleBlock,
+    /// `filterload`.
+    FilterLoad,
+    /// `filteradd`.
+    FilterAdd,
+    /// `filterclear`.
+    FilterClear,
+    /// `tx`.
+    Tx,
+    /// `getcfilters`.
+    GetCFilters,
+    /// `cfilter`.
+    CFilter,
+    /// `getcfheaders`.
+    GetCFHeaders,
+    /// `cfheaders`.
+    CFHeaders,
+    /// `getcfcheckpt`.
+    GetCFCheckpt,
+    /// `cfcheckpt`.
+    CFCheckpt,
+    /// `reject`.
+    Reject,
+    /// `alert`.
+    Alert,
+    /// `feefilter`.
+    FeeFilter,
+    /// `sendcmpct`.
+    SendCmpct,
+    /// `cmpctblock`.
+    CmpctBlock,
+    /// `getblocktxn`.
+    GetBlockTxn,
+    /// `blocktxn`.
+    BlockTxn,
+    /// `wtxidrelay`.
+    WtxidRelay,
+    /// `addrv2`.
+    AddrV2,
+    /// `sendaddrv2`.
+    SendAddrV2,
+    /// Any command `NetworkMessage` does not model.
+    Unknown(CommandString),
+}
+
+impl Command {
+    /// Returns the command carried by `command`.
+    pub fn from_command_string(command: &CommandString) -> Self {
+        Self::known(command.as_ref()).unwrap_or_else(|| Command::Unknown(command.clone()))
+    }
+
+    /// Returns the command as it is framed in a header.
+    pub fn to_command_string(&self) -> CommandString {
+        match self {
+            Command::Unknown(command) => command.clone(),
+            known => {
+                let name = known.known_name().expect("known commands have a name");
+                CommandString::try_from_static(name).expect("known commands fit")
+            }
+        }
+    }
+
+    /// Returns the command as a string, such as `"ping"`.
+    pub fn as_str(&self) -> &str {
+        match self {
+            Command::Unknown(command) => command.as_ref(),
+            known => known.known_name().expect("known commands have a name"),
+        }
+    }
+
+    /// Returns whether `NetworkMessage` models the command.
+    pub fn is_known(&self) -> bool {
+        !matches!(self, Command::Unknown(_))
+    }
+
+    /// The modeled command named `command`, if any.
+    pub(crate) fn known(command: &str) -> Option<Self> {
+        Some(match command {
+            "version" => Command::Version,
+            "verack" => Command::Verack,
+            "addr" => Command::Addr,
+            "inv" => Command::Inv,
+            "getdata" => Command::GetData,
+            "notfound" => Command::NotFound,
+            "getblocks" => Command::GetBlocks,
+            "getheaders" => Command::GetHeaders,
+            "mempool" => Command::MemPool,
+            "block" => Command::Block,
+            "headers" => Command::Headers,
+            "sendheaders" => Command::SendHeaders,
+            "getaddr" => Command::GetAddr,
+            "ping" => Command::Ping,
+            "pong" => Command::Pong,
+            "merkleblock" => Command::MerkleBlock,
+            "filterload" => Command::FilterLoad,
+            "filteradd" => Command::FilterAdd,
+            "filterclear" => Command::FilterClear,
+            "tx" => Command::Tx,
+            "getcfilters" => Command::GetCFilters,
+            "cfilter" => Command::CFilter,
+            "getcfheaders" => Command::GetCFHeaders,
+            "cfheaders" => Command::CFHeaders,
+            "getcfcheckpt" => Command::GetCFCheckpt,
+            "cfcheckpt" => Command::CFCheckpt,
+            "reject" => Command::Reject,
+            "alert" => Command::Alert,
+            "feefilter" => Command::FeeFilter,
+            "sendcmpct" => Command::SendCmpct,
+            "cmpctblock" => Command::CmpctBlock,
+            "getblocktxn" => Command::GetBlockTxn,
+            "blocktxn" => Command::BlockTxn,
+            "wtxidrelay" => Command::WtxidRelay,
+            "addrv2" => Command::AddrV2,
+            "sendaddrv2" => Command::SendAddrV2,
+            _ => return None,
+        })
+    }
+
+    /// The name of a modeled command.
+    fn known_name(&self) -> Option<&'static str> {
+        Some(match self {
+            Command::Version => "version",
+            Command::Verack => "verack",
+            Command::Addr => "addr",
+            Command::Inv => "inv",
+            Command::GetData => "getdata",
+            Command::NotFound => "notfound",
+            Command::GetBlocks => "getblocks",
+            Command::GetHeaders => "getheaders",
+            Command::MemPool => "mempool",
+            Command::Block => "block",
+            Command::Headers => "headers",
+            Command::SendHeaders => "sendheaders",
+            Command::GetAddr => "getaddr",
+            Command::Ping => "ping",
+            Command::Pong => "pong",
+            Command::MerkleBlock => "merkleblock",
+            Command::FilterLoad => "filterload",
+            Command::FilterAdd => "filteradd",
+            Command::FilterClear => "filterclear",
+            Command::Tx => "tx",
+            Command::GetCFilters => "getcfilters",
+            Command::CFilter => "cfilter",
+            Command::GetCFHeaders => "getcfheaders",
+            Command::CFHeaders => "cfheaders",
+            Command::GetCFCheckpt => "getcfcheckpt",
+            Command::CFCheckpt => "cfcheckpt",
+            Command::Reject => "reject",
+            Command::Alert => "alert",
+            Command::FeeFilter => "feefilter",
+            Command::SendCmpct => "sendcmpct",
+            Command::CmpctBlock => "cmpctblock",
+            Command::GetBlockTxn => "getblocktxn",
+            Command::BlockTxn => "blocktxn",
+            Command::WtxidRelay => "wtxidrelay",
+            Command::AddrV2 => "addrv2",
+            Command::SendAddrV2 => "sendaddrv2",
+            Command::Unknown(_) => return None,
+        })
+    }
+}
+
+impl From<&CommandString> for Command {
+    fn from(command: &CommandString) -> Self {
+        Self::from_command_string(command)
+    }
+}
+
+impl From<&NetworkMessage> for Command {
+    /// Keeps the raw command of a [`NetworkMessage::Unknown`].
+    fn from(message: &NetworkMessage) -> Self {
+        Self::from_command_string(&message.command())
+    }
+}
+
+impl From<&Header> for Command {
+    fn from(header: &Header) -> Self {
+        Self::from_command_string(&header.command)
+    }
+}
+
+impl From<Command> for CommandString {
+    fn from(command: Command) -> Self {
+        match command {
+            Command::Unknown(command) => command,
+            known => known.to_command_string(),
+        }
+    }
+}
+
+impl core::hash::Hash for Command {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        self.as_str().hash(state);
+    }
+}
+
+impl core::fmt::Display for Command {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
@@ -0,0 +1,208 @@
+//! Owning both directions of a V1 connection.
+
+use std::io::{self, BufReader, Read, Write};
+
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::Network;
+use push_decode::ReadError;
+
+use crate::{DecodeError, V1MessageDecoder, V1MessageEncoder};
+
+/// Peer connection which owns a reader and writer and speaks V1 over them
+///
+/// Wraps the sans-io codecs for the common case of a blocking socket. The
+/// reader is buffered internally and a single [`V1MessageDecoder`] is reused
+/// for every message, outgoing messages are framed in to one reused buffer and
+/// flushed with a single write. Nothing is answered on the caller's behalf,
+/// replying to a `version` or `ping` is still up to the caller.
+///
+/// ```no_run
+/// use std::net::TcpStream;
+///
+/// use bitcoin::p2p::message::NetworkMessage;
+/// use bitcoin::Network;
+/// use bitcoin_codecs::V1Connection;
+///
+/// let stream = TcpStream::connect("127.0.0.1:8333")?;
+/// let mut connection = V1Connection::new(stream.try_clone()?, stream, Network::Bitcoin);
+/// loop {
+///     if let NetworkMessage::Ping(nonce) = connection.recv()? {
+///         connection.send(&NetworkMessage::Pong(nonce))?;
+///     }
+/// }
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct V1Connection<R, W> {
+    reader: BufReader<R>,
+    writer: W,
+    network: Network,
+    decoder: V1MessageDecoder,
+    out: Vec<u8>,
+}
+
+impl<R: Read, W: Write> V1Connection<R, W> {
+    /// Creates a connection to a peer on `network`
+    pub fn new(reader: R, writer: W, network: Network) -> Self {
+        Self::with_decoder(reader, writer, network, V1MessageDecoder::new(network))
+    }
+
+    /// Creates a connection which receives with a configured `decoder`
+    ///
+    /// For limits or options beyond the defaults of [`V1MessageDecoder::new`].
+    /// Outgoing messages are framed for `network`, which should match the
+    /// magic the decoder expects.
+    pub fn with_decoder(reader: R, writer: W, network: Network, decoder: V1MessageDecoder) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+            writer,
+            network,
+            decoder,
+            out: Vec::new(),
+        }
+    }
+
+    /// Receives the next message from the peer
+    ///
+    /// Behaves like [`V1MessageDecoder::read_message`], so a peer closing the
+    /// connection between messages is [`DecodeError::EndOfStream`]. The
+    /// decoder is reset after any decode error, so calling this again reads
+    /// on from where the failed message stopped rather than panicking,
+    /// though after a fatal error the stream is usually best dropped.
+    pub fn recv(&mut self) -> Result<NetworkMessage, ReadError<DecodeError>> {
+        let result = self.decoder.read_message(&mut self.reader);
+        reset_on_decode_error(&mut self.decoder, result)
+    }
+
+    /// Sends `message` to the peer and flushes the writer.
+    pub fn send(&mut self, message: &NetworkMessage) -> io::Result<()> {
+        self.out.clear();
+        V1MessageEncoder::encode_into(self.network, message, &mut self.out);
+        self.writer.write_all(&self.out)?;
+        self.writer.flush()
+    }
+
+    /// Returns the network messages are framed for.
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Returns the decoder, for example to [`reset`](V1MessageDecoder::reset) it after an error.
+    pub fn decoder_mut(&mut self) -> &mut V1MessageDecoder {
+        &mut self.decoder
+    }
+
+    /// Returns the underlying reader and writer
+    ///
+    /// Any bytes which were read ahead in to the internal buffer are lost.
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader.into_inner(), self.writer)
+    }
+}
+
+/// Tokio counterpart of [`V1Connection`] for async peers
+///
+/// Typically built from the two halves of
+/// [`TcpStream::into_split`](https://docs.rs/tokio/latest/tokio/net/struct.TcpStream.html#method.into_split).
+/// The reader is buffered internally and a single [`V1MessageDecoder`] is
+/// reused for every message, so [`recv`](Self::recv) is cancel safe: a partial
+/// message stays in the decoder and the next call picks up where it left off.
+/// That lets it sit in a `tokio::select!` alongside whatever produces the
+/// messages to [`send`](Self::send), which is not cancel safe.
+///
+/// ```no_run
+/// # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+/// use bitcoin::p2p::message::NetworkMessage;
+/// use bitcoin::Network;
+/// use bitcoin_codecs::AsyncV1Connection;
+/// use tokio::net::TcpStream;
+///
+/// let (reader, writer) = TcpStream::connect("127.0.0.1:8333").await?.into_split();
+/// let mut connection = AsyncV1Connection::new(reader, writer, Network::Bitcoin);
+/// loop {
+///     if let NetworkMessage::Ping(nonce) = connection.recv().await? {
+///         connection.send(&NetworkMessage::Pong(nonce)).await?;
+///     }
+/// }
+/// # }
+/// ```
+#[cfg(feature = "tokio")]
+pub struct AsyncV1Connection<R, W> {
+    reader: tokio::io::BufReader<R>,
+    writer: W,
+    network: Network,
+    decoder: V1MessageDecoder,
+    out: Vec<u8>,
+}
+
+#[cfg(feature = "tokio")]
+impl<R, W> AsyncV1Connection<R, W>
+where
+    R: tokio::io::AsyncRead + Unpin,
+    W: tokio::io::AsyncWrite + Unpin,
+{
+    /// Creates a connection to a peer on `network`
+    pub fn new(reader: R, writer: W, network: Network) -> Self {
+        Self::with_decoder(reader, writer, network, V1MessageDecoder::new(network))
+    }
+
+    /// Creates a connection which receives with a configured `decoder`
+    ///
+    /// See [`V1Connection::with_decoder`].
+    pub fn with_decoder(reader: R, writer: W, network: Network, decoder: V1MessageDecoder) -> Self {
+        Self {
+            reader: tokio::io::BufReader::new(reader),
+            writer,
+            network,
+            decoder,
+            out: Vec::new(),
+        }
+    }
+
+    /// Receives the next message from the peer
+    ///
+    /// Behaves like [`V1MessageDecoder::read_message_tokio`], so a peer closing
+    /// the connection between messages is [`DecodeError::EndOfStream`]. As for
+    /// [`V1Connection::recv`] the decoder is reset after any decode error.
+    pub async fn recv(&mut self) -> Result<NetworkMessage, ReadError<DecodeError>> {
+        let result = self.decoder.read_message_tokio(&mut self.reader).await;
+        reset_on_decode_error(&mut self.decoder, result)
+    }
+
+    /// Sends `message` to the peer and flushes the writer.
+    pub async fn send(&mut self, message: &NetworkMessage) -> io::Result<()> {
+        use tokio::io::AsyncWriteExt;
+
+        self.out.clear();
+        V1MessageEncoder::encode_into(self.network, message, &mut self.out);
+        self.writer.write_all(&self.out).await?;
+        self.writer.flush().await
+    }
+
+    /// Returns the network messages are framed for.
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Returns the decoder, for example to [`reset`](V1MessageDecoder::reset) it after an error.
+    pub fn decoder_mut(&mut self) -> &mut V1MessageDecoder {
+        &mut self.decoder
+    }
+
+    /// Returns the underlying reader and writer
+    ///
+    /// Any bytes which were read ahead in to the internal buffer are lost.
+    pub fn into_inner(self) -> (R, W) {
+        (self.reader.into_inner(), self.writer)
+    }
+}
+
+/// Resets `decoder` if `result` is a decode error, so the next read does not panic.
+fn reset_on_decode_error(
+    decoder: &mut V1MessageDecoder,
+    result: Result<NetworkMessage, ReadError<DecodeError>>,
+) -> Result<NetworkMessage, ReadError<DecodeError>> {
+    if let Err(ReadError::Decode(_)) = result {
+        decoder.reset();
+    }
+    result
+}
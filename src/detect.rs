@@ -0,0 +1,91 @@
+//! Learning the network of a stream from its first message.
+
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::p2p::Magic;
+use bitcoin::Network;
+use push_decode::Decoder;
+
+use crate::{DecodeError, V1MessageDecoder};
+
+/// Decoder for the first message of a stream whose network is not known
+///
+/// Created with [`V1MessageDecoder::auto_detect`]. The magic is buffered until
+/// all 4 bytes have arrived and then picks the [`Network`], after which the
+/// message is decoded as usual. Decoding stops at the end of the first message
+/// and yields it as [`Detected`], whose decoder then carries on with the stream.
+pub struct AutoDetectDecoder {
+    magic: [u8; 4],
+    received: usize,
+    // Set once the magic is complete and known.
+    detected: Option<(Network, V1MessageDecoder)>,
+}
+
+/// The first message of a stream along with the network it was sent on.
+#[derive(Debug)]
+pub struct Detected {
+    /// The network whose magic the message was framed with.
+    pub network: Network,
+    /// The first message.
+    pub message: NetworkMessage,
+    /// Decoder for the rest of the stream, which rejects any other magic.
+    pub decoder: V1MessageDecoder,
+}
+
+impl AutoDetectDecoder {
+    pub(crate) fn new() -> Self {
+        Self {
+            magic: [0; 4],
+            received: 0,
+            detected: None,
+        }
+    }
+}
+
+impl Default for AutoDetectDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for AutoDetectDecoder {
+    type Value = Detected;
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        if self.detected.is_none() {
+            let take = (self.magic.len() - self.received).min(bytes.len());
+            self.magic[self.received..self.received + take].copy_from_slice(&bytes[..take]);
+            self.received += take;
+            *bytes = &bytes[take..];
+            if self.received < self.magic.len() {
+                return Ok(());
+            }
+
+            let magic = Magic::from_bytes(self.magic);
+            let network = Network::from_magic(magic).ok_or(DecodeError::UnknownMagic(magic))?;
+            let mut decoder = V1MessageDecoder::new(network);
+            decoder.decode_chunk(&mut &self.magic[..])?;
+            self.detected = Some((network, decoder));
+        }
+
+        match &mut self.detected {
+            Some((_, decoder)) => decoder.decode_chunk(bytes),
+            None => unreachable!("the network is detected above"),
+        }
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        match self.detected {
+            Some((network, mut decoder)) => {
+                let message = (&mut decoder).end()?;
+                Ok(Detected {
+                    network,
+                    message,
+                    decoder,
+                })
+            }
+            None if self.received == 0 => Err(DecodeError::EndOfStream),
+            None => Err(DecodeError::IncompleteHeader),
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Routing decoded messages to handlers by command.
+
+use std::collections::BTreeMap;
+use std::io;
+
+use bitcoin::p2p::message::NetworkMessage;
+use push_decode::ReadError;
+
+use crate::{DecodeError, DecoderExt, V1MessageDecoder};
+
+// Type alias for a boxed message handler.
+type Handler<'a> = Box<dyn FnMut(NetworkMessage) + 'a>;
+
+/// Dispatch table which decodes messages and hands each to the handler for its command
+///
+/// A thin layer over a [`V1MessageDecoder`] for nodes which would otherwise
+/// match on dozens of message types. Handlers are looked up by the command of
+/// the decoded message, which for [`NetworkMessage::Unknown`] is the command the
+/// peer sent, and commands without a handler go to the default one.
+pub struct Dispatcher<'a> {
+    decoder: V1MessageDecoder,
+    handlers: BTreeMap<String, Handler<'a>>,
+    default: Handler<'a>,
+}
+
+impl<'a> Dispatcher<'a> {
+    /// Creates a dispatcher decoding with `decoder` and sending unhandled commands to `default`
+    pub fn new(decoder: V1MessageDecoder, default: impl FnMut(NetworkMessage) + 'a) -> Self {
+        Self {
+            decoder,
+            handlers: BTreeMap::new(),
+            default: Box::new(default),
+        }
+    }
+
+    /// Registers the handler for `command`, replacing any previous one
+    pub fn on(&mut self, command: &str, handler: impl FnMut(NetworkMessage) + 'a) -> &mut Self {
+        self.handlers.insert(command.to_string(), Box::new(handler));
+        self
+    }
+
+    /// Decodes one message from `reader` and invokes the matching handler
+    pub fn dispatch<R: io::BufRead + ?Sized>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<(), ReadError<DecodeError>> {
+        let message = (&mut self.decoder).decode_sync(reader)?;
+        let handler = match self.handlers.get_mut(message.command().as_ref()) {
+            Some(handler) => handler,
+            None => &mut self.default,
+        };
+        handler(message);
+        Ok(())
+    }
+}
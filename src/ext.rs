@@ -0,0 +1,148 @@
+//! Extension traits which delegate to the [`push_decode`] I/O drivers.
+
+use push_decode::{Decoder, Encoder, ReadError};
+use std::io;
+
+#[cfg(feature = "tokio")]
+use crate::DecodeError;
+#[cfg(feature = "tokio")]
+use core::{future::Future, pin::Pin, time::Duration};
+
+// Type alias for the boxed tokio decode future.
+#[cfg(feature = "tokio")]
+type TokioDecodeFuture<'a, V, E> =
+    Pin<Box<dyn Future<Output = Result<V, ReadError<E>>> + Send + 'a>>;
+
+/// Drive a [`Decoder`] to completion from an I/O source.
+///
+/// Implemented for every [`Decoder`], so callers do not need to import the
+/// [`push_decode`] driver functions directly.
+pub trait DecoderExt: Decoder {
+    /// Synchronously decodes a value from the reader.
+    fn decode_sync<R: io::BufRead + ?Sized>(
+        self,
+        reader: &mut R,
+    ) -> Result<Self::Value, ReadError<Self::Error>> {
+        push_decode::decode_sync_with(reader, self)
+    }
+
+    /// Synchronously decodes a value along with the number of bytes it took.
+    ///
+    /// For a V1 or V2 message decoder that is the size of the whole frame, so a
+    /// capture tool can keep track of its offset in the stream.
+    fn decode_sync_counted<R: io::BufRead + ?Sized>(
+        self,
+        reader: &mut R,
+    ) -> Result<(Self::Value, usize), ReadError<Self::Error>> {
+        push_decode::decode_sync_with(reader, Counted::new(self))
+    }
+
+    /// Asynchronously decodes a value from the tokio reader.
+    ///
+    /// The future is boxed since the driver's future type can not be named.
+    #[cfg(feature = "tokio")]
+    fn decode_tokio<'a, R>(self, reader: R) -> TokioDecodeFuture<'a, Self::Value, Self::Error>
+    where
+        Self: Send + 'a,
+        Self::Value: Send,
+        Self::Error: Send,
+        R: tokio::io::AsyncBufRead + Send + 'a,
+    {
+        Box::pin(push_decode::decode_tokio_with(reader, self))
+    }
+
+    /// Asynchronously decodes a value along with the number of bytes it took.
+    ///
+    /// The tokio counterpart of [`decode_sync_counted`](Self::decode_sync_counted).
+    #[cfg(feature = "tokio")]
+    fn decode_tokio_counted<'a, R>(
+        self,
+        reader: R,
+    ) -> TokioDecodeFuture<'a, (Self::Value, usize), Self::Error>
+    where
+        Self: Send + 'a,
+        Self::Value: Send,
+        Self::Error: Send,
+        R: tokio::io::AsyncBufRead + Send + 'a,
+    {
+        Box::pin(push_decode::decode_tokio_with(reader, Counted::new(self)))
+    }
+
+    /// Asynchronously decodes a value, giving up once `timeout` has elapsed.
+    ///
+    /// The timeout covers the whole message from the first read, and fails with
+    /// [`DecodeError::Timeout`]. The decoder is dropped along with any partial
+    /// message, but a borrowed reusable decoder such as `&mut V1MessageDecoder`
+    /// keeps what it had read and must be reset before it is used again.
+    #[cfg(feature = "tokio")]
+    fn decode_tokio_timeout<'a, R>(
+        self,
+        reader: R,
+        timeout: Duration,
+    ) -> TokioDecodeFuture<'a, Self::Value, Self::Error>
+    where
+        Self: Decoder<Error = DecodeError> + Send + 'a,
+        Self::Value: Send,
+        R: tokio::io::AsyncBufRead + Send + 'a,
+    {
+        let decode = push_decode::decode_tokio_with(reader, self);
+        Box::pin(async move {
+            tokio::time::timeout(timeout, decode)
+                .await
+                .unwrap_or(Err(ReadError::Decode(DecodeError::Timeout)))
+        })
+    }
+}
+
+impl<D: Decoder> DecoderExt for D {}
+
+/// Decoder which counts the bytes consumed by the decoder it wraps.
+struct Counted<D> {
+    inner: D,
+    consumed: usize,
+}
+
+impl<D> Counted<D> {
+    fn new(inner: D) -> Self {
+        Self { inner, consumed: 0 }
+    }
+}
+
+impl<D: Decoder> Decoder for Counted<D> {
+    type Value = (D::Value, usize);
+    type Error = D::Error;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        let len = bytes.len();
+        let result = self.inner.decode_chunk(bytes);
+        self.consumed += len - bytes.len();
+        result
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        Ok((self.inner.end()?, self.consumed))
+    }
+}
+
+/// Drive an [`Encoder`] to completion in to an I/O sink.
+///
+/// Each encoded chunk is written directly, so the writer does not need to be
+/// buffered for encoders which produce a few large chunks like
+/// [`V1MessageEncoder`](crate::V1MessageEncoder).
+pub trait EncoderExt: Encoder {
+    /// Synchronously writes all encoded bytes to the writer.
+    fn encode_sync<W: io::Write>(self, writer: &mut W) -> io::Result<()> {
+        self.track_position().write_all(writer)
+    }
+
+    /// Asynchronously writes all encoded bytes to the tokio writer.
+    #[cfg(feature = "tokio")]
+    fn encode_tokio<W: tokio::io::AsyncWrite>(
+        self,
+        writer: W,
+    ) -> push_decode::future::TokioEncodeFuture<push_decode::AssumeBuffered<W>, Self> {
+        self.write_all_tokio(push_decode::AssumeBuffered::new(writer))
+    }
+}
+
+impl<E: Encoder> EncoderExt for E {}
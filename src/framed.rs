@@ -0,0 +1,74 @@
+//! Adapter for the `tokio-util` codec framework.
+
+use bitcoin::p2p::message::NetworkMessage;
+use bytes::{Buf, BytesMut};
+use std::io;
+
+use crate::buf::frame_into_bytes;
+use crate::{DecodeError, V1MessageDecoder};
+
+/// A [`tokio_util::codec`] implementation for Bitcoin V1 protocol messages
+///
+/// Plugs in to `tokio_util::codec::Framed` to turn an I/O object in to a
+/// stream and sink of [`NetworkMessage`]s. Decoding is done by the wrapped
+/// [`V1MessageDecoder`], so it is configured the same way, and messages are
+/// encoded with the magic the decoder expects.
+///
+/// Commands filtered out by [`V1MessageDecoder::with_commands`] are skipped
+/// silently. Other decode errors are surfaced as [`io::ErrorKind::InvalidData`]
+/// wrapping a [`DecodeError`], which `Framed` treats as the end of the stream.
+pub struct V1Codec {
+    decoder: V1MessageDecoder,
+}
+
+impl V1Codec {
+    /// Creates a new V1 codec which decodes with `decoder`
+    pub fn new(decoder: V1MessageDecoder) -> Self {
+        Self { decoder }
+    }
+}
+
+impl tokio_util::codec::Decoder for V1Codec {
+    type Item = NetworkMessage;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        // Partial messages are held by the decoder, so `src` is always drained.
+        loop {
+            let mut bytes = &src[..];
+            let result = self.decoder.feed(&mut bytes);
+            let consumed = src.len() - bytes.len();
+            src.advance(consumed);
+            match result {
+                Err(DecodeError::Skipped { .. }) => continue,
+                result => return result.map_err(invalid_data),
+            }
+        }
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        match self.decode(src)? {
+            Some(message) => Ok(Some(message)),
+            None if self.decoder.inner.is_idle() => Ok(None),
+            None => match self.decoder.inner.end_and_reset() {
+                Err(DecodeError::Skipped { .. }) => Ok(None),
+                result => result
+                    .map(|frame| Some(frame.message))
+                    .map_err(invalid_data),
+            },
+        }
+    }
+}
+
+impl tokio_util::codec::Encoder<NetworkMessage> for V1Codec {
+    type Error = io::Error;
+
+    fn encode(&mut self, item: NetworkMessage, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        frame_into_bytes(self.decoder.inner.expected_magic, &item, dst);
+        Ok(())
+    }
+}
+
+fn invalid_data(error: DecodeError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
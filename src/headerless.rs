@@ -0,0 +1,145 @@
+//! Decoding messages framed without the network magic.
+
+use bitcoin::p2p::{message::NetworkMessage, Magic};
+use push_decode::{
+    decoders::{combinators::Chain, ByteArrayDecoder, IntDecoder},
+    int::LittleEndian,
+    Decoder,
+};
+
+use crate::{parse_command, DecodeError, Header, PayloadDecoder, PayloadOptions, MAX_PAYLOAD_SIZE};
+
+// Type alias for the decoder chain that parses a header without its magic
+type RawHeaderlessDecoder =
+    Chain<Chain<ByteArrayDecoder<12>, IntDecoder<u32, LittleEndian>>, ByteArrayDecoder<4>>;
+
+/// Size of the header without its magic.
+const HEADERLESS_SIZE: usize = 20;
+
+/// Decoder for Bitcoin V1 protocol messages framed without the network magic
+///
+/// Some tools drop the 4 byte magic on already authenticated channels such as
+/// a loopback or IPC transport, leaving a 20 byte header of command, length and
+/// checksum. Since there is no magic nothing ties the stream to a network, so
+/// this decoder is a separate type rather than an option on
+/// [`V1MessageDecoder`](crate::V1MessageDecoder) which keeps the check from
+/// being skipped by accident.
+pub struct HeaderlessDecoder {
+    stage: Stage,
+}
+
+impl HeaderlessDecoder {
+    /// Creates a new headerless decoder with the default [`MAX_PAYLOAD_SIZE`] limit
+    pub fn new() -> Self {
+        Self::with_max_payload(MAX_PAYLOAD_SIZE)
+    }
+
+    /// Creates a new headerless decoder which rejects payloads larger than `max` bytes
+    pub fn with_max_payload(max: usize) -> Self {
+        Self {
+            stage: Stage::Header {
+                inner: ByteArrayDecoder::<12>::new()
+                    .chain(IntDecoder::<u32, LittleEndian>::new())
+                    .chain(ByteArrayDecoder::<4>::new()),
+                received: 0,
+                max_payload: max,
+            },
+        }
+    }
+}
+
+impl Default for HeaderlessDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Stages of decoding a headerless message.
+// Boxing the payload stage would cost an allocation per message.
+#[allow(clippy::large_enum_variant)]
+enum Stage {
+    Header {
+        inner: RawHeaderlessDecoder,
+        received: usize,
+        max_payload: usize,
+    },
+    Payload(PayloadDecoder),
+    // The header failed validation and decoding can not continue.
+    Errored,
+}
+
+/// Validates the trimmed header and prepares the payload decoder.
+fn payload_decoder(
+    inner: RawHeaderlessDecoder,
+    max_payload: usize,
+) -> Result<PayloadDecoder, DecodeError> {
+    let ((command_bytes, length), checksum) =
+        inner.end().map_err(|_| DecodeError::IncompleteHeader)?;
+    let command = parse_command(&command_bytes)?;
+
+    if length as usize > max_payload {
+        return Err(DecodeError::PayloadTooLarge {
+            length: length as usize,
+            max: max_payload,
+        });
+    }
+
+    let header = Header {
+        // The payload decoder never looks at the magic and the header is not
+        // handed out, so a placeholder stands in for the missing field.
+        magic: Magic::from_bytes([0; 4]),
+        command,
+        length,
+        checksum,
+    };
+    Ok(PayloadDecoder::new(header, PayloadOptions::default()))
+}
+
+impl Decoder for HeaderlessDecoder {
+    type Value = NetworkMessage;
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        if let Stage::Header {
+            inner, received, ..
+        } = &mut self.stage
+        {
+            let len = bytes.len();
+            inner
+                .decode_chunk(bytes)
+                .map_err(|_| DecodeError::IncompleteHeader)?;
+            // Move on as soon as the header is complete so it is checked
+            // without waiting for further bytes.
+            *received += len - bytes.len();
+            if *received < HEADERLESS_SIZE {
+                return Ok(());
+            }
+            if let Stage::Header {
+                inner, max_payload, ..
+            } = core::mem::replace(&mut self.stage, Stage::Errored)
+            {
+                self.stage = Stage::Payload(payload_decoder(inner, max_payload)?);
+            }
+        }
+
+        match &mut self.stage {
+            Stage::Payload(decoder) => decoder.decode_chunk(bytes),
+            _ => panic!("Decoder::decode_chunk called after it already returned an error"),
+        }
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        let (_, message) = match self.stage {
+            // Input ending before any byte is a clean close, not a cut off header.
+            Stage::Header { received: 0, .. } => return Err(DecodeError::EndOfStream),
+            Stage::Header {
+                inner, max_payload, ..
+            } => payload_decoder(inner, max_payload)?.end()?,
+            Stage::Payload(decoder) => decoder.end()?,
+            Stage::Errored => {
+                panic!("Decoder::end called after Decoder::decode_chunk already returned an error")
+            }
+        };
+        Ok(message)
+    }
+}
@@ -0,0 +1,80 @@
+//! Payload lengths which are possible for each command.
+
+use std::collections::BTreeMap;
+
+use core::ops::RangeInclusive;
+
+/// Table of the payload lengths allowed per command
+///
+/// Checked once a header is complete, so a message whose announced length
+/// could never deserialize, such as a `verack` with a payload or a `ping` which
+/// is not 8 bytes, is rejected with [`DecodeError::UnexpectedLength`] before
+/// any of its payload is buffered. Commands without an entry accept any length
+/// up to the decoder's maximum.
+///
+/// [`LengthLimits::default`] covers the fixed size messages plus `headers`,
+/// bounded by the 2000 headers a peer may send at once. Entries can be replaced
+/// or removed to suit other protocol versions.
+///
+/// [`DecodeError::UnexpectedLength`]: crate::DecodeError::UnexpectedLength
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LengthLimits {
+    limits: BTreeMap<String, RangeInclusive<u32>>,
+}
+
+impl LengthLimits {
+    /// Creates an empty table which allows any length for every command
+    pub fn new() -> Self {
+        Self {
+            limits: BTreeMap::new(),
+        }
+    }
+
+    /// Allows payloads of `command` only when their length falls in `range`
+    pub fn set(&mut self, command: &str, range: RangeInclusive<u32>) -> &mut Self {
+        self.limits.insert(command.to_string(), range);
+        self
+    }
+
+    /// Removes the entry for `command`, allowing any length
+    pub fn remove(&mut self, command: &str) -> &mut Self {
+        self.limits.remove(command);
+        self
+    }
+
+    /// Returns whether a payload of `length` bytes is allowed for `command`.
+    pub fn allows(&self, command: &str, length: u32) -> bool {
+        self.limits
+            .get(command)
+            .map_or(true, |range| range.contains(&length))
+    }
+}
+
+impl Default for LengthLimits {
+    fn default() -> Self {
+        // A compact size count of up to 2000 followed by 80 byte headers which
+        // each carry an empty transaction count.
+        const MAX_HEADERS: u32 = 3 + 2000 * 81;
+
+        let mut limits = Self::new();
+        for command in [
+            "verack",
+            "getaddr",
+            "mempool",
+            "sendheaders",
+            "filterclear",
+            "wtxidrelay",
+            "sendaddrv2",
+        ] {
+            limits.set(command, 0..=0);
+        }
+        limits
+            .set("ping", 8..=8)
+            .set("pong", 8..=8)
+            .set("feefilter", 8..=8)
+            .set("sendcmpct", 9..=9)
+            .set("headers", 1..=MAX_HEADERS);
+        limits
+    }
+}
@@ -14,32 +14,146 @@
 //! 4. Keep the library agnostic and have helper crates (e.g. `bitcoin-codecs-tokio`)
 //!    which flip on the [`push_decode`] flags and add wrappers.
 //!
+//! Option 2 is provided by the [`DecoderExt`] and [`EncoderExt`] traits. The
+//! synchronous methods are always available while the async methods are gated
+//! behind the `tokio` feature flag. Transports which yield chunks of bytes instead
+//! of implementing `AsyncRead` can use `V1MessageStream` behind the `futures`
+//! feature flag, and network buffers from the `bytes` crate can be decoded from
+//! and encoded in to directly behind the `bytes` feature flag. Messages tunnelled
+//! through WebSocket binary frames are decoded by `V1WebSocketDecoder` behind the
+//! `websocket` feature flag.
+//!
+//! Option 3 is provided by [`V1Connection`], which owns a blocking reader and
+//! writer and sends and receives whole messages over them, with
+//! `AsyncV1Connection` doing the same for tokio behind the `tokio` feature flag.
+//! Also behind it, `DecodeAhead` keeps decoding from a reader in to a bounded
+//! channel so reading overlaps with handling the messages.
+//!
+//! Code driving these over a connection can be tested without networking using
+//! the in-memory `test_util::duplex` behind the `test-util` feature flag, and
+//! the V1 decoders report headers, messages and errors as events to the `tracing`
+//! crate behind the `tracing` feature flag, with the command and length of each
+//! message but never its payload.
+//!
 //! [`push_decode`]: https://docs.rs/push_decode
 
+#[cfg(feature = "tokio")]
+mod ahead;
+mod blocks;
+#[cfg(feature = "bytes")]
+mod buf;
+mod builder;
+mod capture;
+mod checksum;
+mod command;
+mod connection;
+mod detect;
+mod dispatch;
+mod ext;
+#[cfg(feature = "tokio-util")]
+mod framed;
+mod headerless;
+mod lengths;
+mod limit;
+mod map;
+mod metered;
+mod parser;
+mod pool;
+mod progress;
+mod ring;
+#[cfg(feature = "serde")]
+mod serde_utils;
+mod stall;
+#[cfg(feature = "serde")]
+mod state;
+#[cfg(feature = "futures")]
+mod stream;
+mod streaming;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+mod timed;
+#[cfg(feature = "tracing")]
+mod trace;
+mod tracker;
+mod v2;
+#[cfg(feature = "websocket")]
+mod websocket;
+
+pub use blocks::{BlockMessage, V1BlockDecoder, V1BlockEncoder};
+pub use builder::V1MessageDecoderBuilder;
+pub use capture::{CaptureReader, CaptureWriter, CAPTURE_SIGNATURE};
+pub use checksum::{checksum, Checksum, Sha256d};
+pub use command::Command;
+pub use connection::V1Connection;
+pub use detect::{AutoDetectDecoder, Detected};
+pub use dispatch::Dispatcher;
+pub use ext::{DecoderExt, EncoderExt};
+pub use headerless::HeaderlessDecoder;
+pub use lengths::LengthLimits;
+pub use limit::{ByteBudget, RateLimiter};
+pub use map::Map;
+pub use metered::{CommandMetrics, DecodeMetrics, MessageCommand, MeteredDecoder};
+pub use parser::{NetworkMessageParser, PayloadParser, V1ParserDecoder};
+pub use pool::{BufferPool, HeapBuffers, PayloadBufferSource};
+pub use progress::ProgressDecoder;
+pub use ring::RingBuffer;
+pub use stall::StallGuard;
+pub use streaming::V1StreamingDecoder;
+pub use timed::{CommandTimings, DecodeTimings, TimedDecoder};
+pub use tracker::{HandshakeDecoder, HandshakeTracker, HandshakeViolation, PeerCapabilities};
+pub use v2::{
+    HandshakeError, Role, SessionKeys, V2Handshake, V2MessageDecoder, V2MessageEncoder,
+    MAX_GARBAGE_LEN,
+};
+
+#[cfg(feature = "tokio")]
+pub use ahead::{DecodeAhead, DecodeAheadItem};
+#[cfg(feature = "tokio")]
+pub use connection::AsyncV1Connection;
+#[cfg(feature = "tokio-util")]
+pub use framed::V1Codec;
+#[cfg(feature = "serde")]
+pub use state::V1DecoderState;
+#[cfg(feature = "futures")]
+pub use stream::V1MessageStream;
+#[cfg(feature = "websocket")]
+pub use websocket::{V1WebSocketDecoder, WebSocketMessages};
+
 use bitcoin::{
-    consensus::encode,
+    block,
+    consensus::{encode, encode::VarInt, Decodable, Encodable},
+    hex::DisplayHex,
     p2p::{
         message::{CommandString, NetworkMessage, RawNetworkMessage},
-        Magic,
+        message_blockdata::Inventory,
+        message_bloom::{FilterAdd, FilterLoad},
+        message_network::VersionMessage,
+        Address, Magic, ServiceFlags,
     },
-    Network,
+    Amount, Network,
 };
-use either::Either;
+use checksum::finish;
 use push_decode::{
-    decoders::{
-        combinators::{Chain, Then},
-        ByteArrayDecoder, ByteVecDecoder, IntDecoder,
-    },
+    decoders::{combinators::Chain, ByteArrayDecoder, IntDecoder},
+    encoders::BytesEncoder,
     int::LittleEndian,
-    Decoder,
+    Decoder, Encoder, ReadError,
 };
+use std::borrow::Cow;
+use std::sync::Arc;
 
 /// A decoded Bitcoin message header.
+///
+/// With the `serde` feature enabled the magic is serialized as a hex string and
+/// the command as a plain string.
 #[derive(Clone, Debug, PartialEq, Eq)]
-struct Header {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Header {
     /// Network magic bytes.
+    #[cfg_attr(feature = "serde", serde(with = "serde_utils::display_from_str"))]
     pub magic: Magic,
     /// Command name.
+    #[cfg_attr(feature = "serde", serde(with = "serde_utils::display_from_str"))]
     pub command: CommandString,
     /// Payload length.
     pub length: u32,
@@ -47,6 +161,98 @@ struct Header {
     pub checksum: [u8; 4],
 }
 
+impl Header {
+    /// Computes the header `message` is framed with on `network`
+    ///
+    /// The message is serialized to find its length and checksum, exactly as
+    /// [`V1MessageEncoder`] does, but only the header is kept. This lets a caller
+    /// work out framing metadata, such as the size of the frame, without
+    /// emitting the message.
+    ///
+    /// # Panics
+    ///
+    /// If the payload is 4GiB or larger, which only an oversized
+    /// [`NetworkMessage::Unknown`] can be, as its length would not fit the header.
+    pub fn from_message(network: Network, message: &NetworkMessage) -> Self {
+        Self::framing::<Sha256d>(network.magic(), message, &encode::serialize(message))
+    }
+
+    /// The header of `message` framed with `magic` around its serialized `payload`.
+    fn framing<C: Checksum>(magic: Magic, message: &NetworkMessage, payload: &[u8]) -> Self {
+        Self {
+            magic,
+            command: message.command(),
+            length: u32::try_from(payload.len()).expect("payloads are far smaller than 4GiB"),
+            checksum: C::checksum(payload),
+        }
+    }
+
+    /// Serializes the header to the 24 bytes which precede its payload.
+    pub fn to_bytes(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0; HEADER_SIZE];
+        bytes[..4].copy_from_slice(&self.magic.to_bytes());
+        self.command
+            .consensus_encode(&mut &mut bytes[4..16])
+            .expect("commands are 12 bytes");
+        bytes[16..20].copy_from_slice(&self.length.to_le_bytes());
+        bytes[20..].copy_from_slice(&self.checksum);
+        bytes
+    }
+
+    /// Checks a payload which is already in memory against this header and deserializes it
+    ///
+    /// The second half of decoding a frame whose payload lives somewhere other
+    /// than the stream, such as a block file or a memory mapped region. Decode
+    /// the header with a [`HeaderDecoder`], locate its [`length`](Self::length)
+    /// bytes of payload, then hand them here to be checked against the
+    /// [`checksum`](Self::checksum) without pushing them through a decoder.
+    ///
+    /// Fails with [`DecodeError::IncompletePayload`] if `payload` is shorter than
+    /// the announced length and [`DecodeError::TrailingPayloadBytes`] if it is
+    /// longer, otherwise the checks are those of [`V1MessageDecoder::new`].
+    pub fn verify_payload(&self, payload: &[u8]) -> Result<NetworkMessage, DecodeError> {
+        let length = self.length as usize;
+        if payload.len() < length {
+            return Err(DecodeError::IncompletePayload);
+        }
+        if payload.len() > length {
+            return Err(DecodeError::TrailingPayloadBytes(payload.len() - length));
+        }
+        let decoder = PayloadDecoder::<Sha256d>::new(self.clone(), PayloadOptions::default());
+        let mut engine = Sha256d::engine();
+        Sha256d::input(&mut engine, payload);
+        decoder.message(engine, Cow::Borrowed(payload))
+    }
+}
+
+/// Payload limit used unless a decoder is configured with its own, 32MiB.
+///
+/// This is Bitcoin Core's limit on the size of a whole message, so any payload
+/// a well-behaved peer sends fits within it.
+pub const MAX_PAYLOAD_SIZE: usize = 32 * 1024 * 1024;
+
+// Announced lengths are cast from `u32` to `usize` throughout, which is only
+// lossless where `usize` is at least 32 bits wide.
+const _: () = assert!(usize::BITS >= u32::BITS);
+
+/// Largest bloom filter a `filterload` may carry, in bytes (BIP-37).
+pub const MAX_BLOOM_FILTER_SIZE: usize = 36_000;
+
+/// Most hash functions a `filterload` may ask for (BIP-37).
+pub const MAX_BLOOM_HASH_FUNCS: u32 = 50;
+
+/// Largest data element a `filteradd` may carry, in bytes (BIP-37).
+pub const MAX_FILTER_ADD_SIZE: usize = 520;
+
+/// Most entries an `inv`, `getdata` or `notfound` may carry, as in Bitcoin Core.
+pub const MAX_INV_SIZE: u64 = 50_000;
+
+/// Size in bytes of a V1 message header.
+pub const HEADER_SIZE: usize = 24;
+
+// Most a split payload reserves before its bytes arrive, a typical socket read.
+const INITIAL_RESERVATION: usize = 64 * 1024;
+
 // Type alias for the decoder chain that parses raw header bytes
 type RawHeaderDecoder = Chain<
     Chain<Chain<ByteArrayDecoder<4>, ByteArrayDecoder<12>>, IntDecoder<u32, LittleEndian>>,
@@ -54,19 +260,45 @@ type RawHeaderDecoder = Chain<
 >;
 
 /// Decoder for bitcoin v1 transport message headers.
-struct HeaderDecoder {
+///
+/// Decodes just the 24 byte header, which allows a caller to inspect the command
+/// and payload length before committing to buffering the payload. A payload the
+/// caller already holds can then be checked with [`Header::verify_payload`].
+pub struct HeaderDecoder {
     inner: RawHeaderDecoder,
     expected_magic: Magic,
+    max_payload: usize,
 }
 
 impl HeaderDecoder {
-    fn new(expected_magic: Magic) -> Self {
+    /// Creates a new header decoder for the specified network
+    pub fn new(network: Network) -> Self {
+        Self::with_magic(network.magic())
+    }
+
+    /// Creates a new header decoder which expects the given network magic
+    pub fn with_magic(magic: Magic) -> Self {
+        Self::from_magic(magic, MAX_PAYLOAD_SIZE)
+    }
+
+    /// Creates a new header decoder which rejects payloads larger than `max` bytes
+    pub fn with_max_payload(network: Network, max: usize) -> Self {
+        Self::from_magic(network.magic(), max)
+    }
+
+    /// Discards any partially read header, keeping the expected magic and payload limit
+    pub fn reset(&mut self) {
+        *self = Self::from_magic(self.expected_magic, self.max_payload);
+    }
+
+    fn from_magic(expected_magic: Magic, max_payload: usize) -> Self {
         Self {
             inner: ByteArrayDecoder::<4>::new()
                 .chain(ByteArrayDecoder::<12>::new())
                 .chain(IntDecoder::<u32, LittleEndian>::new())
                 .chain(ByteArrayDecoder::<4>::new()),
             expected_magic,
+            max_payload,
         }
     }
 }
@@ -76,95 +308,1238 @@ impl Decoder for HeaderDecoder {
     type Error = DecodeError;
 
     fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
-        self.inner.decode_chunk(bytes)?;
-        Ok(())
+        self.inner
+            .decode_chunk(bytes)
+            .map_err(|_| DecodeError::IncompleteHeader)
     }
 
     fn end(self) -> Result<Self::Value, Self::Error> {
-        // Extract the raw values from the inner decoder and validate.
-        let (((magic_bytes, command_bytes), length), checksum) = self.inner.end()?;
-        let magic = Magic::from_bytes(magic_bytes);
-        let command = encode::deserialize::<CommandString>(&command_bytes[..])
-            .map_err(|_| DecodeError::InvalidCommand)?;
-
-        if magic != self.expected_magic {
-            return Err(DecodeError::WrongMagic {
-                expected: self.expected_magic,
-                actual: magic,
-            });
-        }
-
-        if length > 32 * 1024 * 1024 {
-            return Err(DecodeError::PayloadTooLarge(length as usize));
-        }
+        self.validate().map_err(|(e, _)| e)
+    }
+}
 
-        Ok(Header {
+impl HeaderDecoder {
+    /// Validates the header, returning the announced length along with errors
+    /// which leave the frame boundary intact.
+    ///
+    /// The magic and length are checked before the command, so a length is only
+    /// returned for a frame of the expected network which is within the limit,
+    /// or for a payload which is too large.
+    fn validate(self) -> Result<Header, (DecodeError, Option<usize>)> {
+        // Extract the raw values from the inner decoder and validate.
+        let (((magic, command), length), checksum) = self
+            .inner
+            .end()
+            .map_err(|_| (DecodeError::IncompleteHeader, None))?;
+        check_header(
+            self.expected_magic,
+            self.max_payload,
             magic,
-            command,
+            &command,
             length,
             checksum,
-        })
+        )
+    }
+
+    /// Decodes a header which is already buffered in full
+    ///
+    /// A fast path for callers holding the 24 bytes, which skips the chain of
+    /// decoders and only allocates to hold a command bitcoin does not model,
+    /// like every other header decode. The checks are those of
+    /// [`Decoder::end`], against this decoder's expected magic and payload
+    /// limit, and any partially read header is left untouched.
+    pub fn decode_array(&self, bytes: &[u8; HEADER_SIZE]) -> Result<Header, DecodeError> {
+        let (magic, rest) = bytes.split_at(4);
+        let (command, rest) = rest.split_at(12);
+        let (length, checksum) = rest.split_at(4);
+        check_header(
+            self.expected_magic,
+            self.max_payload,
+            magic.try_into().expect("split at 4"),
+            command.try_into().expect("split at 12"),
+            u32::from_le_bytes(length.try_into().expect("split at 4")),
+            checksum.try_into().expect("4 bytes remain"),
+        )
+        .map_err(|(e, _)| e)
+    }
+}
+
+/// Decodes a buffered header of a message on `network`
+///
+/// The free standing form of [`HeaderDecoder::decode_array`] with the default
+/// payload limit.
+pub fn decode_header(bytes: &[u8; HEADER_SIZE], network: Network) -> Result<Header, DecodeError> {
+    HeaderDecoder::new(network).decode_array(bytes)
+}
+
+/// Checks the fields of a header, see [`HeaderDecoder::validate`].
+fn check_header(
+    expected_magic: Magic,
+    max_payload: usize,
+    magic: [u8; 4],
+    command: &[u8; 12],
+    length: u32,
+    checksum: [u8; 4],
+) -> Result<Header, (DecodeError, Option<usize>)> {
+    let magic = Magic::from_bytes(magic);
+    if magic != expected_magic {
+        return Err((
+            DecodeError::WrongMagic {
+                expected: expected_magic,
+                actual: magic,
+                network: Network::from_magic(magic),
+            },
+            None,
+        ));
     }
+
+    if length as usize > max_payload {
+        let error = DecodeError::PayloadTooLarge {
+            length: length as usize,
+            max: max_payload,
+        };
+        return Err((error, Some(length as usize)));
+    }
+
+    let command = parse_command(command).map_err(|e| (e, Some(length as usize)))?;
+    Ok(Header {
+        magic,
+        command,
+        length,
+        checksum,
+    })
+}
+
+/// Options which control how a payload is turned in to a message.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+struct PayloadOptions {
+    /// Return payloads which fail to deserialize as `NetworkMessage::Unknown`.
+    unknown_fallback: bool,
+    /// Trust the payload without hashing it against the header's checksum.
+    skip_checksum: bool,
+    /// Grow the payload buffer as bytes arrive instead of reserving it up front.
+    incremental_alloc: bool,
+    /// Accept messages which leave bytes of their payload undeserialized.
+    ignore_trailing: bool,
+    /// Protocol version negotiated with the peer, the latest if unset.
+    protocol_version: Option<u32>,
+    /// Fill in the optional trailing fields of a `version` which leaves them out.
+    lenient_version: bool,
 }
 
 /// Decoder for Bitcoin message payloads
-struct PayloadDecoder {
-    inner: ByteVecDecoder,
-    expected_checksum: [u8; 4],
+///
+/// The checksum is computed as bytes arrive so the payload is only passed over
+/// once more, to deserialize it. If the whole payload arrives in one chunk it is
+/// deserialized straight from the caller's buffer and is never copied.
+struct PayloadDecoder<C: Checksum = Sha256d, P: PayloadParser = NetworkMessageParser> {
+    // Created once the payload turns out to be split across chunks.
+    buffer: Option<Vec<u8>>,
+    // Where the buffer comes from and goes back to, allocated if unset.
+    buffers: Option<Arc<dyn PayloadBufferSource>>,
+    remaining: usize,
+    engine: C::Engine,
+    header: Header,
+    options: PayloadOptions,
+    parser: P,
+    // Set when the payload was decoded in place.
+    message: Option<P::Message>,
+}
+
+impl<C: Checksum> PayloadDecoder<C> {
+    fn new(header: Header, options: PayloadOptions) -> Self {
+        let parser = NetworkMessageParser {
+            unknown_fallback: options.unknown_fallback,
+            ignore_trailing: options.ignore_trailing,
+            protocol_version: options.protocol_version,
+            lenient_version: options.lenient_version,
+        };
+        Self::with_parser(header, options, parser)
+    }
 }
 
-impl PayloadDecoder {
-    fn new(header: Header) -> Self {
+impl<C: Checksum, P: PayloadParser> PayloadDecoder<C, P> {
+    fn with_parser(header: Header, options: PayloadOptions, parser: P) -> Self {
         Self {
-            inner: ByteVecDecoder::new(header.length as usize),
-            expected_checksum: header.checksum,
+            buffer: None,
+            buffers: None,
+            remaining: header.length as usize,
+            engine: C::engine(),
+            header,
+            options,
+            parser,
+            message: None,
         }
     }
+
+    /// Checks the payload against the header and deserializes it.
+    fn message(
+        &self,
+        engine: C::Engine,
+        payload: Cow<'_, [u8]>,
+    ) -> Result<P::Message, DecodeError> {
+        // Validate checksum
+        if !self.options.skip_checksum {
+            let computed = finish::<C>(engine);
+            if computed != self.header.checksum {
+                return Err(DecodeError::InvalidChecksum {
+                    command: self.header.command.clone(),
+                    expected: self.header.checksum,
+                    computed,
+                });
+            }
+        }
+
+        self.parser.parse(&self.header, payload)
+    }
 }
 
-impl Decoder for PayloadDecoder {
-    type Value = NetworkMessage;
+impl<C: Checksum, P: PayloadParser> Decoder for PayloadDecoder<C, P> {
+    type Value = (Header, P::Message);
     type Error = DecodeError;
 
     fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
-        self.inner.decode_chunk(bytes)?;
+        if self.message.is_some() {
+            return Ok(());
+        }
+
+        // Nothing has been buffered yet and the chunk holds the whole payload.
+        if self.buffer.is_none() && bytes.len() >= self.remaining {
+            let (payload, rest) = bytes.split_at(self.remaining);
+            *bytes = rest;
+            self.remaining = 0;
+            let mut engine = C::engine();
+            if !self.options.skip_checksum {
+                C::input(&mut engine, payload);
+            }
+            self.message = Some(self.message(engine, Cow::Borrowed(payload))?);
+            return Ok(());
+        }
+
+        let length = self.header.length as usize;
+        let incremental = self.options.incremental_alloc;
+        let buffers = &self.buffers;
+        let buffer = self.buffer.get_or_insert_with(|| {
+            let mut buffer = buffers.as_ref().map_or_else(Vec::new, |b| b.take(length));
+            if !incremental {
+                buffer.reserve_exact(length.min(INITIAL_RESERVATION));
+            }
+            buffer
+        });
+        let (consumed, rest) = bytes.split_at(bytes.len().min(self.remaining));
+        *bytes = rest;
+        // Double the buffer as bytes arrive, but never past the announced length,
+        // so the peer has to actually send what it claims.
+        if buffer.capacity() - buffer.len() < consumed.len() {
+            let target = (buffer.len() + consumed.len())
+                .max(buffer.capacity().saturating_mul(2))
+                .min(length);
+            buffer.reserve_exact(target - buffer.len());
+        }
+        buffer.extend_from_slice(consumed);
+        self.remaining -= consumed.len();
+        if !self.options.skip_checksum {
+            C::input(&mut self.engine, consumed);
+        }
         Ok(())
     }
 
-    fn end(self) -> Result<Self::Value, Self::Error> {
-        let payload_bytes = self.inner.end()?;
+    fn end(mut self) -> Result<Self::Value, Self::Error> {
+        let message = match (self.message.take(), self.buffer.take()) {
+            (Some(message), _) => message,
+            (None, Some(_)) if self.remaining > 0 => return Err(DecodeError::IncompletePayload),
+            (None, Some(payload)) => {
+                let engine = core::mem::replace(&mut self.engine, C::engine());
+                match self.buffers.take() {
+                    // A parser which keeps the payload copies it out of a pooled buffer.
+                    Some(buffers) => {
+                        let message = self.message(engine, Cow::Borrowed(&payload));
+                        buffers.give_back(payload);
+                        message?
+                    }
+                    None => self.message(engine, Cow::Owned(payload))?,
+                }
+            }
+            // No bytes were fed, which is only complete for an empty payload.
+            (None, None) if self.remaining == 0 => self.message(C::engine(), Cow::Borrowed(&[]))?,
+            (None, None) => return Err(DecodeError::IncompletePayload),
+        };
+        Ok((self.header, message))
+    }
+}
 
-        // Validate checksum
-        let checksum = sha256d_checksum(&payload_bytes);
-        if checksum != self.expected_checksum {
-            return Err(DecodeError::InvalidChecksum);
+/// Parse the 12 command bytes of a header.
+///
+/// Stricter than `CommandString`'s decoding, which only trims trailing nulls, so
+/// a null followed by other bytes is rejected as it is by Bitcoin Core.
+fn parse_command(bytes: &[u8; 12]) -> Result<CommandString, DecodeError> {
+    let len = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    let (command, padding) = bytes.split_at(len);
+    if padding.iter().any(|&b| b != 0) {
+        return Err(DecodeError::CommandNotNullPadded { bytes: *bytes });
+    }
+    match core::str::from_utf8(command) {
+        // Known commands borrow a static string, so only unknown ones allocate.
+        Ok(command) if command.is_ascii() => match Command::known(command) {
+            Some(known) => Ok(known.to_command_string()),
+            None => Ok(CommandString::try_from(command).expect("commands are at most 12 bytes")),
+        },
+        _ => Err(DecodeError::CommandNotAscii { bytes: *bytes }),
+    }
+}
+
+/// Deserialize a payload into a `NetworkMessage` based on the header's command.
+///
+/// Mirrors the dispatch of `bitcoin`'s `RawNetworkMessage` decoding, which can only
+/// be driven over a whole frame and would verify the checksum a second time.
+/// Returns the message along with the number of payload bytes left over, or
+/// `None` for commands which are not modeled by `NetworkMessage`. Payloads are
+/// read as the latest protocol version serializes them unless an older
+/// `protocol_version` is given, and a `version` may be cut short when
+/// `lenient_version` is set.
+fn deserialize_payload(
+    command: &CommandString,
+    payload: &[u8],
+    protocol_version: Option<u32>,
+    lenient_version: bool,
+) -> Result<Option<(NetworkMessage, usize)>, encode::Error> {
+    let below = |version| protocol_version.is_some_and(|v| v < version);
+    let mut r = payload;
+    let message = match command.as_ref() {
+        "version" if lenient_version => NetworkMessage::Version(decode_lenient_version(&mut r)?),
+        "version" if below(RELAY_VERSION) => NetworkMessage::Version(decode_old_version(&mut r)?),
+        "version" => NetworkMessage::Version(decode(&mut r)?),
+        "verack" => NetworkMessage::Verack,
+        "addr" if below(ADDR_TIME_VERSION) => NetworkMessage::Addr(decode_untimed_addr(&mut r)?),
+        "addr" => NetworkMessage::Addr(decode(&mut r)?),
+        "inv" => NetworkMessage::Inv(decode_inventory(&mut r)?),
+        "getdata" => NetworkMessage::GetData(decode_inventory(&mut r)?),
+        "notfound" => NetworkMessage::NotFound(decode_inventory(&mut r)?),
+        "getblocks" => NetworkMessage::GetBlocks(decode(&mut r)?),
+        "getheaders" => NetworkMessage::GetHeaders(decode(&mut r)?),
+        "mempool" => NetworkMessage::MemPool,
+        "block" => NetworkMessage::Block(decode(&mut r)?),
+        "headers" => NetworkMessage::Headers(decode_headers(&mut r)?),
+        "sendheaders" => NetworkMessage::SendHeaders,
+        "getaddr" => NetworkMessage::GetAddr,
+        "ping" => NetworkMessage::Ping(decode(&mut r)?),
+        "pong" => NetworkMessage::Pong(decode(&mut r)?),
+        "merkleblock" => NetworkMessage::MerkleBlock(decode(&mut r)?),
+        "filterload" => {
+            let filter: FilterLoad = decode(&mut r)?;
+            if filter.filter.len() > MAX_BLOOM_FILTER_SIZE {
+                return Err(encode::Error::ParseFailed("filterload filter too large"));
+            }
+            if filter.hash_funcs > MAX_BLOOM_HASH_FUNCS {
+                return Err(encode::Error::ParseFailed(
+                    "filterload has too many hash functions",
+                ));
+            }
+            NetworkMessage::FilterLoad(filter)
+        }
+        "filteradd" => {
+            let add: FilterAdd = decode(&mut r)?;
+            if add.data.len() > MAX_FILTER_ADD_SIZE {
+                return Err(encode::Error::ParseFailed("filteradd data too large"));
+            }
+            NetworkMessage::FilterAdd(add)
+        }
+        "filterclear" => NetworkMessage::FilterClear,
+        "tx" => NetworkMessage::Tx(decode(&mut r)?),
+        "getcfilters" => NetworkMessage::GetCFilters(decode(&mut r)?),
+        "cfilter" => NetworkMessage::CFilter(decode(&mut r)?),
+        "getcfheaders" => NetworkMessage::GetCFHeaders(decode(&mut r)?),
+        "cfheaders" => NetworkMessage::CFHeaders(decode(&mut r)?),
+        "getcfcheckpt" => NetworkMessage::GetCFCheckpt(decode(&mut r)?),
+        "cfcheckpt" => NetworkMessage::CFCheckpt(decode(&mut r)?),
+        "reject" => NetworkMessage::Reject(decode(&mut r)?),
+        "alert" => NetworkMessage::Alert(decode(&mut r)?),
+        "feefilter" => {
+            // The upper limit is MAX_MONEY, matching Core's use of MoneyRange.
+            let fee: i64 = decode(&mut r)?;
+            if fee < 0 || fee > Amount::MAX_MONEY.to_sat() as i64 {
+                return Err(encode::Error::ParseFailed("feefilter value out of range"));
+            }
+            NetworkMessage::FeeFilter(fee)
+        }
+        "sendcmpct" => NetworkMessage::SendCmpct(decode(&mut r)?),
+        "cmpctblock" => NetworkMessage::CmpctBlock(decode(&mut r)?),
+        "getblocktxn" => NetworkMessage::GetBlockTxn(decode(&mut r)?),
+        "blocktxn" => NetworkMessage::BlockTxn(decode(&mut r)?),
+        "wtxidrelay" => NetworkMessage::WtxidRelay,
+        "addrv2" => NetworkMessage::AddrV2(decode(&mut r)?),
+        "sendaddrv2" => NetworkMessage::SendAddrV2,
+        _ => return Ok(None),
+    };
+
+    Ok(Some((message, r.len())))
+}
+
+fn decode<T: Decodable>(r: &mut &[u8]) -> Result<T, encode::Error> {
+    T::consensus_decode_from_finite_reader(r)
+}
+
+/// First protocol version whose `addr` entries carry a timestamp.
+const ADDR_TIME_VERSION: u32 = 31402;
+
+/// First protocol version whose `version` message carries `relay` (BIP-37).
+const RELAY_VERSION: u32 = 70001;
+
+/// Before BIP-37 `version` ends at the start height, and Bitcoin Core assumes
+/// such peers want transactions relayed.
+fn decode_old_version(r: &mut &[u8]) -> Result<VersionMessage, encode::Error> {
+    Ok(VersionMessage {
+        version: decode(r)?,
+        services: decode(r)?,
+        timestamp: decode(r)?,
+        receiver: decode(r)?,
+        sender: decode(r)?,
+        nonce: decode(r)?,
+        user_agent: decode(r)?,
+        start_height: decode(r)?,
+        relay: if r.is_empty() { true } else { decode(r)? },
+    })
+}
+
+/// Bitcoin Core only requires a `version` up to the receiver's address, each of
+/// the later groups of fields may be missing from the end and takes a default.
+fn decode_lenient_version(r: &mut &[u8]) -> Result<VersionMessage, encode::Error> {
+    let version = decode(r)?;
+    let services = decode(r)?;
+    let timestamp = decode(r)?;
+    let receiver = decode(r)?;
+    let (sender, nonce) = if r.is_empty() {
+        let unspecified = Address {
+            services: ServiceFlags::NONE,
+            address: [0; 8],
+            port: 0,
+        };
+        (unspecified, 0)
+    } else {
+        (decode(r)?, decode(r)?)
+    };
+    Ok(VersionMessage {
+        version,
+        services,
+        timestamp,
+        receiver,
+        sender,
+        nonce,
+        user_agent: if r.is_empty() {
+            String::new()
+        } else {
+            decode(r)?
+        },
+        start_height: if r.is_empty() { 0 } else { decode(r)? },
+        relay: if r.is_empty() { true } else { decode(r)? },
+    })
+}
+
+/// Before timestamps were added `addr` is a plain list of addresses.
+fn decode_untimed_addr(r: &mut &[u8]) -> Result<Vec<(u32, Address)>, encode::Error> {
+    let len = decode::<VarInt>(r)?.0;
+    // Each address takes 26 bytes, which bounds the allocation.
+    let mut addresses = Vec::with_capacity(core::cmp::min(len as usize, r.len() / 26));
+    for _ in 0..len {
+        addresses.push((0, decode(r)?));
+    }
+    Ok(addresses)
+}
+
+/// Inventory lists are bounded by [`MAX_INV_SIZE`], which is checked against the
+/// count before any entry is decoded.
+fn decode_inventory(r: &mut &[u8]) -> Result<Vec<Inventory>, encode::Error> {
+    let len = decode::<VarInt>(&mut &r[..])?.0;
+    if len > MAX_INV_SIZE {
+        return Err(encode::Error::ParseFailed(
+            "inventory count exceeds MAX_INV_SIZE",
+        ));
+    }
+    decode(r)
+}
+
+/// Headers are serialized with a trailing, always zero, transaction count.
+fn decode_headers(r: &mut &[u8]) -> Result<Vec<block::Header>, encode::Error> {
+    let len = decode::<VarInt>(r)?.0;
+    // Each header takes at least 81 bytes, which bounds the allocation.
+    let mut headers = Vec::with_capacity(core::cmp::min(len as usize, r.len() / 81));
+    for _ in 0..len {
+        headers.push(decode(r)?);
+        if decode::<u8>(r)? != 0 {
+            return Err(encode::Error::ParseFailed(
+                "Headers message should not contain transactions",
+            ));
+        }
+    }
+    Ok(headers)
+}
+
+/// Stages of decoding a V1 message.
+// Boxing the payload stage would cost an allocation per message.
+#[allow(clippy::large_enum_variant)]
+enum Stage<C: Checksum> {
+    Header(HeaderDecoder),
+    Payload(PayloadDecoder<C>),
+    // The command is filtered out and the payload is drained.
+    Skip {
+        header: Header,
+        remaining: usize,
+    },
+    // The payload is over the limit, or its message is invalid, and is drained
+    // before reporting the error.
+    Drain {
+        error: DecodeError,
+        remaining: usize,
+    },
+    // The header failed validation and decoding can not continue.
+    Errored,
+}
+
+/// Decoder chaining the header and payload stages.
+///
+/// Unlike the [`Then`](push_decode::decoders::combinators::Then) combinator this
+/// carries the payload options across the stages.
+struct V1DecoderInner<C: Checksum = Sha256d> {
+    stage: Stage<C>,
+    header_received: usize,
+    // The header as received, kept so a partial message can be replayed.
+    header_bytes: [u8; HEADER_SIZE],
+    expected_magic: Magic,
+    max_payload: usize,
+    options: PayloadOptions,
+    // Commands which are deserialized, all of them if unset.
+    allowed: Option<Vec<String>>,
+    // Drain oversized payloads instead of failing on their header.
+    drain_oversized: bool,
+    // Drain frames with an invalid command or payload so decoding can go on.
+    recover_invalid: bool,
+    // Payload lengths allowed per command, any up to the maximum if unset.
+    lengths: Option<LengthLimits>,
+    // Source of payload buffers, allocated per message if unset.
+    buffers: Option<Arc<dyn PayloadBufferSource>>,
+    // Report a first magic of no known network as another protocol.
+    detect_foreign: bool,
+    // Whether a valid header has been received since the decoder was created.
+    header_seen: bool,
+}
+
+impl<C: Checksum> V1DecoderInner<C> {
+    fn new(header: HeaderDecoder, options: PayloadOptions) -> Self {
+        Self {
+            header_received: 0,
+            header_bytes: [0; HEADER_SIZE],
+            expected_magic: header.expected_magic,
+            max_payload: header.max_payload,
+            stage: Stage::Header(header),
+            options,
+            allowed: None,
+            drain_oversized: false,
+            recover_invalid: false,
+            lengths: None,
+            buffers: None,
+            detect_foreign: false,
+            header_seen: false,
+        }
+    }
+
+    /// Writes the fields shown by the public decoders' `Debug` impls.
+    fn debug_fields(&self, f: &mut core::fmt::Formatter<'_>, name: &str) -> core::fmt::Result {
+        let stage = match self.stage {
+            Stage::Header(_) => "header",
+            Stage::Payload(_) => "payload",
+            Stage::Skip { .. } => "skip",
+            Stage::Drain { .. } => "drain",
+            Stage::Errored => "errored",
+        };
+        f.debug_struct(name)
+            .field("expected_magic", &self.expected_magic)
+            .field("max_payload", &self.max_payload)
+            .field("stage", &stage)
+            .finish_non_exhaustive()
+    }
+
+    /// Picks the stage which handles the payload announced by `header`.
+    fn payload_stage(&self, header: Header) -> Result<Stage<C>, DecodeError> {
+        if let Some(lengths) = &self.lengths {
+            if !lengths.allows(header.command.as_ref(), header.length) {
+                return Err(DecodeError::UnexpectedLength {
+                    command: header.command,
+                    length: header.length,
+                });
+            }
+        }
+        Ok(match &self.allowed {
+            Some(allowed) if !allowed.iter().any(|c| c == header.command.as_ref()) => Stage::Skip {
+                remaining: header.length as usize,
+                header,
+            },
+            _ => {
+                let mut decoder = PayloadDecoder::new(header, self.options);
+                decoder.buffers.clone_from(&self.buffers);
+                Stage::Payload(decoder)
+            }
+        })
+    }
+
+    /// Finishes decoding the message in `stage`.
+    fn end_stage(&self, stage: Stage<C>) -> Result<Frame, DecodeError> {
+        match stage {
+            // Input ending between messages is a clean close, not a cut off header.
+            Stage::Header(_) if self.header_received == 0 => Err(DecodeError::EndOfStream),
+            Stage::Header(decoder) => {
+                let header = decoder.end()?;
+                self.end_stage(self.payload_stage(header)?)
+            }
+            Stage::Payload(decoder) => {
+                let (header, message) = decoder.end()?;
+                #[cfg(feature = "tracing")]
+                trace::payload(&header);
+                Ok(Frame { header, message })
+            }
+            Stage::Skip {
+                header,
+                remaining: 0,
+            } => Err(DecodeError::Skipped {
+                command: header.command,
+            }),
+            Stage::Drain {
+                error,
+                remaining: 0,
+            } => Err(error),
+            Stage::Skip { .. } | Stage::Drain { .. } => Err(DecodeError::IncompletePayload),
+            Stage::Errored => {
+                panic!("Decoder::end called after Decoder::decode_chunk already returned an error")
+            }
+        }
+    }
+
+    /// Returns to the header stage, dropping any partially read message.
+    fn reset(&mut self) {
+        self.stage = Stage::Header(HeaderDecoder::from_magic(
+            self.expected_magic,
+            self.max_payload,
+        ));
+        self.header_received = 0;
+    }
+
+    /// The header of the message whose payload is being read.
+    fn header(&self) -> Option<&Header> {
+        match &self.stage {
+            Stage::Payload(decoder) => Some(&decoder.header),
+            Stage::Skip { header, .. } => Some(header),
+            _ => None,
+        }
+    }
+
+    /// Bytes still to be received before the current stage is complete.
+    fn bytes_needed(&self) -> Option<usize> {
+        match &self.stage {
+            Stage::Header(_) => Some(HEADER_SIZE - self.header_received),
+            Stage::Payload(decoder) => Some(decoder.remaining),
+            Stage::Skip { remaining, .. } | Stage::Drain { remaining, .. } => Some(*remaining),
+            Stage::Errored => None,
+        }
+    }
+
+    /// Whether every byte of the current message has been received.
+    fn is_complete(&self) -> bool {
+        match &self.stage {
+            Stage::Payload(decoder) => decoder.remaining == 0,
+            Stage::Skip { remaining, .. } | Stage::Drain { remaining, .. } => *remaining == 0,
+            _ => false,
+        }
+    }
+
+    /// Whether the current frame is being drained or skipped rather than decoded.
+    #[cfg(feature = "bytes")]
+    fn is_discarding(&self) -> bool {
+        matches!(self.stage, Stage::Skip { .. } | Stage::Drain { .. })
+    }
+
+    /// Whether no byte of a message has been received yet.
+    #[cfg(any(feature = "futures", feature = "tokio-util"))]
+    fn is_idle(&self) -> bool {
+        matches!(self.stage, Stage::Header(_)) && self.header_received == 0
+    }
+
+    /// Whether decoding can carry on with the next message after `error`.
+    fn is_recoverable(&self, error: &DecodeError) -> bool {
+        match error {
+            DecodeError::Skipped { .. } => true,
+            DecodeError::PayloadTooLarge { .. } => self.drain_oversized,
+            DecodeError::CommandNotNullPadded { .. }
+            | DecodeError::CommandNotAscii { .. }
+            | DecodeError::InvalidChecksum { .. }
+            | DecodeError::InvalidPayload(_)
+            | DecodeError::TrailingPayloadBytes(_) => self.recover_invalid,
+            _ => false,
+        }
+    }
+
+    /// Whether the frame whose header failed with `error` is drained instead.
+    fn drains(&self, error: &DecodeError) -> bool {
+        match error {
+            DecodeError::PayloadTooLarge { .. } => self.drain_oversized,
+            DecodeError::CommandNotNullPadded { .. } | DecodeError::CommandNotAscii { .. } => {
+                self.recover_invalid
+            }
+            _ => false,
+        }
+    }
+
+    /// Decodes from `bytes`, returning the frame as soon as it is complete.
+    fn feed(&mut self, bytes: &mut &[u8]) -> Result<Option<Frame>, DecodeError> {
+        if let Err(e) = self.decode_chunk(bytes) {
+            // A payload which arrived in one chunk fails as it is consumed, which
+            // leaves `bytes` on the next frame.
+            if self.is_recoverable(&e) {
+                self.reset();
+            }
+            return Err(e);
+        }
+        if self.is_complete() {
+            self.end_and_reset().map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Reports a wrong magic of no known network on the first header as another protocol.
+    fn magic_error(&self, error: DecodeError) -> DecodeError {
+        match error {
+            DecodeError::WrongMagic {
+                actual,
+                network: None,
+                ..
+            } if self.detect_foreign && !self.header_seen => DecodeError::NotBitcoinProtocol {
+                bytes: actual.to_bytes(),
+            },
+            error => error,
+        }
+    }
+
+    /// Ends the current message and resets for the next one.
+    fn end_and_reset(&mut self) -> Result<Frame, DecodeError> {
+        let header = HeaderDecoder::from_magic(self.expected_magic, self.max_payload);
+        let stage = core::mem::replace(&mut self.stage, Stage::Header(header));
+        let result = self.end_stage(stage);
+        self.header_received = 0;
+        #[cfg(feature = "tracing")]
+        if let Err(e) = &result {
+            trace::error(e);
+        }
+        result
+    }
+}
+
+impl<C: Checksum> V1DecoderInner<C> {
+    /// Feeds `bytes` to the stage they belong to, moving on once the header is complete.
+    fn decode_stages(&mut self, bytes: &mut &[u8]) -> Result<(), DecodeError> {
+        if let Stage::Header(decoder) = &mut self.stage {
+            let chunk = *bytes;
+            decoder.decode_chunk(bytes)?;
+            let consumed = &chunk[..chunk.len() - bytes.len()];
+            self.header_bytes[self.header_received..][..consumed.len()].copy_from_slice(consumed);
+            // Move on as soon as the header is complete so an empty payload is
+            // recognized without waiting for further bytes.
+            self.header_received += consumed.len();
+            if self.header_received < HEADER_SIZE {
+                return Ok(());
+            }
+            if let Stage::Header(decoder) = core::mem::replace(&mut self.stage, Stage::Errored) {
+                self.stage = match decoder.validate() {
+                    Ok(header) => {
+                        #[cfg(feature = "tracing")]
+                        trace::header(&header);
+                        self.header_seen = true;
+                        self.payload_stage(header)?
+                    }
+                    Err((error, Some(length))) if self.drains(&error) => Stage::Drain {
+                        error,
+                        remaining: length,
+                    },
+                    Err((e, _)) => return Err(self.magic_error(e)),
+                };
+            }
         }
 
-        // Decode the network message
-        let message = encode::deserialize::<RawNetworkMessage>(&payload_bytes[..])
-            .map_err(DecodeError::InvalidPayload)?;
-        Ok(message.into_payload())
+        match &mut self.stage {
+            Stage::Payload(decoder) => decoder.decode_chunk(bytes),
+            Stage::Skip { remaining, .. } | Stage::Drain { remaining, .. } => {
+                let skip = (*remaining).min(bytes.len());
+                *bytes = &bytes[skip..];
+                *remaining -= skip;
+                Ok(())
+            }
+            _ => panic!("Decoder::decode_chunk called after it already returned an error"),
+        }
     }
 }
 
-// Type alias for the decoder chain.
-type V1DecoderInner = Then<HeaderDecoder, PayloadDecoder, fn(Header) -> PayloadDecoder>;
+impl<C: Checksum> Decoder for V1DecoderInner<C> {
+    type Value = Frame;
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        let result = self.decode_stages(bytes);
+        #[cfg(feature = "tracing")]
+        if let Err(e) = &result {
+            trace::error(e);
+        }
+        result
+    }
+
+    fn end(mut self) -> Result<Self::Value, Self::Error> {
+        let stage = core::mem::replace(&mut self.stage, Stage::Errored);
+        let result = self.end_stage(stage);
+        #[cfg(feature = "tracing")]
+        if let Err(e) = &result {
+            trace::error(e);
+        }
+        result
+    }
+}
 
 /// Decoder for Bitcoin V1 protocol messages
-pub struct V1MessageDecoder {
-    inner: V1DecoderInner,
+///
+/// When a chunk handed to [`Decoder::decode_chunk`] holds the entire payload, the
+/// message is deserialized directly from that chunk and the payload is never
+/// buffered. Otherwise the payload is copied in to a buffer as it arrives. The
+/// buffer starts at no more than 64KiB, however large the announced length, and
+/// doubles as bytes are received up to that length, so a header alone can not
+/// make the decoder reserve the maximum payload. Payloads beyond 64KiB pay for
+/// this with a reallocation and copy on each doubling, though for a 4MiB block
+/// that is lost in the cost of checksumming it. Either way the
+/// decoded [`NetworkMessage`] owns its data, and [`NetworkMessage::Unknown`]
+/// payloads are always copied out.
+///
+/// [`Decoder`] is also implemented for `&mut V1MessageDecoder`, which resets the
+/// decoder once a message is returned so one instance can decode a whole stream.
+///
+/// Payloads are checked with bitcoin's [`Sha256d`] checksum unless the decoder
+/// is created [`with_checksum`](Self::with_checksum).
+pub struct V1MessageDecoder<C: Checksum = Sha256d> {
+    inner: V1DecoderInner<C>,
 }
 
 impl V1MessageDecoder {
     /// Creates a new V1 message decoder for the specified network
     pub fn new(network: Network) -> Self {
         Self {
-            inner: HeaderDecoder::new(network.magic()).then(PayloadDecoder::new),
+            inner: V1DecoderInner::new(HeaderDecoder::new(network), PayloadOptions::default()),
+        }
+    }
+
+    /// Returns a builder for a decoder which combines several options
+    ///
+    /// The constructors below each set a single option, the builder is for
+    /// decoders which need more than one of them.
+    pub fn builder() -> V1MessageDecoderBuilder {
+        V1MessageDecoderBuilder::default()
+    }
+
+    /// Creates a decoder which learns the network from the first message's magic
+    ///
+    /// For diagnostic tools which accept connections from any network. The
+    /// magic is matched against every known [`Network`] and the first message
+    /// comes out along with the network and a decoder expecting its magic for
+    /// the rest of the stream. A magic of no known network fails with
+    /// [`DecodeError::UnknownMagic`].
+    pub fn auto_detect() -> AutoDetectDecoder {
+        AutoDetectDecoder::new()
+    }
+
+    /// Creates a new V1 message decoder which expects the given network magic
+    ///
+    /// Custom signets derive their magic from the challenge script, so they have no
+    /// matching [`Network`] variant. The same goes for networks which are newer
+    /// than the `bitcoin` release this crate depends on.
+    pub fn with_magic(magic: Magic) -> Self {
+        Self {
+            inner: V1DecoderInner::new(HeaderDecoder::with_magic(magic), PayloadOptions::default()),
+        }
+    }
+
+    /// Creates a new V1 message decoder which rejects payloads larger than `max` bytes
+    ///
+    /// The default limit used by [`V1MessageDecoder::new`] is [`MAX_PAYLOAD_SIZE`].
+    pub fn with_max_payload(network: Network, max: usize) -> Self {
+        Self {
+            inner: V1DecoderInner::new(
+                HeaderDecoder::with_max_payload(network, max),
+                PayloadOptions::default(),
+            ),
+        }
+    }
+
+    /// Creates a new V1 message decoder which falls back to [`NetworkMessage::Unknown`]
+    ///
+    /// Payloads with a valid checksum which fail to deserialize are returned raw
+    /// along with their command instead of failing with [`DecodeError::InvalidPayload`].
+    /// This keeps a stream alive when a peer sends a malformed or unexpected message.
+    pub fn with_unknown_fallback(network: Network) -> Self {
+        Self {
+            inner: V1DecoderInner::new(
+                HeaderDecoder::new(network),
+                PayloadOptions {
+                    unknown_fallback: true,
+                    ..PayloadOptions::default()
+                },
+            ),
+        }
+    }
+
+    /// Creates a new V1 message decoder which does not verify payload checksums
+    ///
+    /// The checksum bytes are still read from the header but the payload is never
+    /// hashed, so corrupted payloads go unnoticed unless they fail to deserialize.
+    /// Only use this over a link which already guarantees integrity, such as a
+    /// localhost socket or an authenticated transport.
+    pub fn without_checksum(network: Network) -> Self {
+        Self {
+            inner: V1DecoderInner::new(
+                HeaderDecoder::new(network),
+                PayloadOptions {
+                    skip_checksum: true,
+                    ..PayloadOptions::default()
+                },
+            ),
+        }
+    }
+
+    /// Creates a new V1 message decoder which allocates payloads as they arrive
+    ///
+    /// By default a payload split across chunks reserves up to 64KiB as soon as
+    /// the first bytes arrive, so each connection a peer opens costs up to that
+    /// with a single header. This decoder reserves nothing up front and grows the
+    /// buffer only once bytes are received, a few more reallocations for memory
+    /// which tracks exactly what the peer has sent. Use it when holding many
+    /// connections to untrusted peers.
+    pub fn with_incremental_allocation(network: Network) -> Self {
+        Self {
+            inner: V1DecoderInner::new(
+                HeaderDecoder::new(network),
+                PayloadOptions {
+                    incremental_alloc: true,
+                    ..PayloadOptions::default()
+                },
+            ),
+        }
+    }
+
+    /// Creates a new V1 message decoder which checks payload lengths per command
+    ///
+    /// Headers announcing a length outside the command's entry in `lengths` fail
+    /// with [`DecodeError::UnexpectedLength`] before any payload is read, which
+    /// is cheaper than buffering a payload which can not deserialize. Pass
+    /// [`LengthLimits::default`] for the standard table.
+    pub fn with_length_limits(network: Network, lengths: LengthLimits) -> Self {
+        let mut inner = V1DecoderInner::new(HeaderDecoder::new(network), PayloadOptions::default());
+        inner.lengths = Some(lengths);
+        Self { inner }
+    }
+
+    /// Creates a new V1 message decoder which ignores trailing payload bytes
+    ///
+    /// By default a payload with bytes left over once its message is
+    /// deserialized fails with [`DecodeError::TrailingPayloadBytes`], since it
+    /// points at a framing or serialization mismatch. Bitcoin Core ignores such
+    /// bytes, so this lenient decoder does the same for peers which append
+    /// fields a newer protocol version defines.
+    pub fn with_trailing_bytes_ignored(network: Network) -> Self {
+        Self {
+            inner: V1DecoderInner::new(
+                HeaderDecoder::new(network),
+                PayloadOptions {
+                    ignore_trailing: true,
+                    ..PayloadOptions::default()
+                },
+            ),
+        }
+    }
+
+    /// Creates a new V1 message decoder which only deserializes the `allowed` commands
+    ///
+    /// The payloads of other commands are drained and decoding ends with
+    /// [`DecodeError::Skipped`], which leaves the stream aligned on the next
+    /// message. Since skipped payloads are thrown away their checksum is not
+    /// verified either.
+    pub fn with_commands(network: Network, allowed: &[&str]) -> Self {
+        let mut inner = V1DecoderInner::new(HeaderDecoder::new(network), PayloadOptions::default());
+        inner.allowed = Some(allowed.iter().map(|c| c.to_string()).collect());
+        Self { inner }
+    }
+
+    /// Creates a new V1 message decoder which drains payloads larger than `max` bytes
+    ///
+    /// Rather than failing as soon as the header announces an oversized payload,
+    /// the payload is read and thrown away before decoding ends with
+    /// [`DecodeError::PayloadTooLarge`]. The stream is then aligned on the next
+    /// message, so a connection can be kept open at the cost of reading the
+    /// announced length, which can be up to 4GB.
+    pub fn with_max_payload_drained(network: Network, max: usize) -> Self {
+        let mut inner = V1DecoderInner::new(
+            HeaderDecoder::with_max_payload(network, max),
+            PayloadOptions::default(),
+        );
+        inner.drain_oversized = true;
+        Self { inner }
+    }
+
+    /// Creates a new V1 message decoder which recovers from invalid messages
+    ///
+    /// A frame whose header and length are sound can be stepped over even if its
+    /// contents are not, so a long running crawler need not drop a peer over one
+    /// malformed message. Frames with a command which is not null padded ASCII
+    /// are drained before decoding ends with the command error, and payloads
+    /// which fail their checksum or deserialization are consumed whole. Either
+    /// way the decoder is then ready for the next message, as it is after a
+    /// [`DecodeError::Skipped`], so [`decode_all`](Self::decode_all) carries on
+    /// and [`decode_resilient`](Self::decode_resilient) collects the errors.
+    ///
+    /// A wrong magic or oversized payload means the frame boundary can not be
+    /// trusted and still stops decoding.
+    pub fn with_recovery(network: Network) -> Self {
+        let mut inner = V1DecoderInner::new(HeaderDecoder::new(network), PayloadOptions::default());
+        inner.recover_invalid = true;
+        Self { inner }
+    }
+
+    /// Creates a new V1 message decoder which verifies payloads with checksum `C`
+    ///
+    /// For chains derived from bitcoin which frame messages the same way but hash
+    /// payloads with another algorithm. They have no [`Network`], so the decoder
+    /// takes their magic.
+    pub fn with_checksum<C: Checksum>(magic: Magic) -> V1MessageDecoder<C> {
+        V1MessageDecoder {
+            inner: V1DecoderInner::new(HeaderDecoder::with_magic(magic), PayloadOptions::default()),
+        }
+    }
+
+    /// Walks the frames of a buffer holding a whole capture of messages on `network`
+    ///
+    /// For offline analysis of dumped bytes, such as payloads extracted from a
+    /// pcap. Unlike [`decode_all`](Self::decode_all) the buffer is taken to be
+    /// complete, so a partial frame at its end is yielded as
+    /// [`DecodeError::IncompleteHeader`] or [`DecodeError::IncompletePayload`]
+    /// rather than held for more bytes. The iterator stops after that or any
+    /// other error the decoder can not step over, and its
+    /// [`offset`](SliceMessages::offset) tells where in `bytes` the frame of
+    /// each item starts.
+    pub fn iter_slice(network: Network, bytes: &[u8]) -> SliceMessages<'_> {
+        SliceMessages {
+            decoder: Self::new(network),
+            bytes,
+            position: 0,
+            offset: 0,
+            done: false,
         }
     }
 }
 
-impl Decoder for V1MessageDecoder {
+impl<C: Checksum> V1MessageDecoder<C> {
+    /// Discards any partially read message, keeping the decoder's configuration
+    pub fn reset(&mut self) {
+        self.inner.reset();
+    }
+
+    /// Decodes payloads as they are serialized at protocol `version`
+    ///
+    /// Peers agree on the lower of their two versions during the handshake,
+    /// so this is usually set once the peer's `version` has been received and
+    /// applies from the next message on. Without it payloads are decoded as
+    /// the latest version serializes them. Below 31402 `addr` entries carry no
+    /// timestamp, which is decoded as zero, and below 70001 the `relay` field
+    /// of `version` may be missing, which is decoded as `true`.
+    pub fn set_protocol_version(&mut self, version: u32) {
+        self.inner.options.protocol_version = Some(version);
+    }
+
+    /// Decodes from `bytes` without blocking, for callers which drive their own I/O
+    ///
+    /// Returns `Ok(Some(message))` as soon as a message is complete, the decoder
+    /// is then ready for the next one. `bytes` is advanced past the consumed bytes,
+    /// so whatever remains belongs to the following message and should be fed in
+    /// again. `Ok(None)` means every byte was consumed and more are needed.
+    ///
+    /// After an error the position of `bytes` is unspecified and the decoder must
+    /// be [`reset`](Self::reset) before it is used again. The exceptions are
+    /// [`DecodeError::Skipped`], a drained [`DecodeError::PayloadTooLarge`] and
+    /// the errors stepped over by a decoder created
+    /// [`with_recovery`](Self::with_recovery), after which `bytes` holds the
+    /// following message and the decoder is ready for it.
+    pub fn feed(&mut self, bytes: &mut &[u8]) -> Result<Option<NetworkMessage>, DecodeError> {
+        let frame = self.inner.feed(bytes)?;
+        Ok(frame.map(|frame| frame.message))
+    }
+
+    /// Returns the header of the message whose payload is still being read
+    ///
+    /// The header is available as soon as its 24 bytes have been fed, so feeding
+    /// just those first lets a caller dispatch on the command or size a buffer by
+    /// the payload length before any of the payload is read. Returns `None`
+    /// between messages and while the header is incomplete.
+    pub fn header(&self) -> Option<&Header> {
+        self.inner.header()
+    }
+
+    /// Returns how many more bytes the current stage of the message needs
+    ///
+    /// While the header is being read this is what remains of its 24 bytes, as
+    /// the payload length is not known yet. Once the header is complete it is
+    /// what remains of the payload, which is zero for an empty payload. A caller
+    /// driving its own reads can size each read with this so it never reads past
+    /// the frame. Returns `None` after an error until the decoder is
+    /// [`reset`](Self::reset).
+    pub fn bytes_needed(&self) -> Option<usize> {
+        self.inner.bytes_needed()
+    }
+
+    /// Reads exactly one message from `reader`, leaving it just past the frame
+    ///
+    /// Unlike [`DecoderExt::decode_sync`], which needs to see the byte after a
+    /// message or end of file before it can return, this returns as soon as the
+    /// frame is complete and never consumes or waits for bytes beyond it. The
+    /// reader can then be handed to something else, such as a different decoder.
+    ///
+    /// If the reader ends between messages the error is
+    /// [`DecodeError::EndOfStream`], part way through one it is
+    /// [`DecodeError::IncompleteHeader`] or [`DecodeError::IncompletePayload`].
+    /// Either way the decoder is reset. Other errors leave the reader and decoder as
+    /// described for [`feed`](Self::feed).
+    pub fn read_message<R: std::io::BufRead + ?Sized>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<NetworkMessage, ReadError<DecodeError>> {
+        loop {
+            let buf = match reader.fill_buf() {
+                Ok(buf) => buf,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(ReadError::Read(e)),
+            };
+            let (consumed, result) = self.read_chunk(buf);
+            reader.consume(consumed);
+            if let Some(message) = result? {
+                return Ok(message);
+            }
+        }
+    }
+
+    /// Asynchronously reads exactly one message from `reader`, leaving it just past the frame
+    ///
+    /// The tokio counterpart of [`read_message`](Self::read_message), with the
+    /// same guarantee of not reading beyond the frame.
+    #[cfg(feature = "tokio")]
+    pub async fn read_message_tokio<R: tokio::io::AsyncBufRead + Unpin + ?Sized>(
+        &mut self,
+        reader: &mut R,
+    ) -> Result<NetworkMessage, ReadError<DecodeError>> {
+        core::future::poll_fn(|cx| loop {
+            let mut reader = core::pin::Pin::new(&mut *reader);
+            let buf = match core::task::ready!(reader.as_mut().poll_fill_buf(cx)) {
+                Ok(buf) => buf,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return core::task::Poll::Ready(Err(ReadError::Read(e))),
+            };
+            let (consumed, result) = self.read_chunk(buf);
+            reader.as_mut().consume(consumed);
+            match result {
+                Ok(None) => continue,
+                Ok(Some(message)) => return core::task::Poll::Ready(Ok(message)),
+                Err(e) => return core::task::Poll::Ready(Err(e)),
+            }
+        })
+        .await
+    }
+
+    /// Feeds one buffer from a reader, returning how much of it was consumed.
+    fn read_chunk(
+        &mut self,
+        buf: &[u8],
+    ) -> (
+        usize,
+        Result<Option<NetworkMessage>, ReadError<DecodeError>>,
+    ) {
+        if buf.is_empty() {
+            let error = match self.inner.end_and_reset() {
+                Err(e) => e,
+                Ok(_) => unreachable!("complete messages are returned as they finish"),
+            };
+            return (0, Err(ReadError::Decode(error)));
+        }
+        let mut chunk = buf;
+        let result = self.feed(&mut chunk);
+        (buf.len() - chunk.len(), result.map_err(ReadError::Decode))
+    }
+
+    /// Decodes every message in `bytes`, for reads which pull in several at once
+    ///
+    /// The returned iterator feeds `bytes` through the decoder until they run out.
+    /// A trailing partial message is held by the decoder, so the buffer can be
+    /// reused for the next read and the message is finished by the next call.
+    /// Recoverable errors, those listed for [`feed`](Self::feed), are yielded
+    /// without ending the iteration, any other error is yielded last and leaves the decoder needing
+    /// a [`reset`](Self::reset).
+    pub fn decode_all<'a>(&'a mut self, bytes: &'a [u8]) -> DecodeAll<'a, C> {
+        DecodeAll {
+            decoder: self,
+            bytes,
+            failed: false,
+        }
+    }
+
+    /// Decodes every message in `bytes`, collecting recoverable errors as warnings
+    ///
+    /// The loop of [`decode_all`](Self::decode_all) gathered in to a
+    /// [`Recovered`], for callers which would rather log a bad message than stop.
+    /// Which errors are recoverable depends on how the decoder was created, with
+    /// [`with_recovery`](Self::with_recovery) that covers invalid commands,
+    /// checksums and payloads. A trailing partial message is held by the decoder
+    /// for the next call, unless a fatal error stopped decoding first.
+    pub fn decode_resilient(&mut self, bytes: &[u8]) -> Recovered {
+        let mut recovered = Recovered::default();
+        let mut messages = self.decode_all(bytes);
+        while let Some(result) = messages.next() {
+            match result {
+                Ok(message) => recovered.messages.push(message),
+                Err(e) if messages.failed => recovered.error = Some(e),
+                Err(e) => recovered.warnings.push(e),
+            }
+        }
+        recovered
+    }
+
+    /// Converts in to a decoder which also returns the message's [`Header`]
+    pub fn into_frame_decoder(self) -> V1FrameDecoder<C> {
+        V1FrameDecoder { inner: self.inner }
+    }
+
+    /// Converts in to a decoder which also returns the message's bytes as received
+    pub fn into_wire_decoder(self) -> V1WireDecoder<C> {
+        V1WireDecoder {
+            inner: self.inner,
+            wire: Vec::new(),
+        }
+    }
+}
+
+impl<C: Checksum> Decoder for V1MessageDecoder<C> {
+    type Value = NetworkMessage;
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        self.inner.decode_chunk(bytes)?;
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        let frame = self.inner.end()?;
+        Ok(frame.message)
+    }
+}
+
+impl<C: Checksum> Decoder for &mut V1MessageDecoder<C> {
     type Value = NetworkMessage;
     type Error = DecodeError;
 
@@ -173,71 +1548,835 @@ impl Decoder for V1MessageDecoder {
         Ok(())
     }
 
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        let frame = self.inner.end_and_reset()?;
+        Ok(frame.message)
+    }
+}
+
+impl From<Network> for V1MessageDecoder {
+    fn from(network: Network) -> Self {
+        Self::new(network)
+    }
+}
+
+impl From<&Network> for V1MessageDecoder {
+    fn from(network: &Network) -> Self {
+        Self::new(*network)
+    }
+}
+
+impl From<Magic> for V1MessageDecoder {
+    fn from(magic: Magic) -> Self {
+        Self::with_magic(magic)
+    }
+}
+
+/// Clones the configuration in to a decoder in its initial state
+///
+/// A decoder set up once, for example by a [`V1MessageDecoderBuilder`], can
+/// serve as a template which is cloned for each new connection. A partially
+/// decoded message is not carried over, the clone always starts at the next
+/// header, since the checksum state and a pending error can not be cloned.
+impl<C: Checksum> Clone for V1MessageDecoder<C> {
+    fn clone(&self) -> Self {
+        let inner = &self.inner;
+        let header = HeaderDecoder::from_magic(inner.expected_magic, inner.max_payload);
+        let mut clone = V1DecoderInner::new(header, inner.options);
+        clone.allowed.clone_from(&inner.allowed);
+        clone.drain_oversized = inner.drain_oversized;
+        clone.recover_invalid = inner.recover_invalid;
+        clone.lengths.clone_from(&inner.lengths);
+        clone.buffers.clone_from(&inner.buffers);
+        clone.detect_foreign = inner.detect_foreign;
+        Self { inner: clone }
+    }
+}
+
+impl<C: Checksum> core::fmt::Debug for V1MessageDecoder<C> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.inner.debug_fields(f, "V1MessageDecoder")
+    }
+}
+
+/// Iterator over the messages in a buffer, created by [`V1MessageDecoder::decode_all`].
+pub struct DecodeAll<'a, C: Checksum = Sha256d> {
+    decoder: &'a mut V1MessageDecoder<C>,
+    bytes: &'a [u8],
+    failed: bool,
+}
+
+impl<C: Checksum> Iterator for DecodeAll<'_, C> {
+    type Item = Result<NetworkMessage, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.bytes.is_empty() {
+            return None;
+        }
+        match self.decoder.feed(&mut self.bytes) {
+            Ok(message) => message.map(Ok),
+            Err(e) => {
+                self.failed = !self.decoder.inner.is_recoverable(&e);
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Iterator over the frames of a whole buffer, created by [`V1MessageDecoder::iter_slice`].
+pub struct SliceMessages<'a> {
+    decoder: V1MessageDecoder,
+    bytes: &'a [u8],
+    // Bytes of the buffer consumed so far.
+    position: usize,
+    offset: usize,
+    done: bool,
+}
+
+impl SliceMessages<'_> {
+    /// Returns the offset in the buffer of the frame the last item came from.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+impl Iterator for SliceMessages<'_> {
+    type Item = Result<NetworkMessage, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done || self.bytes.is_empty() {
+            return None;
+        }
+        self.offset = self.position;
+        let len = self.bytes.len();
+        let result = self.decoder.feed(&mut self.bytes);
+        self.position += len - self.bytes.len();
+        match result {
+            Ok(Some(message)) => Some(Ok(message)),
+            // Every byte went in to a frame which the buffer does not finish.
+            Ok(None) => {
+                self.done = true;
+                match self.decoder.inner.end_and_reset() {
+                    Err(e) => Some(Err(e)),
+                    Ok(_) => unreachable!("complete messages are returned as they finish"),
+                }
+            }
+            Err(e) => {
+                self.done = !self.decoder.inner.is_recoverable(&e);
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Outcome of [`V1MessageDecoder::decode_resilient`].
+#[derive(Debug, Default)]
+pub struct Recovered {
+    /// The messages decoded, in stream order.
+    pub messages: Vec<NetworkMessage>,
+    /// Errors which were stepped over, in stream order.
+    pub warnings: Vec<DecodeError>,
+    /// The error which stopped decoding, after which the decoder must be
+    /// [`reset`](V1MessageDecoder::reset).
+    pub error: Option<DecodeError>,
+}
+
+/// A decoded message along with the header it was framed with.
+///
+/// The header's command is the ground truth for what the peer sent, whereas
+/// [`NetworkMessage::cmd`] collapses unmodeled commands to `"unknown"`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// The message header.
+    pub header: Header,
+    /// The decoded message.
+    pub message: NetworkMessage,
+}
+
+impl From<Frame> for RawNetworkMessage {
+    /// Keeps the magic the message arrived with, for relaying it unchanged.
+    fn from(frame: Frame) -> Self {
+        RawNetworkMessage::new(frame.header.magic, frame.message)
+    }
+}
+
+impl From<&RawNetworkMessage> for Header {
+    /// Computes the header as [`Header::from_message`] does, with the message's own magic.
+    fn from(message: &RawNetworkMessage) -> Self {
+        let payload = message.payload();
+        Header::framing::<Sha256d>(*message.magic(), payload, &encode::serialize(payload))
+    }
+}
+
+impl From<RawNetworkMessage> for Frame {
+    /// Computes the header the message would be framed with.
+    fn from(message: RawNetworkMessage) -> Self {
+        Frame {
+            header: Header::from(&message),
+            message: message.into_payload(),
+        }
+    }
+}
+
+/// Decoder for Bitcoin V1 protocol messages which keeps the header
+///
+/// Created with [`V1MessageDecoder::into_frame_decoder`]. Like the message
+/// decoder, [`Decoder`] is also implemented for `&mut V1FrameDecoder`.
+pub struct V1FrameDecoder<C: Checksum = Sha256d> {
+    inner: V1DecoderInner<C>,
+}
+
+impl<C: Checksum> V1FrameDecoder<C> {
+    /// Discards any partially read message, keeping the decoder's configuration
+    pub fn reset(&mut self) {
+        self.inner.reset();
+    }
+}
+
+impl<C: Checksum> core::fmt::Debug for V1FrameDecoder<C> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.inner.debug_fields(f, "V1FrameDecoder")
+    }
+}
+
+impl<C: Checksum> Decoder for V1FrameDecoder<C> {
+    type Value = Frame;
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        self.inner.decode_chunk(bytes)?;
+        Ok(())
+    }
+
     fn end(self) -> Result<Self::Value, Self::Error> {
         let value = self.inner.end()?;
         Ok(value)
     }
 }
 
+impl<C: Checksum> Decoder for &mut V1FrameDecoder<C> {
+    type Value = Frame;
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        self.inner.decode_chunk(bytes)?;
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        self.inner.end_and_reset()
+    }
+}
+
+/// Decoder for Bitcoin V1 protocol messages which keeps the frame's bytes
+///
+/// Created with [`V1MessageDecoder::into_wire_decoder`]. Each message comes
+/// with the header and payload exactly as they arrived, so a relay can forward
+/// them verbatim instead of trusting a re-serialization to reproduce them. The
+/// bytes are copied as they are fed, which costs a copy of every payload even
+/// when it is deserialized straight from the chunk. Filtered or drained frames
+/// are not kept. Like the message decoder, [`Decoder`] is also implemented for
+/// `&mut V1WireDecoder`.
+pub struct V1WireDecoder<C: Checksum = Sha256d> {
+    inner: V1DecoderInner<C>,
+    // Header and payload of the current message received so far.
+    wire: Vec<u8>,
+}
+
+impl<C: Checksum> V1WireDecoder<C> {
+    /// Discards any partially read message, keeping the decoder's configuration
+    pub fn reset(&mut self) {
+        self.inner.reset();
+        self.wire.clear();
+    }
+
+    /// Feeds `bytes` to the inner decoder, keeping those of a payload which is decoded.
+    fn record(&mut self, bytes: &mut &[u8]) -> Result<(), DecodeError> {
+        let chunk = *bytes;
+        let header_received = self.inner.header_received;
+        self.inner.decode_chunk(bytes)?;
+        if let Stage::Payload(_) = self.inner.stage {
+            if self.wire.is_empty() {
+                self.wire.extend_from_slice(&self.inner.header_bytes);
+            }
+            let consumed = &chunk[..chunk.len() - bytes.len()];
+            self.wire
+                .extend_from_slice(&consumed[HEADER_SIZE - header_received..]);
+        }
+        Ok(())
+    }
+}
+
+impl<C: Checksum> core::fmt::Debug for V1WireDecoder<C> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.inner.debug_fields(f, "V1WireDecoder")
+    }
+}
+
+impl<C: Checksum> Decoder for V1WireDecoder<C> {
+    type Value = (NetworkMessage, Vec<u8>);
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        self.record(bytes)
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        let frame = self.inner.end()?;
+        Ok((frame.message, self.wire))
+    }
+}
+
+impl<C: Checksum> Decoder for &mut V1WireDecoder<C> {
+    type Value = (NetworkMessage, Vec<u8>);
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        self.record(bytes)
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        let wire = core::mem::take(&mut self.wire);
+        let frame = self.inner.end_and_reset()?;
+        Ok((frame.message, wire))
+    }
+}
+
+/// Encoder for Bitcoin V1 protocol messages
+///
+/// The payload is serialized up front since the header's length and checksum
+/// depend on it, the framed bytes are then pulled as a single chunk.
+pub struct V1MessageEncoder {
+    // Header and payload together, so an empty payload never yields an empty chunk.
+    inner: BytesEncoder<Vec<u8>>,
+}
+
+impl V1MessageEncoder {
+    /// Creates a new V1 message encoder for the specified network
+    ///
+    /// The payload size is not checked against the limit enforced by
+    /// [`V1MessageDecoder`], so callers are responsible for not exceeding it.
+    pub fn new(network: Network, message: &NetworkMessage) -> Self {
+        let mut bytes = Vec::new();
+        Self::encode_into(network, message, &mut bytes);
+
+        Self {
+            inner: BytesEncoder::new(bytes),
+        }
+    }
+
+    /// Creates a new V1 message encoder which keeps the raw message's own magic
+    ///
+    /// For proxies and relays which re-emit messages with the magic they arrived
+    /// with, such as a [`Frame`] converted in to a [`RawNetworkMessage`], rather
+    /// than that of a configured [`Network`].
+    pub fn from_raw(message: &RawNetworkMessage) -> Self {
+        let mut bytes = Vec::new();
+        frame_into::<Sha256d>(*message.magic(), message.payload(), &mut bytes);
+
+        Self {
+            inner: BytesEncoder::new(bytes),
+        }
+    }
+
+    /// Creates a new V1 message encoder which checksums the payload with `C`
+    ///
+    /// The counterpart of [`V1MessageDecoder::with_checksum`] for chains which
+    /// hash payloads with another algorithm.
+    pub fn with_checksum<C: Checksum>(magic: Magic, message: &NetworkMessage) -> Self {
+        let mut bytes = Vec::new();
+        frame_into::<C>(magic, message, &mut bytes);
+
+        Self {
+            inner: BytesEncoder::new(bytes),
+        }
+    }
+
+    /// Appends the framed message to `out`
+    ///
+    /// The message is serialized straight in to `out`, so once its capacity has
+    /// grown no allocation is made. Appending several messages to one buffer
+    /// lets them be written with a single call.
+    pub fn encode_into(network: Network, message: &NetworkMessage, out: &mut Vec<u8>) {
+        frame_into::<Sha256d>(network.magic(), message, out);
+    }
+
+    /// Writes the framed message to the start of `out`, returning its length
+    ///
+    /// Fails with [`EncodeError::BufferTooSmall`] if the message does not fit, in
+    /// which case the contents of `out` are unspecified.
+    pub fn encode_to_slice(
+        network: Network,
+        message: &NetworkMessage,
+        out: &mut [u8],
+    ) -> Result<usize, EncodeError> {
+        let available = out.len();
+        let too_small = || EncodeError::BufferTooSmall {
+            needed: HEADER_SIZE
+                + message
+                    .consensus_encode(&mut bitcoin::io::sink())
+                    .expect("writing to a sink is infallible"),
+            available,
+        };
+        if available < HEADER_SIZE {
+            return Err(too_small());
+        }
+
+        let (header, payload) = out.split_at_mut(HEADER_SIZE);
+        let length = message
+            .consensus_encode(&mut &mut payload[..])
+            .map_err(|_| too_small())?;
+        write_header::<Sha256d>(header, network.magic(), message, &payload[..length]);
+        Ok(HEADER_SIZE + length)
+    }
+}
+
+impl Encoder for V1MessageEncoder {
+    fn encoded_chunk(&self) -> &[u8] {
+        self.inner.encoded_chunk()
+    }
+
+    fn next(&mut self) -> bool {
+        self.inner.next()
+    }
+}
+
+/// Appends `message` framed with `magic` to `out`.
+fn frame_into<C: Checksum>(magic: Magic, message: &NetworkMessage, out: &mut Vec<u8>) {
+    let start = out.len();
+    out.extend_from_slice(&[0u8; HEADER_SIZE]);
+    message
+        .consensus_encode(out)
+        .expect("writing to a vec is infallible");
+    let (header, payload) = out[start..].split_at_mut(HEADER_SIZE);
+    write_header::<C>(header, magic, message, payload);
+}
+
+/// Fills in a V1 header for `message` with the given serialized `payload`.
+fn write_header<C: Checksum>(
+    header: &mut [u8],
+    magic: Magic,
+    message: &NetworkMessage,
+    payload: &[u8],
+) {
+    header.copy_from_slice(&Header::framing::<C>(magic, message, payload).to_bytes());
+}
+
 /// Errors that can occur during decoding.
 #[derive(Debug)]
 pub enum DecodeError {
     /// Wrong network magic bytes.
-    WrongMagic { expected: Magic, actual: Magic },
-    /// Invalid command string.
+    ///
+    /// `network` is the known network whose magic was received, if any, which
+    /// usually means the peer is configured for a different network.
+    WrongMagic {
+        expected: Magic,
+        actual: Magic,
+        network: Option<Network>,
+    },
+    /// A v2 packet's command is neither a known short id nor a full command.
     InvalidCommand,
-    /// Payload size exceeds maximum allowed (32MB).
-    PayloadTooLarge(usize),
+    /// The command has bytes other than null after its first null byte.
+    CommandNotNullPadded { bytes: [u8; 12] },
+    /// The command has non-ASCII bytes.
+    CommandNotAscii { bytes: [u8; 12] },
+    /// Payload size exceeds the configured maximum ([`MAX_PAYLOAD_SIZE`] by default).
+    PayloadTooLarge { length: usize, max: usize },
     /// Checksum verification failed.
-    InvalidChecksum,
-    /// Message incomplete.
-    IncompleteMessage,
-    /// Failed to decode payload contents into a valid NetworkMessage.
+    InvalidChecksum {
+        command: CommandString,
+        expected: [u8; 4],
+        computed: [u8; 4],
+    },
+    /// Input ended cleanly between messages, before any byte of the next one.
+    ///
+    /// This is how a peer closing the connection shows up at the end of a decode
+    /// loop, so it usually means stop rather than report an error.
+    EndOfStream,
+    /// Input ended part way through a message header.
+    IncompleteHeader,
+    /// Input ended part way through a message payload.
+    IncompletePayload,
+    /// Failed to decode payload contents into a valid NetworkMessage
+    ///
+    /// Two of these are equal when their errors are the same variant with the
+    /// same message, so comparing them formats both messages.
     InvalidPayload(encode::Error),
+    /// The peer's v2 garbage terminator was not found within the garbage limit.
+    NoGarbageTerminator,
+    /// A v2 packet failed authentication.
+    DecryptionFailed,
+    /// The message's command was filtered out, its payload has been drained.
+    Skipped { command: CommandString },
+    /// The caller's check cut off a message which was arriving too slowly.
+    Stalled { received: u64 },
+    /// The peer sent more messages in the current window than allowed.
+    RateLimited { command: CommandString },
+    /// The message did not arrive before the timeout elapsed.
+    Timeout,
+    /// The header announced a payload length which is impossible for its command.
+    UnexpectedLength { command: CommandString, length: u32 },
+    /// Bytes of the payload were left over after deserializing the message.
+    TrailingPayloadBytes(usize),
+    /// The first message's magic belongs to none of the known networks.
+    UnknownMagic(Magic),
+    /// The peer sent a message the version handshake does not allow at that point.
+    UnexpectedDuringHandshake { command: CommandString },
+    /// The peer sent more bytes in the current window than its budget allows.
+    ByteBudgetExceeded { budget: u64 },
+    /// The first bytes of the stream are not the magic of any known network.
+    ///
+    /// Only reported when enabled with
+    /// [`detect_foreign_protocol`](V1MessageDecoderBuilder::detect_foreign_protocol),
+    /// `bytes` are the 4 bytes received in place of the magic. The peer most
+    /// likely speaks another protocol entirely, such as an HTTP client or a
+    /// port scanner, rather than bitcoin on a different network.
+    NotBitcoinProtocol { bytes: [u8; 4] },
+}
+
+impl DecodeError {
+    /// Returns the category of the error, without any of its data.
+    pub fn kind(&self) -> DecodeErrorKind {
+        match self {
+            DecodeError::WrongMagic { .. } => DecodeErrorKind::WrongMagic,
+            DecodeError::InvalidCommand => DecodeErrorKind::InvalidCommand,
+            DecodeError::CommandNotNullPadded { .. } => DecodeErrorKind::CommandNotNullPadded,
+            DecodeError::CommandNotAscii { .. } => DecodeErrorKind::CommandNotAscii,
+            DecodeError::PayloadTooLarge { .. } => DecodeErrorKind::PayloadTooLarge,
+            DecodeError::InvalidChecksum { .. } => DecodeErrorKind::InvalidChecksum,
+            DecodeError::EndOfStream => DecodeErrorKind::EndOfStream,
+            DecodeError::IncompleteHeader => DecodeErrorKind::IncompleteHeader,
+            DecodeError::IncompletePayload => DecodeErrorKind::IncompletePayload,
+            DecodeError::InvalidPayload(_) => DecodeErrorKind::InvalidPayload,
+            DecodeError::NoGarbageTerminator => DecodeErrorKind::NoGarbageTerminator,
+            DecodeError::DecryptionFailed => DecodeErrorKind::DecryptionFailed,
+            DecodeError::Skipped { .. } => DecodeErrorKind::Skipped,
+            DecodeError::Stalled { .. } => DecodeErrorKind::Stalled,
+            DecodeError::RateLimited { .. } => DecodeErrorKind::RateLimited,
+            DecodeError::Timeout => DecodeErrorKind::Timeout,
+            DecodeError::UnexpectedLength { .. } => DecodeErrorKind::UnexpectedLength,
+            DecodeError::TrailingPayloadBytes(_) => DecodeErrorKind::TrailingPayloadBytes,
+            DecodeError::UnknownMagic(_) => DecodeErrorKind::UnknownMagic,
+            DecodeError::UnexpectedDuringHandshake { .. } => {
+                DecodeErrorKind::UnexpectedDuringHandshake
+            }
+            DecodeError::ByteBudgetExceeded { .. } => DecodeErrorKind::ByteBudgetExceeded,
+            DecodeError::NotBitcoinProtocol { .. } => DecodeErrorKind::NotBitcoinProtocol,
+        }
+    }
+}
+
+/// Category of a [`DecodeError`], one for each of its variants
+///
+/// Unlike the error itself this is [`Copy`] and hashable, so it can bucket
+/// errors for metrics without matching on the data each variant carries.
+/// [`as_str`](Self::as_str) gives a stable label which does not change along
+/// with the error's [`Display`](core::fmt::Display) text.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum DecodeErrorKind {
+    /// See [`DecodeError::WrongMagic`].
+    WrongMagic,
+    /// See [`DecodeError::InvalidCommand`].
+    InvalidCommand,
+    /// See [`DecodeError::CommandNotNullPadded`].
+    CommandNotNullPadded,
+    /// See [`DecodeError::CommandNotAscii`].
+    CommandNotAscii,
+    /// See [`DecodeError::PayloadTooLarge`].
+    PayloadTooLarge,
+    /// See [`DecodeError::InvalidChecksum`].
+    InvalidChecksum,
+    /// See [`DecodeError::EndOfStream`].
+    EndOfStream,
+    /// See [`DecodeError::IncompleteHeader`].
+    IncompleteHeader,
+    /// See [`DecodeError::IncompletePayload`].
+    IncompletePayload,
+    /// See [`DecodeError::InvalidPayload`].
+    InvalidPayload,
+    /// See [`DecodeError::NoGarbageTerminator`].
+    NoGarbageTerminator,
+    /// See [`DecodeError::DecryptionFailed`].
+    DecryptionFailed,
+    /// See [`DecodeError::Skipped`].
+    Skipped,
+    /// See [`DecodeError::Stalled`].
+    Stalled,
+    /// See [`DecodeError::RateLimited`].
+    RateLimited,
+    /// See [`DecodeError::Timeout`].
+    Timeout,
+    /// See [`DecodeError::UnexpectedLength`].
+    UnexpectedLength,
+    /// See [`DecodeError::TrailingPayloadBytes`].
+    TrailingPayloadBytes,
+    /// See [`DecodeError::UnknownMagic`].
+    UnknownMagic,
+    /// See [`DecodeError::UnexpectedDuringHandshake`].
+    UnexpectedDuringHandshake,
+    /// See [`DecodeError::ByteBudgetExceeded`].
+    ByteBudgetExceeded,
+    /// See [`DecodeError::NotBitcoinProtocol`].
+    NotBitcoinProtocol,
+}
+
+impl DecodeErrorKind {
+    /// Returns the kind as a snake case label, such as `"invalid_checksum"`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DecodeErrorKind::WrongMagic => "wrong_magic",
+            DecodeErrorKind::InvalidCommand => "invalid_command",
+            DecodeErrorKind::CommandNotNullPadded => "command_not_null_padded",
+            DecodeErrorKind::CommandNotAscii => "command_not_ascii",
+            DecodeErrorKind::PayloadTooLarge => "payload_too_large",
+            DecodeErrorKind::InvalidChecksum => "invalid_checksum",
+            DecodeErrorKind::EndOfStream => "end_of_stream",
+            DecodeErrorKind::IncompleteHeader => "incomplete_header",
+            DecodeErrorKind::IncompletePayload => "incomplete_payload",
+            DecodeErrorKind::InvalidPayload => "invalid_payload",
+            DecodeErrorKind::NoGarbageTerminator => "no_garbage_terminator",
+            DecodeErrorKind::DecryptionFailed => "decryption_failed",
+            DecodeErrorKind::Skipped => "skipped",
+            DecodeErrorKind::Stalled => "stalled",
+            DecodeErrorKind::RateLimited => "rate_limited",
+            DecodeErrorKind::Timeout => "timeout",
+            DecodeErrorKind::UnexpectedLength => "unexpected_length",
+            DecodeErrorKind::TrailingPayloadBytes => "trailing_payload_bytes",
+            DecodeErrorKind::UnknownMagic => "unknown_magic",
+            DecodeErrorKind::UnexpectedDuringHandshake => "unexpected_during_handshake",
+            DecodeErrorKind::ByteBudgetExceeded => "byte_budget_exceeded",
+            DecodeErrorKind::NotBitcoinProtocol => "not_bitcoin_protocol",
+        }
+    }
+}
+
+impl core::fmt::Display for DecodeErrorKind {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.write_str(self.as_str())
+    }
 }
 
 impl core::fmt::Display for DecodeError {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         match self {
-            DecodeError::WrongMagic { expected, actual } => {
-                write!(f, "wrong magic: expected {expected:?}, got {actual:?}")
+            DecodeError::WrongMagic {
+                expected,
+                actual,
+                network,
+            } => {
+                write!(f, "wrong magic: expected {expected:?}, got {actual:?}")?;
+                match network {
+                    Some(network) => write!(f, " ({network} magic)"),
+                    None => Ok(()),
+                }
             }
             DecodeError::InvalidCommand => write!(f, "invalid command string"),
-            DecodeError::PayloadTooLarge(size) => write!(f, "payload too large: {size} bytes"),
-            DecodeError::InvalidChecksum => write!(f, "checksum verification failed"),
-            DecodeError::IncompleteMessage => write!(f, "incomplete message"),
+            DecodeError::CommandNotNullPadded { bytes } => {
+                write!(f, "command is not null padded: {}", bytes.as_hex())
+            }
+            DecodeError::CommandNotAscii { bytes } => {
+                write!(f, "command is not ascii: {}", bytes.as_hex())
+            }
+            DecodeError::PayloadTooLarge { length, max } => {
+                write!(
+                    f,
+                    "payload too large: {length} bytes, maximum is {max} bytes"
+                )
+            }
+            DecodeError::InvalidChecksum {
+                command,
+                expected,
+                computed,
+            } => write!(
+                f,
+                "checksum verification failed for {command}: expected {}, computed {}",
+                expected.as_hex(),
+                computed.as_hex()
+            ),
+            DecodeError::EndOfStream => write!(f, "end of stream"),
+            DecodeError::IncompleteHeader => write!(f, "incomplete header"),
+            DecodeError::IncompletePayload => write!(f, "incomplete payload"),
             DecodeError::InvalidPayload(e) => write!(f, "invalid payload: {e}"),
+            DecodeError::NoGarbageTerminator => write!(f, "garbage terminator not found"),
+            DecodeError::DecryptionFailed => write!(f, "packet decryption failed"),
+            DecodeError::Skipped { command } => write!(f, "skipped filtered message {command}"),
+            DecodeError::Stalled { received } => {
+                write!(f, "message stalled after {received} bytes")
+            }
+            DecodeError::RateLimited { command } => {
+                write!(f, "rate limit exceeded by {command} message")
+            }
+            DecodeError::Timeout => write!(f, "message timed out"),
+            DecodeError::UnexpectedLength { command, length } => {
+                write!(f, "unexpected payload length {length} for {command}")
+            }
+            DecodeError::TrailingPayloadBytes(trailing) => {
+                write!(f, "{trailing} bytes left over after payload")
+            }
+            DecodeError::UnknownMagic(magic) => {
+                write!(f, "magic {magic:?} matches no known network")
+            }
+            DecodeError::UnexpectedDuringHandshake { command } => {
+                write!(f, "unexpected {command} message during handshake")
+            }
+            DecodeError::ByteBudgetExceeded { budget } => {
+                write!(f, "byte budget of {budget} bytes exceeded")
+            }
+            DecodeError::NotBitcoinProtocol { bytes } => {
+                write!(f, "not a bitcoin stream, first bytes {}", bytes.as_hex())
+            }
         }
     }
 }
 
-impl From<push_decode::error::UnexpectedEnd> for DecodeError {
-    fn from(_: push_decode::error::UnexpectedEnd) -> Self {
-        DecodeError::IncompleteMessage
+impl core::error::Error for DecodeError {}
+
+/// Payload errors are compared by their variant and message, since
+/// [`encode::Error`] can carry an I/O error which has no equality.
+impl PartialEq for DecodeError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                DecodeError::WrongMagic {
+                    expected,
+                    actual,
+                    network,
+                },
+                DecodeError::WrongMagic {
+                    expected: other_expected,
+                    actual: other_actual,
+                    network: other_network,
+                },
+            ) => (expected, actual, network) == (other_expected, other_actual, other_network),
+            (
+                DecodeError::CommandNotNullPadded { bytes },
+                DecodeError::CommandNotNullPadded { bytes: other },
+            )
+            | (
+                DecodeError::CommandNotAscii { bytes },
+                DecodeError::CommandNotAscii { bytes: other },
+            ) => bytes == other,
+            (
+                DecodeError::PayloadTooLarge { length, max },
+                DecodeError::PayloadTooLarge {
+                    length: other_length,
+                    max: other_max,
+                },
+            ) => (length, max) == (other_length, other_max),
+            (
+                DecodeError::InvalidChecksum {
+                    command,
+                    expected,
+                    computed,
+                },
+                DecodeError::InvalidChecksum {
+                    command: other_command,
+                    expected: other_expected,
+                    computed: other_computed,
+                },
+            ) => (command, expected, computed) == (other_command, other_expected, other_computed),
+            (DecodeError::InvalidPayload(e), DecodeError::InvalidPayload(other)) => {
+                core::mem::discriminant(e) == core::mem::discriminant(other)
+                    && e.to_string() == other.to_string()
+            }
+            (DecodeError::Skipped { command }, DecodeError::Skipped { command: other })
+            | (DecodeError::RateLimited { command }, DecodeError::RateLimited { command: other })
+            | (
+                DecodeError::UnexpectedDuringHandshake { command },
+                DecodeError::UnexpectedDuringHandshake { command: other },
+            ) => command == other,
+            (DecodeError::Stalled { received }, DecodeError::Stalled { received: other })
+            | (
+                DecodeError::ByteBudgetExceeded { budget: received },
+                DecodeError::ByteBudgetExceeded { budget: other },
+            ) => received == other,
+            (
+                DecodeError::UnexpectedLength { command, length },
+                DecodeError::UnexpectedLength {
+                    command: other_command,
+                    length: other_length,
+                },
+            ) => (command, length) == (other_command, other_length),
+            (DecodeError::TrailingPayloadBytes(n), DecodeError::TrailingPayloadBytes(other)) => {
+                n == other
+            }
+            (DecodeError::UnknownMagic(magic), DecodeError::UnknownMagic(other)) => magic == other,
+            (
+                DecodeError::NotBitcoinProtocol { bytes },
+                DecodeError::NotBitcoinProtocol { bytes: other },
+            ) => bytes == other,
+            (DecodeError::InvalidCommand, DecodeError::InvalidCommand)
+            | (DecodeError::EndOfStream, DecodeError::EndOfStream)
+            | (DecodeError::IncompleteHeader, DecodeError::IncompleteHeader)
+            | (DecodeError::IncompletePayload, DecodeError::IncompletePayload)
+            | (DecodeError::NoGarbageTerminator, DecodeError::NoGarbageTerminator)
+            | (DecodeError::DecryptionFailed, DecodeError::DecryptionFailed)
+            | (DecodeError::Timeout, DecodeError::Timeout) => true,
+            _ => false,
+        }
     }
 }
 
-impl<L, R> From<Either<L, R>> for DecodeError
-where
-    DecodeError: From<L>,
-    DecodeError: From<R>,
-{
-    fn from(err: Either<L, R>) -> Self {
-        match err {
-            Either::Left(l) => DecodeError::from(l),
-            Either::Right(r) => DecodeError::from(r),
+impl Eq for DecodeError {}
+
+impl core::hash::Hash for DecodeError {
+    fn hash<H: core::hash::Hasher>(&self, state: &mut H) {
+        core::mem::discriminant(self).hash(state);
+        match self {
+            DecodeError::WrongMagic {
+                expected,
+                actual,
+                network,
+            } => (expected, actual, network).hash(state),
+            DecodeError::CommandNotNullPadded { bytes }
+            | DecodeError::CommandNotAscii { bytes } => bytes.hash(state),
+            DecodeError::PayloadTooLarge { length, max } => (length, max).hash(state),
+            DecodeError::InvalidChecksum {
+                command,
+                expected,
+                computed,
+            } => (command.as_ref(), expected, computed).hash(state),
+            // Equal errors share a variant, so hashing it alone is consistent
+            // with equality and needs no formatting.
+            DecodeError::InvalidPayload(e) => core::mem::discriminant(e).hash(state),
+            DecodeError::Skipped { command }
+            | DecodeError::RateLimited { command }
+            | DecodeError::UnexpectedDuringHandshake { command } => command.as_ref().hash(state),
+            DecodeError::Stalled { received } => received.hash(state),
+            DecodeError::ByteBudgetExceeded { budget } => budget.hash(state),
+            DecodeError::UnexpectedLength { command, length } => {
+                (command.as_ref(), length).hash(state)
+            }
+            DecodeError::TrailingPayloadBytes(n) => n.hash(state),
+            DecodeError::UnknownMagic(magic) => magic.hash(state),
+            DecodeError::NotBitcoinProtocol { bytes } => bytes.hash(state),
+            DecodeError::InvalidCommand
+            | DecodeError::EndOfStream
+            | DecodeError::IncompleteHeader
+            | DecodeError::IncompletePayload
+            | DecodeError::NoGarbageTerminator
+            | DecodeError::DecryptionFailed
+            | DecodeError::Timeout => {}
         }
     }
 }
 
-impl std::error::Error for DecodeError {}
-
-/// Calculate SHA256d checksum (first 4 bytes of SHA256(SHA256(data))).
-fn sha256d_checksum(data: &[u8]) -> [u8; 4] {
-    use bitcoin::hashes::{sha256d, Hash};
+/// Errors that can occur during encoding.
+#[derive(Debug)]
+pub enum EncodeError {
+    /// The output buffer can not hold the framed message.
+    BufferTooSmall { needed: usize, available: usize },
+}
 
-    let hash = sha256d::Hash::hash(data);
-    let mut checksum = [0u8; 4];
-    checksum.copy_from_slice(&hash[..4]);
-    checksum
+impl core::fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            EncodeError::BufferTooSmall { needed, available } => write!(
+                f,
+                "buffer too small: message needs {needed} bytes, {available} available"
+            ),
+        }
+    }
 }
+
+impl core::error::Error for EncodeError {}
@@ -0,0 +1,173 @@
+//! Capping how much a peer can send in a window of time.
+
+use std::collections::BTreeMap;
+
+use push_decode::Decoder;
+
+use crate::{DecodeError, MessageCommand};
+
+/// Decoder wrapper which limits the number of messages per window
+///
+/// The crate is sans-io so it has no clock. Instead `window` is called as each
+/// message completes and returns the window it falls in, for example the
+/// seconds elapsed since the connection opened divided by the window length.
+/// Counts start over whenever the returned window changes. A message which
+/// takes the total past the overall maximum, or its command past a quota set
+/// with [`limit`](Self::limit), ends with [`DecodeError::RateLimited`].
+///
+/// The rejected message has been read in full, so the stream stays aligned on
+/// the next one, though a flooding peer is usually disconnected instead. Like
+/// [`MeteredDecoder`](crate::MeteredDecoder) this wraps a reusable decoder and
+/// implements [`Decoder`] for `&mut RateLimiter`.
+pub struct RateLimiter<D, F> {
+    inner: D,
+    window: F,
+    max_messages: u32,
+    quotas: BTreeMap<String, u32>,
+    // Window the counts belong to, unset until the first message.
+    current: Option<u64>,
+    total: u32,
+    counts: BTreeMap<String, u32>,
+}
+
+impl<D, F: FnMut() -> u64> RateLimiter<D, F> {
+    /// Wraps `inner`, allowing `max_messages` of any command per window
+    pub fn new(inner: D, max_messages: u32, window: F) -> Self {
+        Self {
+            inner,
+            window,
+            max_messages,
+            quotas: BTreeMap::new(),
+            current: None,
+            total: 0,
+            counts: BTreeMap::new(),
+        }
+    }
+
+    /// Allows at most `max` messages of `command` per window
+    ///
+    /// Messages with a quota still count towards the overall maximum.
+    pub fn limit(&mut self, command: &str, max: u32) -> &mut Self {
+        self.quotas.insert(command.to_string(), max);
+        self
+    }
+
+    /// Returns the wrapped decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<D, V, F> Decoder for &mut RateLimiter<D, F>
+where
+    for<'a> &'a mut D: Decoder<Value = V, Error = DecodeError>,
+    V: MessageCommand,
+    F: FnMut() -> u64,
+{
+    type Value = V;
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        (&mut self.inner).decode_chunk(bytes)
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        let value = (&mut self.inner).end()?;
+        let window = (self.window)();
+        if self.current != Some(window) {
+            self.current = Some(window);
+            self.total = 0;
+            self.counts.clear();
+        }
+
+        let command = value.message_command();
+        self.total = self.total.saturating_add(1);
+        let count = self.counts.entry(command.to_string()).or_default();
+        *count = count.saturating_add(1);
+        let over_quota = self
+            .quotas
+            .get(command.as_ref())
+            .is_some_and(|max| *count > *max);
+        if over_quota || self.total > self.max_messages {
+            return Err(DecodeError::RateLimited { command });
+        }
+        Ok(value)
+    }
+}
+
+/// Decoder wrapper which limits the number of bytes per window
+///
+/// Complements the per message [`DecodeError::PayloadTooLarge`] with a limit on
+/// the rate, so a peer can not keep a node busy with a stream of messages which
+/// are each within the limit. `window` is called with every chunk and works as
+/// for [`RateLimiter`], a window which never changes makes the budget cover the
+/// whole connection. Every byte fed counts, framing included, and once the
+/// total for the window goes over `budget` the chunk fails with
+/// [`DecodeError::ByteBudgetExceeded`].
+///
+/// The budget trips part way through a message, which leaves the stream
+/// misaligned, so the peer is expected to be disconnected. Like
+/// [`MeteredDecoder`](crate::MeteredDecoder) this wraps a reusable decoder and
+/// implements [`Decoder`] for `&mut ByteBudget`.
+pub struct ByteBudget<D, F> {
+    inner: D,
+    window: F,
+    budget: u64,
+    // Window the count belongs to, unset until the first chunk.
+    current: Option<u64>,
+    received: u64,
+}
+
+impl<D, F: FnMut() -> u64> ByteBudget<D, F> {
+    /// Wraps `inner`, allowing `budget` bytes per window
+    pub fn new(inner: D, budget: u64, window: F) -> Self {
+        Self {
+            inner,
+            window,
+            budget,
+            current: None,
+            received: 0,
+        }
+    }
+
+    /// Returns the bytes received in the current window.
+    pub fn received(&self) -> u64 {
+        self.received
+    }
+
+    /// Returns the wrapped decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<D, V, F> Decoder for &mut ByteBudget<D, F>
+where
+    for<'a> &'a mut D: Decoder<Value = V, Error = DecodeError>,
+    F: FnMut() -> u64,
+{
+    type Value = V;
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        let window = (self.window)();
+        if self.current != Some(window) {
+            self.current = Some(window);
+            self.received = 0;
+        }
+
+        let len = bytes.len();
+        let result = (&mut self.inner).decode_chunk(bytes);
+        self.received = self.received.saturating_add((len - bytes.len()) as u64);
+        if self.received > self.budget {
+            return Err(DecodeError::ByteBudgetExceeded {
+                budget: self.budget,
+            });
+        }
+        result
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        (&mut self.inner).end()
+    }
+}
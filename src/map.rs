@@ -0,0 +1,80 @@
+//! Turning decoded messages into the caller's own types.
+
+use push_decode::Decoder;
+
+/// Decoder wrapper which passes every decoded value through a function
+///
+/// Lets a caller funnel [`NetworkMessage`]s into its own domain type right
+/// where they are decoded, instead of matching on the result of every driver
+/// call. Mapping to an [`Option`] filters too, messages the caller has no
+/// interest in become `None` and the loop moves on. Errors of the wrapped
+/// decoder pass through untouched.
+///
+/// Like [`MeteredDecoder`](crate::MeteredDecoder) this wraps a reusable decoder
+/// and implements [`Decoder`] for `&mut Map`.
+///
+/// [`NetworkMessage`]: bitcoin::p2p::message::NetworkMessage
+///
+/// ```
+/// use bitcoin::p2p::message::NetworkMessage;
+/// use bitcoin::Network;
+/// use bitcoin_codecs::{DecoderExt, EncoderExt, Map, V1MessageDecoder, V1MessageEncoder};
+///
+/// #[derive(Debug, PartialEq)]
+/// enum Intent {
+///     ReplyPong(u64),
+/// }
+///
+/// let mut stream = Vec::new();
+/// for message in [NetworkMessage::Verack, NetworkMessage::Ping(7)] {
+///     V1MessageEncoder::new(Network::Bitcoin, &message)
+///         .encode_sync(&mut stream)
+///         .unwrap();
+/// }
+///
+/// let mut decoder = Map::new(V1MessageDecoder::new(Network::Bitcoin), |message| {
+///     match message {
+///         NetworkMessage::Ping(nonce) => Some(Intent::ReplyPong(nonce)),
+///         _ => None,
+///     }
+/// });
+/// let mut reader = &stream[..];
+/// assert_eq!((&mut decoder).decode_sync(&mut reader).unwrap(), None);
+/// assert_eq!(
+///     (&mut decoder).decode_sync(&mut reader).unwrap(),
+///     Some(Intent::ReplyPong(7))
+/// );
+/// ```
+pub struct Map<D, F> {
+    inner: D,
+    map: F,
+}
+
+impl<D, F> Map<D, F> {
+    /// Wraps `inner`, passing each decoded value through `map`
+    pub fn new(inner: D, map: F) -> Self {
+        Self { inner, map }
+    }
+
+    /// Returns the wrapped decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<D, V, E, F, T> Decoder for &mut Map<D, F>
+where
+    for<'a> &'a mut D: Decoder<Value = V, Error = E>,
+    F: FnMut(V) -> T,
+{
+    type Value = T;
+    type Error = E;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        (&mut self.inner).decode_chunk(bytes)
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        (&mut self.inner).end().map(&mut self.map)
+    }
+}
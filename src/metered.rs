@@ -0,0 +1,116 @@
+//! Counting the traffic which passes through a decoder.
+
+use std::collections::BTreeMap;
+
+use bitcoin::p2p::message::{CommandString, NetworkMessage};
+use push_decode::Decoder;
+
+use crate::{Frame, Header};
+
+/// Decoded values which carry the command of their message.
+pub trait MessageCommand {
+    /// Returns the command of the message.
+    fn message_command(&self) -> CommandString;
+}
+
+impl MessageCommand for NetworkMessage {
+    fn message_command(&self) -> CommandString {
+        self.command()
+    }
+}
+
+impl MessageCommand for Frame {
+    fn message_command(&self) -> CommandString {
+        self.header.command.clone()
+    }
+}
+
+impl MessageCommand for Header {
+    fn message_command(&self) -> CommandString {
+        self.command.clone()
+    }
+}
+
+/// Counters for a single command.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CommandMetrics {
+    /// Number of messages decoded.
+    pub messages: u64,
+    /// Bytes fed while decoding those messages, including framing.
+    pub bytes: u64,
+}
+
+/// Counters collected by a [`MeteredDecoder`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DecodeMetrics {
+    /// Every byte fed, including those of messages which failed to decode.
+    pub bytes: u64,
+    /// Counters of successfully decoded messages keyed by command.
+    pub commands: BTreeMap<String, CommandMetrics>,
+}
+
+/// Decoder wrapper which counts bytes and messages
+///
+/// Wraps a decoder which is reused across messages, such as
+/// [`V1MessageDecoder`](crate::V1MessageDecoder) or
+/// [`V2MessageDecoder`](crate::V2MessageDecoder), and implements [`Decoder`]
+/// for `&mut MeteredDecoder` in the same way. The counters accumulate over the
+/// life of the wrapper.
+pub struct MeteredDecoder<D> {
+    inner: D,
+    metrics: DecodeMetrics,
+    // Bytes fed for the message currently being decoded.
+    message_bytes: u64,
+}
+
+impl<D> MeteredDecoder<D> {
+    /// Wraps `inner` with zeroed counters
+    pub fn new(inner: D) -> Self {
+        Self {
+            inner,
+            metrics: DecodeMetrics::default(),
+            message_bytes: 0,
+        }
+    }
+
+    /// Returns a copy of the counters collected so far.
+    pub fn snapshot(&self) -> DecodeMetrics {
+        self.metrics.clone()
+    }
+
+    /// Returns the wrapped decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<D, V, E> Decoder for &mut MeteredDecoder<D>
+where
+    for<'a> &'a mut D: Decoder<Value = V, Error = E>,
+    V: MessageCommand,
+{
+    type Value = V;
+    type Error = E;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        let len = bytes.len();
+        let result = (&mut self.inner).decode_chunk(bytes);
+        let consumed = (len - bytes.len()) as u64;
+        self.metrics.bytes += consumed;
+        self.message_bytes += consumed;
+        result
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        let message_bytes = core::mem::take(&mut self.message_bytes);
+        let value = (&mut self.inner).end()?;
+        let counters = self
+            .metrics
+            .commands
+            .entry(value.message_command().to_string())
+            .or_default();
+        counters.messages += 1;
+        counters.bytes += message_bytes;
+        Ok(value)
+    }
+}
@@ -0,0 +1,193 @@
+//! Deserializing payloads in to types other than `NetworkMessage`.
+
+use std::borrow::Cow;
+
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::p2p::Magic;
+use bitcoin::Network;
+use push_decode::Decoder;
+
+use crate::{deserialize_payload, DecodeError, Header, HeaderDecoder, PayloadDecoder, HEADER_SIZE};
+
+/// Turns a checksummed payload in to a message
+///
+/// The V1 decoders frame messages and verify checksums the same way whatever
+/// the payload becomes, deserializing it is the only part which depends on
+/// the message model. Implementing this and decoding with a
+/// [`V1ParserDecoder`] reuses the framing for another model, such as a subset
+/// enum of the commands a caller handles or a view which borrows nothing from
+/// `bitcoin`'s types. [`NetworkMessageParser`] is the model every other
+/// decoder uses.
+///
+/// The payload is borrowed when it arrived in a single chunk and owned when it
+/// had to be buffered, so a parser which keeps the raw bytes can take them
+/// without a copy in the second case.
+pub trait PayloadParser {
+    /// Message the payload is deserialized in to.
+    type Message;
+
+    /// Deserializes the `payload` of the message announced by `header`.
+    fn parse(&self, header: &Header, payload: Cow<'_, [u8]>) -> Result<Self::Message, DecodeError>;
+}
+
+/// The parser of bitcoin's [`NetworkMessage`], used by default.
+///
+/// Commands which are not modeled become [`NetworkMessage::Unknown`], while
+/// malformed payloads or payloads with bytes left over are rejected.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NetworkMessageParser {
+    pub(crate) unknown_fallback: bool,
+    pub(crate) ignore_trailing: bool,
+    pub(crate) protocol_version: Option<u32>,
+    pub(crate) lenient_version: bool,
+}
+
+impl PayloadParser for NetworkMessageParser {
+    type Message = NetworkMessage;
+
+    fn parse(
+        &self,
+        header: &Header,
+        payload: Cow<'_, [u8]>,
+    ) -> Result<NetworkMessage, DecodeError> {
+        // The checksum has already vouched for the bytes so they can be handed
+        // back raw if requested.
+        let unknown = |payload: Cow<'_, [u8]>| NetworkMessage::Unknown {
+            command: header.command.clone(),
+            payload: payload.into_owned(),
+        };
+        match deserialize_payload(
+            &header.command,
+            &payload,
+            self.protocol_version,
+            self.lenient_version,
+        ) {
+            Ok(Some((message, 0))) => Ok(message),
+            Ok(Some((message, _))) if self.ignore_trailing => Ok(message),
+            Ok(Some((_, trailing))) if !self.unknown_fallback => {
+                Err(DecodeError::TrailingPayloadBytes(trailing))
+            }
+            Ok(_) => Ok(unknown(payload)),
+            Err(_) if self.unknown_fallback => Ok(unknown(payload)),
+            Err(e) => Err(DecodeError::InvalidPayload(e)),
+        }
+    }
+}
+
+/// Decoder for Bitcoin V1 protocol messages which deserializes payloads with a [`PayloadParser`]
+///
+/// Headers and checksums are checked exactly as by
+/// [`V1MessageDecoder::new`](crate::V1MessageDecoder::new), and payloads are
+/// buffered the same way, only the resulting message is up to the parser. The
+/// parser is cloned for each message, so it should be cheap to clone.
+///
+/// [`Decoder`] is implemented for `&mut V1ParserDecoder`, which resets the
+/// decoder once a message is returned so one instance can decode a whole
+/// stream. After an error it must be [`reset`](Self::reset) before it is used
+/// again.
+///
+/// ```
+/// use std::borrow::Cow;
+///
+/// use bitcoin::p2p::message::NetworkMessage;
+/// use bitcoin::Network;
+/// use bitcoin_codecs::{
+///     DecodeError, DecoderExt, Header, PayloadParser, V1MessageEncoder, V1ParserDecoder,
+/// };
+///
+/// // Keeps just the command and payload size of each message.
+/// #[derive(Clone)]
+/// struct Summary;
+///
+/// impl PayloadParser for Summary {
+///     type Message = (String, usize);
+///
+///     fn parse(&self, header: &Header, payload: Cow<'_, [u8]>) -> Result<Self::Message, DecodeError> {
+///         Ok((header.command.to_string(), payload.len()))
+///     }
+/// }
+///
+/// let mut bytes = Vec::new();
+/// V1MessageEncoder::encode_into(Network::Bitcoin, &NetworkMessage::Ping(1), &mut bytes);
+/// let mut decoder = V1ParserDecoder::new(Network::Bitcoin, Summary);
+/// let summary = (&mut decoder).decode_sync(&mut &bytes[..]).unwrap();
+/// assert_eq!(summary, ("ping".to_string(), 8));
+/// ```
+pub struct V1ParserDecoder<P: PayloadParser> {
+    header: HeaderDecoder,
+    header_received: usize,
+    payload: Option<PayloadDecoder<crate::Sha256d, P>>,
+    parser: P,
+}
+
+impl<P: PayloadParser + Clone> V1ParserDecoder<P> {
+    /// Creates a decoder for messages on `network` which are deserialized by `parser`
+    pub fn new(network: Network, parser: P) -> Self {
+        Self::with_magic(network.magic(), parser)
+    }
+
+    /// Creates a decoder for messages with `magic` which are deserialized by `parser`
+    pub fn with_magic(magic: Magic, parser: P) -> Self {
+        Self {
+            header: HeaderDecoder::with_magic(magic),
+            header_received: 0,
+            payload: None,
+            parser,
+        }
+    }
+
+    /// Discards any partially decoded message, keeping the parser
+    pub fn reset(&mut self) {
+        self.header.reset();
+        self.header_received = 0;
+        self.payload = None;
+    }
+
+    /// Returns the parser.
+    pub fn into_parser(self) -> P {
+        self.parser
+    }
+
+    /// Takes the completed header and prepares the payload decoder.
+    fn start_payload(&mut self) -> Result<PayloadDecoder<crate::Sha256d, P>, DecodeError> {
+        let fresh = HeaderDecoder::from_magic(self.header.expected_magic, self.header.max_payload);
+        let header = core::mem::replace(&mut self.header, fresh).end()?;
+        Ok(PayloadDecoder::with_parser(
+            header,
+            Default::default(),
+            self.parser.clone(),
+        ))
+    }
+}
+
+impl<P: PayloadParser + Clone> Decoder for &mut V1ParserDecoder<P> {
+    type Value = P::Message;
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        if self.payload.is_none() {
+            let len = bytes.len();
+            self.header.decode_chunk(bytes)?;
+            self.header_received += len - bytes.len();
+            if self.header_received < HEADER_SIZE {
+                return Ok(());
+            }
+            self.payload = Some(self.start_payload()?);
+        }
+
+        match &mut self.payload {
+            Some(decoder) => decoder.decode_chunk(bytes),
+            None => unreachable!("the header is complete"),
+        }
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        let received = core::mem::take(&mut self.header_received);
+        let payload = match self.payload.take() {
+            Some(payload) => payload,
+            None if received == 0 => return Err(DecodeError::EndOfStream),
+            None => self.start_payload()?,
+        };
+        payload.end().map(|(_, message)| message)
+    }
+}
@@ -0,0 +1,85 @@
+//! Recycling the buffers payloads are read in to.
+
+use std::sync::Mutex;
+
+/// Source of the buffers split payloads are copied in to
+///
+/// A payload which arrives in a single chunk is deserialized in place and
+/// never needs a buffer, one which is split across chunks is collected in a
+/// buffer taken from the source and handed back once the message has been
+/// deserialized. Set with
+/// [`V1MessageDecoderBuilder::buffer_source`](crate::V1MessageDecoderBuilder::buffer_source),
+/// the source is shared behind an `Arc` so one pool can serve every
+/// connection of a node.
+pub trait PayloadBufferSource: Send + Sync + core::fmt::Debug {
+    /// Returns an empty buffer for a payload of `length` bytes
+    ///
+    /// The buffer may have any capacity, the decoder still grows it as bytes
+    /// arrive rather than trusting the announced length.
+    fn take(&self, length: usize) -> Vec<u8>;
+
+    /// Takes back a buffer once its payload has been deserialized.
+    fn give_back(&self, buffer: Vec<u8>);
+}
+
+/// Source which allocates every buffer and drops it afterwards.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HeapBuffers;
+
+impl PayloadBufferSource for HeapBuffers {
+    fn take(&self, _length: usize) -> Vec<u8> {
+        Vec::new()
+    }
+
+    fn give_back(&self, _buffer: Vec<u8>) {}
+}
+
+/// Source which keeps returned buffers for the next payloads
+///
+/// At most `max_buffers` are kept and those which grew beyond `max_capacity`
+/// are dropped, so a single large block does not pin its memory for good.
+/// Buffers are handed out in no particular order whatever the announced
+/// length.
+#[derive(Debug)]
+pub struct BufferPool {
+    buffers: Mutex<Vec<Vec<u8>>>,
+    max_buffers: usize,
+    max_capacity: usize,
+}
+
+impl BufferPool {
+    /// Creates an empty pool of up to `max_buffers` of at most `max_capacity` bytes
+    pub fn new(max_buffers: usize, max_capacity: usize) -> Self {
+        Self {
+            buffers: Mutex::new(Vec::new()),
+            max_buffers,
+            max_capacity,
+        }
+    }
+
+    /// Returns the number of buffers waiting to be reused.
+    pub fn available(&self) -> usize {
+        self.lock().len()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Vec<u8>>> {
+        self.buffers.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl PayloadBufferSource for BufferPool {
+    fn take(&self, _length: usize) -> Vec<u8> {
+        self.lock().pop().unwrap_or_default()
+    }
+
+    fn give_back(&self, mut buffer: Vec<u8>) {
+        if buffer.capacity() > self.max_capacity {
+            return;
+        }
+        let mut buffers = self.lock();
+        if buffers.len() < self.max_buffers {
+            buffer.clear();
+            buffers.push(buffer);
+        }
+    }
+}
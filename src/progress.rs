@@ -0,0 +1,52 @@
+//! Reporting how much of a payload has arrived.
+
+use bitcoin::p2p::message::NetworkMessage;
+use push_decode::Decoder;
+
+use crate::{DecodeError, V1MessageDecoder};
+
+/// Decoder wrapper which reports payload progress to a callback
+///
+/// Once a message's header is complete, `progress` is called after every chunk
+/// with the payload bytes received so far and the payload length the header
+/// announced, starting from zero. The chunk which completes the payload is
+/// reported before the message is returned, so the last call always has both
+/// numbers equal. That is enough to drive a progress bar for a large block
+/// without any bookkeeping of the caller's own.
+///
+/// Like [`StallGuard`](crate::StallGuard) this implements [`Decoder`] for
+/// `&mut ProgressDecoder`, so one instance decodes a whole stream.
+pub struct ProgressDecoder<F> {
+    inner: V1MessageDecoder,
+    progress: F,
+}
+
+impl<F: FnMut(u64, u64)> ProgressDecoder<F> {
+    /// Wraps `inner`, calling `progress` with `(received, total)` payload bytes
+    pub fn new(inner: V1MessageDecoder, progress: F) -> Self {
+        Self { inner, progress }
+    }
+
+    /// Returns the wrapped decoder.
+    pub fn into_inner(self) -> V1MessageDecoder {
+        self.inner
+    }
+}
+
+impl<F: FnMut(u64, u64)> Decoder for &mut ProgressDecoder<F> {
+    type Value = NetworkMessage;
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        self.inner.decode_chunk(bytes)?;
+        if let (Some(header), Some(needed)) = (self.inner.header(), self.inner.bytes_needed()) {
+            let total = u64::from(header.length);
+            (self.progress)(total - needed as u64, total);
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        (&mut self.inner).end()
+    }
+}
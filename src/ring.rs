@@ -0,0 +1,99 @@
+//! Feeding a decoder from a fixed capacity ring buffer.
+
+use bitcoin::p2p::message::NetworkMessage;
+
+use crate::{DecodeError, V1MessageDecoder};
+
+/// Fixed capacity byte ring buffer which feeds a [`V1MessageDecoder`]
+///
+/// Meant for targets without an I/O stack, where a driver such as an interrupt
+/// handler writes received bytes in and the main loop pulls messages out. The
+/// buffer itself never allocates, the decoder only allocates the payload of a
+/// message which arrives across several fills. A message does not have to fit
+/// in the buffer, its bytes are handed to the decoder as they wrap around.
+///
+/// The crate itself still requires `std` through its dependencies, the buffer
+/// only stands in for the `std` and tokio I/O drivers.
+pub struct RingBuffer<const N: usize> {
+    buf: [u8; N],
+    start: usize,
+    len: usize,
+}
+
+impl<const N: usize> RingBuffer<N> {
+    /// Creates an empty ring buffer
+    ///
+    /// `N` must be at least one, a buffer which can hold no bytes fails to
+    /// compile.
+    ///
+    /// ```compile_fail
+    /// let buffer = bitcoin_codecs::RingBuffer::<0>::new();
+    /// ```
+    pub const fn new() -> Self {
+        const { assert!(N > 0, "a ring buffer needs a capacity of at least one byte") };
+        Self {
+            buf: [0; N],
+            start: 0,
+            len: 0,
+        }
+    }
+
+    /// Returns the number of buffered bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns whether no bytes are buffered.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Copies in as many of `bytes` as fit and returns how many that was.
+    pub fn write(&mut self, bytes: &[u8]) -> usize {
+        let count = bytes.len().min(N - self.len);
+        for (i, byte) in bytes[..count].iter().enumerate() {
+            self.buf[(self.start + self.len + i) % N] = *byte;
+        }
+        self.len += count;
+        count
+    }
+
+    /// Feeds the buffered bytes to `decoder`, freeing the space of those consumed
+    ///
+    /// Has the same results as [`V1MessageDecoder::feed`]. On `Ok(Some(_))` bytes
+    /// of the following message may remain buffered, so this should be called
+    /// again before waiting on more input.
+    pub fn decode(
+        &mut self,
+        decoder: &mut V1MessageDecoder,
+    ) -> Result<Option<NetworkMessage>, DecodeError> {
+        // The buffered bytes are at most two contiguous runs when they wrap.
+        let end = self.start + self.len;
+        let (first, second) = if end <= N {
+            (&self.buf[self.start..end], &[][..])
+        } else {
+            (&self.buf[self.start..], &self.buf[..end - N])
+        };
+
+        let mut consumed = 0;
+        let mut result = Ok(None);
+        for run in [first, second] {
+            let mut chunk = run;
+            result = decoder.feed(&mut chunk);
+            consumed += run.len() - chunk.len();
+            if !matches!(result, Ok(None)) {
+                break;
+            }
+        }
+
+        self.start = (self.start + consumed) % N;
+        self.len -= consumed;
+        result
+    }
+}
+
+impl<const N: usize> Default for RingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
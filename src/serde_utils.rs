@@ -0,0 +1,28 @@
+//! Serde support for foreign types.
+
+/// Serializes a type through its `Display` and `FromStr` implementations.
+///
+/// `Magic` and `CommandString` only implement serde with `bitcoin`'s own `serde`
+/// feature, both round trip through strings though.
+pub(crate) mod display_from_str {
+    use core::{fmt::Display, str::FromStr};
+
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    pub(crate) fn serialize<T: Display, S: Serializer>(
+        value: &T,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(value)
+    }
+
+    pub(crate) fn deserialize<'de, T, D>(deserializer: D) -> Result<T, D::Error>
+    where
+        T: FromStr,
+        T::Err: Display,
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(D::Error::custom)
+    }
+}
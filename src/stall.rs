@@ -0,0 +1,65 @@
+//! Cutting off peers which stall part way through a message.
+
+use push_decode::Decoder;
+
+use crate::DecodeError;
+
+/// Decoder wrapper which asks the caller whether a message is taking too long
+///
+/// The crate is sans-io so it has no clock. Instead `check` is called before
+/// each chunk is fed with the number of bytes received so far for the current
+/// message, zero meaning a new message is starting. The caller compares that
+/// against its own clock, for example a deadline set when zero is seen or a
+/// minimum throughput, and returns `false` to cut the message off with
+/// [`DecodeError::Stalled`]. After that the wrapped decoder must be reset
+/// before it is used again, though usually the connection is dropped instead.
+///
+/// Like [`MeteredDecoder`](crate::MeteredDecoder) this wraps a reusable decoder
+/// and implements [`Decoder`] for `&mut StallGuard`.
+pub struct StallGuard<D, F> {
+    inner: D,
+    check: F,
+    // Bytes fed for the message currently being decoded.
+    received: u64,
+}
+
+impl<D, F: FnMut(u64) -> bool> StallGuard<D, F> {
+    /// Wraps `inner`, consulting `check` between chunks
+    pub fn new(inner: D, check: F) -> Self {
+        Self {
+            inner,
+            check,
+            received: 0,
+        }
+    }
+
+    /// Returns the wrapped decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<D, V, F> Decoder for &mut StallGuard<D, F>
+where
+    for<'a> &'a mut D: Decoder<Value = V, Error = DecodeError>,
+    F: FnMut(u64) -> bool,
+{
+    type Value = V;
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        if !(self.check)(self.received) {
+            let received = core::mem::take(&mut self.received);
+            return Err(DecodeError::Stalled { received });
+        }
+        let len = bytes.len();
+        let result = (&mut self.inner).decode_chunk(bytes);
+        self.received += (len - bytes.len()) as u64;
+        result
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        self.received = 0;
+        (&mut self.inner).end()
+    }
+}
@@ -0,0 +1,115 @@
+//! Pausing a V1 decoder and picking it back up later.
+
+use bitcoin::p2p::Magic;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    serde_utils, DecodeError, HeaderDecoder, LengthLimits, PayloadOptions, Stage, V1DecoderInner,
+    V1MessageDecoder, HEADER_SIZE,
+};
+
+/// Snapshot of a [`V1MessageDecoder`] part way through a message
+///
+/// Holds the decoder's configuration along with the bytes of the current
+/// message received so far, so a connection can be parked on disk and resumed
+/// where it stopped. The bytes of a payload which is being skipped or drained
+/// are not kept, only how many of them have gone by, and neither is a
+/// [`buffer_source`](crate::V1MessageDecoderBuilder::buffer_source), a
+/// restored decoder allocates its buffers. The contents are an
+/// implementation detail and may change between releases, so a state should be
+/// restored by the version of the crate which took it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct V1DecoderState {
+    #[serde(with = "serde_utils::display_from_str")]
+    magic: Magic,
+    max_payload: usize,
+    options: PayloadOptions,
+    allowed: Option<Vec<String>>,
+    drain_oversized: bool,
+    recover_invalid: bool,
+    lengths: Option<LengthLimits>,
+    detect_foreign: bool,
+    header_seen: bool,
+    // The header and any buffered payload bytes of the current message.
+    received: Vec<u8>,
+    // Payload bytes of a skipped or drained frame which have gone by.
+    skipped: usize,
+}
+
+impl V1MessageDecoder {
+    /// Captures the decoder's configuration and partially decoded message
+    ///
+    /// Returns `None` when the state can not be resumed, which is after an
+    /// error and when a payload is complete but [`Decoder::end`] has not been
+    /// called on it yet, as a payload which arrived in one chunk is
+    /// deserialized straight from it and not kept. Between the calls of
+    /// [`feed`](Self::feed) or [`read_message`](Self::read_message) a state is
+    /// always available.
+    ///
+    /// [`Decoder::end`]: push_decode::Decoder::end
+    pub fn into_state(self) -> Option<V1DecoderState> {
+        let inner = self.inner;
+        let header = &inner.header_bytes[..inner.header_received];
+        let (received, skipped) = match &inner.stage {
+            Stage::Header(_) => (header.to_vec(), 0),
+            Stage::Payload(decoder) if decoder.message.is_some() => return None,
+            Stage::Payload(decoder) => {
+                let payload = decoder.buffer.as_deref().unwrap_or_default();
+                ([header, payload].concat(), 0)
+            }
+            Stage::Skip { remaining, .. } | Stage::Drain { remaining, .. } => {
+                (header.to_vec(), announced_length(header) - remaining)
+            }
+            Stage::Errored => return None,
+        };
+        Some(V1DecoderState {
+            magic: inner.expected_magic,
+            max_payload: inner.max_payload,
+            options: inner.options,
+            allowed: inner.allowed,
+            drain_oversized: inner.drain_oversized,
+            recover_invalid: inner.recover_invalid,
+            lengths: inner.lengths,
+            detect_foreign: inner.detect_foreign,
+            header_seen: inner.header_seen,
+            received,
+            skipped,
+        })
+    }
+
+    /// Restores a decoder from a [`into_state`](Self::into_state) snapshot
+    ///
+    /// The received bytes are decoded again, so the checksum is recomputed
+    /// rather than stored and a state which was tampered with fails the same
+    /// checks as any other input. Fails with
+    /// [`DecodeError::TrailingPayloadBytes`] if the state holds more bytes than
+    /// its header announced.
+    pub fn from_state(state: V1DecoderState) -> Result<Self, DecodeError> {
+        let header = HeaderDecoder::from_magic(state.magic, state.max_payload);
+        let mut inner = V1DecoderInner::new(header, state.options);
+        inner.allowed = state.allowed;
+        inner.drain_oversized = state.drain_oversized;
+        inner.recover_invalid = state.recover_invalid;
+        inner.lengths = state.lengths;
+        inner.detect_foreign = state.detect_foreign;
+        inner.header_seen = state.header_seen;
+
+        let mut bytes = &state.received[..];
+        push_decode::Decoder::decode_chunk(&mut inner, &mut bytes)?;
+        if !bytes.is_empty() {
+            return Err(DecodeError::TrailingPayloadBytes(bytes.len()));
+        }
+        if let Stage::Skip { remaining, .. } | Stage::Drain { remaining, .. } = &mut inner.stage {
+            *remaining = remaining
+                .checked_sub(state.skipped)
+                .ok_or_else(|| DecodeError::TrailingPayloadBytes(state.skipped - *remaining))?;
+        }
+        Ok(Self { inner })
+    }
+}
+
+/// The payload length of a complete raw header.
+fn announced_length(header: &[u8]) -> usize {
+    let length: [u8; 4] = header[16..HEADER_SIZE - 4].try_into().expect("4 bytes");
+    u32::from_le_bytes(length) as usize
+}
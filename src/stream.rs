@@ -0,0 +1,89 @@
+//! Adapter for streams of byte chunks.
+
+use bitcoin::p2p::message::NetworkMessage;
+use bytes::{Buf, Bytes};
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use futures_core::Stream;
+
+use crate::{DecodeError, V1MessageDecoder};
+
+/// A [`Stream`] of Bitcoin V1 protocol messages decoded from a stream of [`Bytes`]
+///
+/// For transports which hand out chunks rather than implementing `AsyncRead`.
+/// Chunk boundaries do not need to line up with messages, a message may span
+/// many chunks and a chunk may hold many messages.
+///
+/// The stream ends after the first error, except for [`DecodeError::Skipped`]
+/// and a drained [`DecodeError::PayloadTooLarge`] which leave it aligned on the
+/// next message. If the inner stream ends part
+/// way through a message an [`DecodeError::IncompleteHeader`] or
+/// [`DecodeError::IncompletePayload`] is returned.
+pub struct V1MessageStream<S> {
+    inner: Option<S>,
+    decoder: V1MessageDecoder,
+    // Bytes of the last chunk which have not been fed to the decoder yet.
+    chunk: Bytes,
+}
+
+impl<S> V1MessageStream<S>
+where
+    S: Stream<Item = Bytes> + Unpin,
+{
+    /// Creates a new message stream decoding the chunks of `inner` with `decoder`
+    pub fn new(decoder: V1MessageDecoder, inner: S) -> Self {
+        Self {
+            inner: Some(inner),
+            decoder,
+            chunk: Bytes::new(),
+        }
+    }
+}
+
+impl<S> Stream for V1MessageStream<S>
+where
+    S: Stream<Item = Bytes> + Unpin,
+{
+    type Item = Result<NetworkMessage, DecodeError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        loop {
+            let inner = match &mut this.inner {
+                Some(inner) => inner,
+                None => return Poll::Ready(None),
+            };
+
+            if !this.chunk.is_empty() {
+                let mut bytes = &this.chunk[..];
+                let result = this.decoder.feed(&mut bytes);
+                let consumed = this.chunk.len() - bytes.len();
+                this.chunk.advance(consumed);
+                match result {
+                    Ok(Some(message)) => return Poll::Ready(Some(Ok(message))),
+                    Ok(None) => {}
+                    Err(e) if this.decoder.inner.is_recoverable(&e) => {
+                        return Poll::Ready(Some(Err(e)))
+                    }
+                    Err(e) => {
+                        this.inner = None;
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                }
+            }
+
+            match Pin::new(inner).poll_next(cx) {
+                Poll::Ready(Some(chunk)) => this.chunk = chunk,
+                Poll::Ready(None) => {
+                    this.inner = None;
+                    if this.decoder.inner.is_idle() {
+                        return Poll::Ready(None);
+                    }
+                    let result = this.decoder.inner.end_and_reset();
+                    return Poll::Ready(result.err().map(Err));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
@@ -0,0 +1,135 @@
+//! Decoding without buffering the payload.
+
+use bitcoin::Network;
+use push_decode::Decoder;
+
+use crate::checksum::finish;
+use crate::{Checksum, DecodeError, Header, HeaderDecoder, Sha256d, HEADER_SIZE};
+
+/// Decoder for Bitcoin V1 protocol messages which streams the payload to a sink
+///
+/// Payload bytes are handed to the sink as they arrive while the checksum is
+/// computed incrementally, so memory stays bounded no matter the announced
+/// length. The decoded value is the validated [`Header`], and since the payload
+/// is never held in full it is up to the sink to make sense of it.
+///
+/// The sink may receive the whole payload before [`Decoder::end`] reports an
+/// [`DecodeError::InvalidChecksum`], so it should not act on the bytes until
+/// decoding succeeds.
+pub struct V1StreamingDecoder<S: FnMut(&[u8])> {
+    stage: Stage<S>,
+    header_received: usize,
+}
+
+impl<S: FnMut(&[u8])> V1StreamingDecoder<S> {
+    /// Creates a new V1 streaming decoder for the specified network
+    pub fn new(network: Network, sink: S) -> Self {
+        Self {
+            stage: Stage::Header(HeaderDecoder::new(network), sink),
+            header_received: 0,
+        }
+    }
+}
+
+/// Stages of decoding a streamed V1 message.
+enum Stage<S> {
+    Header(HeaderDecoder, S),
+    Payload(StreamingPayloadDecoder<S>),
+    // The header failed validation and decoding can not continue.
+    Errored,
+}
+
+impl<S: FnMut(&[u8])> Decoder for V1StreamingDecoder<S> {
+    type Value = Header;
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        if let Stage::Header(decoder, _) = &mut self.stage {
+            let len = bytes.len();
+            decoder.decode_chunk(bytes)?;
+            // Move on as soon as the header is complete so it is checked
+            // without waiting for further bytes.
+            self.header_received += len - bytes.len();
+            if self.header_received < HEADER_SIZE {
+                return Ok(());
+            }
+            if let Stage::Header(decoder, sink) =
+                core::mem::replace(&mut self.stage, Stage::Errored)
+            {
+                let header = decoder.end()?;
+                self.stage = Stage::Payload(StreamingPayloadDecoder::new(header, sink));
+            }
+        }
+
+        match &mut self.stage {
+            Stage::Payload(decoder) => decoder.decode_chunk(bytes),
+            _ => panic!("Decoder::decode_chunk called after it already returned an error"),
+        }
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        match self.stage {
+            // Input ending before any byte is a clean close, not a cut off header.
+            Stage::Header(..) if self.header_received == 0 => Err(DecodeError::EndOfStream),
+            Stage::Header(decoder, sink) => {
+                let header = decoder.end()?;
+                StreamingPayloadDecoder::new(header, sink).end()
+            }
+            Stage::Payload(decoder) => decoder.end(),
+            Stage::Errored => {
+                panic!("Decoder::end called after Decoder::decode_chunk already returned an error")
+            }
+        }
+    }
+}
+
+/// Decoder which hashes and forwards payload bytes as they arrive.
+struct StreamingPayloadDecoder<S> {
+    header: Header,
+    remaining: usize,
+    engine: <Sha256d as Checksum>::Engine,
+    sink: S,
+}
+
+impl<S: FnMut(&[u8])> StreamingPayloadDecoder<S> {
+    fn new(header: Header, sink: S) -> Self {
+        Self {
+            remaining: header.length as usize,
+            header,
+            engine: Sha256d::engine(),
+            sink,
+        }
+    }
+}
+
+impl<S: FnMut(&[u8])> Decoder for StreamingPayloadDecoder<S> {
+    type Value = Header;
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        let (chunk, rest) = bytes.split_at(bytes.len().min(self.remaining));
+        Sha256d::input(&mut self.engine, chunk);
+        (self.sink)(chunk);
+        self.remaining -= chunk.len();
+        *bytes = rest;
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        if self.remaining > 0 {
+            return Err(DecodeError::IncompletePayload);
+        }
+
+        // Validate checksum
+        let computed = finish::<Sha256d>(self.engine);
+        if computed != self.header.checksum {
+            return Err(DecodeError::InvalidChecksum {
+                command: self.header.command,
+                expected: self.header.checksum,
+                computed,
+            });
+        }
+
+        Ok(self.header)
+    }
+}
@@ -0,0 +1,267 @@
+//! In-memory transport for testing code which talks to peers.
+//!
+//! [`duplex`] returns two connected [`MemoryStream`]s, bytes written to one are
+//! read from the other. The streams implement the [`std::io`] traits used by
+//! [`decode_sync`](crate::DecoderExt::decode_sync) and
+//! [`encode_sync`](crate::EncoderExt::encode_sync), and the tokio
+//! equivalents when the `tokio` feature is also enabled.
+//!
+//! [`V2LengthDecoder`] exposes the length layer of the v2 transport on its own,
+//! for checking it against the BIP-324 test vectors.
+//!
+//! As with a socket, the [`push_decode`] drivers only return a message once the
+//! byte after it, or end of file, has been read. A test which waits for a reply
+//! before sending more should [`close`](MemoryStream::close) its end first.
+//!
+//! [`push_decode`]: https://docs.rs/push_decode
+//!
+//! ```
+//! use bitcoin::p2p::message::NetworkMessage;
+//! use bitcoin::Network;
+//! use bitcoin_codecs::{test_util, DecoderExt, V1MessageDecoder};
+//!
+//! let (mut peer, mut local) = test_util::duplex();
+//! peer.push_messages(Network::Bitcoin, &[NetworkMessage::Ping(7)]).unwrap();
+//! peer.close();
+//! let message = V1MessageDecoder::new(Network::Bitcoin)
+//!     .decode_sync(&mut local)
+//!     .unwrap();
+//! assert_eq!(message, NetworkMessage::Ping(7));
+//! ```
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, Read, Write};
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::Network;
+
+use crate::{EncoderExt, V1MessageEncoder};
+
+pub use crate::v2::V2LengthDecoder;
+
+/// Returns two connected in-memory streams.
+pub fn duplex() -> (MemoryStream, MemoryStream) {
+    let a = Arc::new(Pipe::default());
+    let b = Arc::new(Pipe::default());
+    (
+        MemoryStream::new(Arc::clone(&a), Arc::clone(&b)),
+        MemoryStream::new(b, a),
+    )
+}
+
+/// One end of an in-memory connection created by [`duplex`].
+///
+/// Writes never block since the pipe is unbounded. Reads block, or return
+/// pending, until the other end writes or is closed. Once the other end is
+/// dropped or [`close`](Self::close)d, buffered bytes can still be read and
+/// then reads return end of file. Writing after the other end is dropped fails
+/// with [`io::ErrorKind::BrokenPipe`].
+pub struct MemoryStream {
+    read: Arc<Pipe>,
+    write: Arc<Pipe>,
+    // Bytes taken from the read pipe but not yet consumed.
+    buffer: Vec<u8>,
+    pos: usize,
+}
+
+impl MemoryStream {
+    fn new(read: Arc<Pipe>, write: Arc<Pipe>) -> Self {
+        Self {
+            read,
+            write,
+            buffer: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Encodes each message as a V1 frame and writes it to the other end.
+    pub fn push_messages<'a, I>(&mut self, network: Network, messages: I) -> io::Result<()>
+    where
+        I: IntoIterator<Item = &'a NetworkMessage>,
+    {
+        for message in messages {
+            V1MessageEncoder::new(network, message).encode_sync(self)?;
+        }
+        Ok(())
+    }
+
+    /// Closes the write half, the other end reads end of file once drained.
+    pub fn close(&mut self) {
+        self.write.close();
+    }
+
+    /// Returns the number of bytes waiting to be read by this end.
+    pub fn available(&self) -> usize {
+        self.buffer.len() - self.pos + self.read.lock().data.len()
+    }
+
+    // Moves everything in the pipe into the local buffer.
+    fn refill(&mut self, state: &mut State) {
+        self.buffer.clear();
+        self.buffer.extend(state.data.drain(..));
+        self.pos = 0;
+    }
+}
+
+impl Drop for MemoryStream {
+    fn drop(&mut self) {
+        // Writes from the other end fail once nothing can read them.
+        self.read.close();
+        self.write.close();
+    }
+}
+
+impl Read for MemoryStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let available = self.fill_buf()?;
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl BufRead for MemoryStream {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.pos == self.buffer.len() {
+            let read = Arc::clone(&self.read);
+            let mut state = read.lock();
+            while state.data.is_empty() && !state.closed {
+                state = read.ready.wait(state).unwrap_or_else(|e| e.into_inner());
+            }
+            self.refill(&mut state);
+        }
+        Ok(&self.buffer[self.pos..])
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.buffer.len());
+    }
+}
+
+impl Write for MemoryStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write.push(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tokio")]
+mod tokio_impls {
+    use core::pin::Pin;
+    use core::task::{Context, Poll};
+    use std::io;
+    use std::sync::Arc;
+
+    use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, ReadBuf};
+
+    use super::MemoryStream;
+
+    impl AsyncRead for MemoryStream {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<io::Result<()>> {
+            let available = match self.as_mut().poll_fill_buf(cx) {
+                Poll::Ready(Ok(available)) => available,
+                Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                Poll::Pending => return Poll::Pending,
+            };
+            let len = available.len().min(buf.remaining());
+            buf.put_slice(&available[..len]);
+            self.consume(len);
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl AsyncBufRead for MemoryStream {
+        fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+            let this = self.get_mut();
+            if this.pos == this.buffer.len() {
+                let read = Arc::clone(&this.read);
+                let mut state = read.lock();
+                if state.data.is_empty() && !state.closed {
+                    state.waker = Some(cx.waker().clone());
+                    return Poll::Pending;
+                }
+                this.refill(&mut state);
+            }
+            Poll::Ready(Ok(&this.buffer[this.pos..]))
+        }
+
+        fn consume(self: Pin<&mut Self>, amt: usize) {
+            std::io::BufRead::consume(self.get_mut(), amt);
+        }
+    }
+
+    impl AsyncWrite for MemoryStream {
+        fn poll_write(
+            self: Pin<&mut Self>,
+            _cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<io::Result<usize>> {
+            Poll::Ready(self.write.push(buf))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+            self.get_mut().close();
+            Poll::Ready(Ok(()))
+        }
+    }
+}
+
+// One direction of the connection.
+#[derive(Default)]
+struct Pipe {
+    state: Mutex<State>,
+    // Signalled for blocked synchronous readers.
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct State {
+    data: VecDeque<u8>,
+    closed: bool,
+    // Set by a pending asynchronous reader.
+    waker: Option<core::task::Waker>,
+}
+
+impl Pipe {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn push(&self, buf: &[u8]) -> io::Result<usize> {
+        let mut state = self.lock();
+        if state.closed {
+            return Err(io::ErrorKind::BrokenPipe.into());
+        }
+        state.data.extend(buf);
+        self.wake(state);
+        Ok(buf.len())
+    }
+
+    fn close(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+        self.wake(state);
+    }
+
+    fn wake(&self, mut state: MutexGuard<'_, State>) {
+        let waker = state.waker.take();
+        drop(state);
+        self.ready.notify_all();
+        if let Some(waker) = waker {
+            waker.wake();
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Timing how long messages take to arrive.
+
+use core::time::Duration;
+use std::collections::BTreeMap;
+
+use push_decode::Decoder;
+
+use crate::MessageCommand;
+
+/// Timings of the messages of a single command.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CommandTimings {
+    /// Number of messages timed.
+    pub messages: u64,
+    /// Quickest message.
+    pub min: Duration,
+    /// Slowest message.
+    pub max: Duration,
+    /// Time taken by all the messages together.
+    pub total: Duration,
+}
+
+impl CommandTimings {
+    /// Returns the mean time a message took, zero if none were timed.
+    pub fn average(&self) -> Duration {
+        if self.messages == 0 {
+            return Duration::ZERO;
+        }
+        let nanos = self.total.as_nanos() / u128::from(self.messages);
+        Duration::from_nanos(nanos as u64)
+    }
+
+    fn record(&mut self, elapsed: Duration) {
+        if self.messages == 0 || elapsed < self.min {
+            self.min = elapsed;
+        }
+        self.max = self.max.max(elapsed);
+        self.total += elapsed;
+        self.messages += 1;
+    }
+}
+
+/// Timings collected by a [`TimedDecoder`].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DecodeTimings {
+    /// Timings of successfully decoded messages keyed by command.
+    pub commands: BTreeMap<String, CommandTimings>,
+}
+
+/// Decoder wrapper which times each message from its first byte to its end
+///
+/// The crate is sans-io so it has no clock. Instead `clock` is called for the
+/// current time, as a duration since any fixed point the caller likes, when
+/// the first byte of a message is fed and again when it is complete. Wrapping
+/// `Instant::elapsed` of an instant taken at startup is the usual choice. The
+/// difference is recorded against the message's command, so a slow command
+/// stands out in the [`snapshot`](Self::snapshot). Messages which fail to
+/// decode are not recorded.
+///
+/// The time covers waiting on the peer as well as decoding, which is what a
+/// latency spike looks like from the outside. Like
+/// [`MeteredDecoder`](crate::MeteredDecoder) this wraps a reusable decoder,
+/// V1 or V2, and implements [`Decoder`] for `&mut TimedDecoder`.
+pub struct TimedDecoder<D, F> {
+    inner: D,
+    clock: F,
+    timings: DecodeTimings,
+    // When the first byte of the current message was fed.
+    started: Option<Duration>,
+}
+
+impl<D, F: FnMut() -> Duration> TimedDecoder<D, F> {
+    /// Wraps `inner`, reading the time from `clock`
+    pub fn new(inner: D, clock: F) -> Self {
+        Self {
+            inner,
+            clock,
+            timings: DecodeTimings::default(),
+            started: None,
+        }
+    }
+
+    /// Returns a copy of the timings collected so far.
+    pub fn snapshot(&self) -> DecodeTimings {
+        self.timings.clone()
+    }
+
+    /// Returns the wrapped decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<D, V, E, F> Decoder for &mut TimedDecoder<D, F>
+where
+    for<'a> &'a mut D: Decoder<Value = V, Error = E>,
+    V: MessageCommand,
+    F: FnMut() -> Duration,
+{
+    type Value = V;
+    type Error = E;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        if self.started.is_none() && !bytes.is_empty() {
+            self.started = Some((self.clock)());
+        }
+        (&mut self.inner).decode_chunk(bytes)
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        let started = self.started.take();
+        let value = (&mut self.inner).end()?;
+        if let Some(started) = started {
+            let elapsed = (self.clock)().saturating_sub(started);
+            self.timings
+                .commands
+                .entry(value.message_command().to_string())
+                .or_default()
+                .record(elapsed);
+        }
+        Ok(value)
+    }
+}
@@ -0,0 +1,36 @@
+//! Reporting decode activity as `tracing` events.
+//!
+//! Events carry the command and announced length of each message, never its
+//! payload, so they are safe to ship to a shared log pipeline.
+
+use crate::{DecodeError, Header};
+
+const TARGET: &str = "bitcoin_codecs";
+
+/// A header passed its checks and its payload is next.
+pub(crate) fn header(header: &Header) {
+    tracing::trace!(
+        target: TARGET,
+        command = %header.command,
+        length = header.length,
+        "header decoded"
+    );
+}
+
+/// A payload was checksummed and deserialized.
+pub(crate) fn payload(header: &Header) {
+    tracing::debug!(
+        target: TARGET,
+        command = %header.command,
+        length = header.length,
+        "message decoded"
+    );
+}
+
+/// Decoding failed, other than by the stream ending between messages.
+pub(crate) fn error(error: &DecodeError) {
+    if matches!(error, DecodeError::EndOfStream) {
+        return;
+    }
+    tracing::debug!(target: TARGET, kind = error.kind().as_str(), %error, "decode failed");
+}
@@ -0,0 +1,252 @@
+//! Tracking the features a peer negotiates around the version handshake.
+
+use bitcoin::p2p::{
+    message::{CommandString, NetworkMessage},
+    message_compact_blocks::SendCmpct,
+    message_network::VersionMessage,
+    ServiceFlags,
+};
+use push_decode::Decoder;
+
+use crate::DecodeError;
+
+/// What a peer announced about itself and the features it opted in to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerCapabilities {
+    /// Protocol version from the peer's `version` message.
+    pub version: u32,
+    /// Services the peer offers.
+    pub services: ServiceFlags,
+    /// The peer's user agent.
+    pub user_agent: String,
+    /// Height of the peer's best block when it connected.
+    pub start_height: i32,
+    /// Whether the peer wants transactions relayed to it.
+    pub relay: bool,
+    /// The peer announces transactions by wtxid (BIP-339).
+    pub wtxid_relay: bool,
+    /// The peer accepts `addrv2` messages (BIP-155).
+    pub send_addr_v2: bool,
+    /// The peer prefers block announcements via `headers` (BIP-130).
+    pub send_headers: bool,
+    /// The peer's minimum fee rate for relayed transactions (BIP-133).
+    pub fee_filter: Option<i64>,
+    /// The peer's latest compact block preference (BIP-152).
+    pub send_cmpct: Option<SendCmpct>,
+}
+
+impl PeerCapabilities {
+    fn new(version: &VersionMessage) -> Self {
+        Self {
+            version: version.version,
+            services: version.services,
+            user_agent: version.user_agent.clone(),
+            start_height: version.start_height,
+            relay: version.relay,
+            wtxid_relay: false,
+            send_addr_v2: false,
+            send_headers: false,
+            fee_filter: None,
+            send_cmpct: None,
+        }
+    }
+}
+
+/// A message which arrived out of the order the handshake allows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HandshakeViolation {
+    /// A message other than `version` arrived before it.
+    BeforeVersion(CommandString),
+    /// A second `version` arrived.
+    DuplicateVersion,
+    /// A second `verack` arrived.
+    DuplicateVerack,
+    /// `wtxidrelay` or `sendaddrv2` arrived after `verack`, which BIP-339 and
+    /// BIP-155 forbid.
+    AfterVerack(CommandString),
+}
+
+impl core::fmt::Display for HandshakeViolation {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            HandshakeViolation::BeforeVersion(command) => {
+                write!(f, "{command} received before version")
+            }
+            HandshakeViolation::DuplicateVersion => write!(f, "version received twice"),
+            HandshakeViolation::DuplicateVerack => write!(f, "verack received twice"),
+            HandshakeViolation::AfterVerack(command) => {
+                write!(f, "{command} received after verack")
+            }
+        }
+    }
+}
+
+impl core::error::Error for HandshakeViolation {}
+
+/// Records the capabilities a peer negotiates from its decoded messages
+///
+/// Every message received from the peer is passed to
+/// [`observe`](Self::observe), which hands out the capabilities once the peer's
+/// `version` and `verack` have both arrived. Feature messages such as
+/// `sendheaders` commonly come after the handshake, they keep updating the
+/// [`capabilities`](Self::capabilities). Repeated `version` messages are
+/// ignored, as are `wtxidrelay` and `sendaddrv2` after `verack` since BIP-339
+/// and BIP-155 only allow them during the handshake.
+///
+/// A peer which sends them out of order is misbehaving, and implementations
+/// are expected to disconnect it. [`check`](Self::check) reports those
+/// messages so the caller can, while [`observe`](Self::observe) keeps its
+/// lenient behavior for callers who would rather carry on.
+#[derive(Clone, Debug, Default)]
+pub struct HandshakeTracker {
+    capabilities: Option<PeerCapabilities>,
+    verack: bool,
+}
+
+impl HandshakeTracker {
+    /// Creates a tracker for a new connection
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records `message`, returning the capabilities when it completes the handshake.
+    pub fn observe(&mut self, message: &NetworkMessage) -> Option<PeerCapabilities> {
+        if let NetworkMessage::Version(version) = message {
+            if self.capabilities.is_none() {
+                self.capabilities = Some(PeerCapabilities::new(version));
+            }
+            return None;
+        }
+
+        let capabilities = self.capabilities.as_mut()?;
+        match message {
+            NetworkMessage::WtxidRelay | NetworkMessage::SendAddrV2 if self.verack => {}
+            NetworkMessage::Verack if !self.verack => {
+                self.verack = true;
+                return Some(capabilities.clone());
+            }
+            NetworkMessage::WtxidRelay => capabilities.wtxid_relay = true,
+            NetworkMessage::SendAddrV2 => capabilities.send_addr_v2 = true,
+            NetworkMessage::SendHeaders => capabilities.send_headers = true,
+            NetworkMessage::FeeFilter(fee) => capabilities.fee_filter = Some(*fee),
+            NetworkMessage::SendCmpct(send_cmpct) => capabilities.send_cmpct = Some(*send_cmpct),
+            _ => {}
+        }
+        None
+    }
+
+    /// Checks that `message` is allowed next in the handshake
+    ///
+    /// Call this before [`observe`](Self::observe) with each message, the
+    /// tracker itself is not changed. Before `version` nothing else may be
+    /// sent, and `version` and `verack` may only be sent once. The empty
+    /// `wtxidrelay` and `sendaddrv2` messages must come between `version` and
+    /// `verack`.
+    pub fn check(&self, message: &NetworkMessage) -> Result<(), HandshakeViolation> {
+        match message {
+            NetworkMessage::Version(_) if self.capabilities.is_some() => {
+                Err(HandshakeViolation::DuplicateVersion)
+            }
+            NetworkMessage::Version(_) => Ok(()),
+            _ if self.capabilities.is_none() => {
+                Err(HandshakeViolation::BeforeVersion(message.command()))
+            }
+            NetworkMessage::Verack if self.verack => Err(HandshakeViolation::DuplicateVerack),
+            NetworkMessage::WtxidRelay | NetworkMessage::SendAddrV2 if self.verack => {
+                Err(HandshakeViolation::AfterVerack(message.command()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns whether both `version` and `verack` have been received.
+    pub fn is_complete(&self) -> bool {
+        self.verack
+    }
+
+    /// Returns the capabilities recorded so far, once the handshake is complete.
+    pub fn capabilities(&self) -> Option<&PeerCapabilities> {
+        if self.verack {
+            self.capabilities.as_ref()
+        } else {
+            None
+        }
+    }
+}
+
+/// Decoder wrapper which holds a peer to the order of the version handshake
+///
+/// Until the handshake is complete only the messages which negotiate it are
+/// let through: `version` first, then `wtxidrelay`, `sendaddrv2` and BIP-330's
+/// `sendtxrcncl` in any order, then `verack`. As for
+/// [`HandshakeTracker::check`] neither `version` nor `verack` may be repeated.
+/// Anything else, such as a `getdata` before
+/// `verack`, fails with [`DecodeError::UnexpectedDuringHandshake`] and the peer
+/// is best disconnected. Once `verack` has arrived every message is handed
+/// through from the inner decoder unchecked.
+///
+/// Like [`MeteredDecoder`](crate::MeteredDecoder) this wraps a reusable
+/// decoder, V1 or V2, and implements [`Decoder`] for `&mut HandshakeDecoder`.
+pub struct HandshakeDecoder<D> {
+    inner: D,
+    tracker: HandshakeTracker,
+}
+
+impl<D> HandshakeDecoder<D> {
+    /// Wraps `inner` for a connection whose handshake has yet to start
+    pub fn new(inner: D) -> Self {
+        Self {
+            inner,
+            tracker: HandshakeTracker::new(),
+        }
+    }
+
+    /// Returns whether both `version` and `verack` have been received.
+    pub fn is_complete(&self) -> bool {
+        self.tracker.is_complete()
+    }
+
+    /// Returns the capabilities the peer negotiated, once the handshake is complete.
+    pub fn capabilities(&self) -> Option<&PeerCapabilities> {
+        self.tracker.capabilities()
+    }
+
+    /// Returns the wrapped decoder.
+    pub fn into_inner(self) -> D {
+        self.inner
+    }
+}
+
+impl<D> Decoder for &mut HandshakeDecoder<D>
+where
+    for<'a> &'a mut D: Decoder<Value = NetworkMessage, Error = DecodeError>,
+{
+    type Value = NetworkMessage;
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        (&mut self.inner).decode_chunk(bytes)
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        let message = (&mut self.inner).end()?;
+        if self.tracker.is_complete() {
+            return Ok(message);
+        }
+        let negotiates = match &message {
+            NetworkMessage::Version(_)
+            | NetworkMessage::Verack
+            | NetworkMessage::WtxidRelay
+            | NetworkMessage::SendAddrV2 => true,
+            NetworkMessage::Unknown { command, .. } => command.as_ref() == "sendtxrcncl",
+            _ => false,
+        };
+        if !negotiates || self.tracker.check(&message).is_err() {
+            return Err(DecodeError::UnexpectedDuringHandshake {
+                command: message.command(),
+            });
+        }
+        self.tracker.observe(&message);
+        Ok(message)
+    }
+}
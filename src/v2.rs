@@ -0,0 +1,541 @@
+//! BIP-324 v2 transport handshake.
+
+mod cipher;
+mod length;
+
+use bitcoin::{
+    consensus::encode,
+    hashes::{hmac, sha256, Hash, HashEngine},
+    p2p::message::{CommandString, NetworkMessage},
+    secp256k1::{
+        ellswift::{ElligatorSwift, ElligatorSwiftParty},
+        Secp256k1, SecretKey,
+    },
+    Network,
+};
+use push_decode::{decoders::ByteArrayDecoder, encoders::BytesEncoder, Decoder};
+
+use crate::{deserialize_payload, parse_command, DecodeError};
+use cipher::{LengthCipher, PacketCipher, LENGTH_LEN, TAG_LEN};
+pub use length::V2LengthDecoder;
+
+/// Maximum number of garbage bytes which may follow the public key.
+pub const MAX_GARBAGE_LEN: usize = 4095;
+
+/// Length of an ElligatorSwift encoded public key.
+const ELLSWIFT_LEN: usize = 64;
+
+/// Which side of the connection the handshake is driven from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    /// The side which opened the connection.
+    Initiator,
+    /// The side which accepted the connection.
+    Responder,
+}
+
+/// Secret material shared by both peers once the handshake completes.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SessionKeys {
+    /// Identifier of the session, equal on both sides.
+    pub session_id: [u8; 32],
+    /// Key for the length cipher of packets sent by the initiator.
+    pub initiator_length_key: [u8; 32],
+    /// Key for the packet cipher of packets sent by the initiator.
+    pub initiator_packet_key: [u8; 32],
+    /// Key for the length cipher of packets sent by the responder.
+    pub responder_length_key: [u8; 32],
+    /// Key for the packet cipher of packets sent by the responder.
+    pub responder_packet_key: [u8; 32],
+    /// Terminator which ends the initiator's garbage.
+    pub initiator_garbage_terminator: [u8; 16],
+    /// Terminator which ends the responder's garbage.
+    pub responder_garbage_terminator: [u8; 16],
+}
+
+// The keys are secret, so only the session identifier is printed.
+impl core::fmt::Debug for SessionKeys {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("SessionKeys")
+            .field("session_id", &self.session_id)
+            .finish_non_exhaustive()
+    }
+}
+
+/// Key exchange of the BIP-324 v2 transport.
+///
+/// The handshake is sans-io like the rest of the crate. The caller sends the
+/// [`initial_message`](Self::initial_message) to the peer and then pushes the
+/// received bytes in to the handshake, which is a [`Decoder`] consuming exactly
+/// the peer's 64 byte public key. Any garbage the peer sent after its key is
+/// left unconsumed. Ending the decoder derives the [`SessionKeys`].
+///
+/// Generating the secret key and randomness is left to the caller so the crate
+/// does not depend on a random number generator, unless the `rand` feature is
+/// enabled for `V2Handshake::generate`.
+pub struct V2Handshake {
+    network: Network,
+    role: Role,
+    secret_key: SecretKey,
+    ours: ElligatorSwift,
+    theirs: ByteArrayDecoder<ELLSWIFT_LEN>,
+}
+
+impl V2Handshake {
+    /// Creates a new handshake with a freshly generated ephemeral key
+    ///
+    /// The key and the randomness of its encoding come from the thread local
+    /// random number generator.
+    #[cfg(feature = "rand")]
+    pub fn generate(network: Network, role: Role) -> Self {
+        use bitcoin::secp256k1::rand::{thread_rng, Rng};
+
+        let mut rng = thread_rng();
+        let secret_key = SecretKey::new(&mut rng);
+        Self::new(network, role, secret_key, rng.gen())
+    }
+
+    /// Creates a new handshake, encoding the public key with the given randomness
+    ///
+    /// The ephemeral `secret_key` and the 32 bytes of `rand` must both be freshly
+    /// generated for each connection from a cryptographically secure source, the
+    /// handshake does not generate them. Enable the `rand` feature to have
+    /// `generate` do it instead.
+    pub fn new(network: Network, role: Role, secret_key: SecretKey, rand: [u8; 32]) -> Self {
+        Self::with_ellswift(
+            network,
+            role,
+            secret_key,
+            ElligatorSwift::from_seckey(&Secp256k1::new(), secret_key, Some(rand)).to_array(),
+        )
+    }
+
+    /// Creates a new handshake with an already encoded public key
+    ///
+    /// The encoding must belong to `secret_key`, this is mostly useful for
+    /// reproducing test vectors.
+    pub fn with_ellswift(
+        network: Network,
+        role: Role,
+        secret_key: SecretKey,
+        ellswift: [u8; ELLSWIFT_LEN],
+    ) -> Self {
+        Self {
+            network,
+            role,
+            secret_key,
+            ours: ElligatorSwift::from_array(ellswift),
+            theirs: ByteArrayDecoder::new(),
+        }
+    }
+
+    /// Returns our 64 byte ElligatorSwift encoded public key.
+    pub fn public_key(&self) -> [u8; ELLSWIFT_LEN] {
+        self.ours.to_array()
+    }
+
+    /// Returns the bytes to send to the peer: our public key followed by `garbage`.
+    pub fn initial_message(&self, garbage: &[u8]) -> Result<Vec<u8>, HandshakeError> {
+        if garbage.len() > MAX_GARBAGE_LEN {
+            return Err(HandshakeError::GarbageTooLarge {
+                length: garbage.len(),
+            });
+        }
+        let mut message = Vec::with_capacity(ELLSWIFT_LEN + garbage.len());
+        message.extend_from_slice(&self.public_key());
+        message.extend_from_slice(garbage);
+        Ok(message)
+    }
+}
+
+impl Decoder for V2Handshake {
+    type Value = SessionKeys;
+    type Error = HandshakeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        Ok(self.theirs.decode_chunk(bytes)?)
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        let theirs = self.theirs.end()?;
+        let theirs = ElligatorSwift::from_array(theirs);
+        // The ECDH hash commits to the initiator's key first.
+        let (a, b, party) = match self.role {
+            Role::Initiator => (self.ours, theirs, ElligatorSwiftParty::A),
+            Role::Responder => (theirs, self.ours, ElligatorSwiftParty::B),
+        };
+        let shared_secret = ElligatorSwift::shared_secret(
+            a,
+            b,
+            self.secret_key,
+            party,
+            Some(b"bip324_ellswift_xonly_ecdh"),
+        );
+        Ok(derive_session_keys(
+            self.network,
+            shared_secret.as_secret_bytes(),
+        ))
+    }
+}
+
+/// Expands the ECDH secret in to the session keys with HKDF-SHA256.
+fn derive_session_keys(network: Network, shared_secret: &[u8; 32]) -> SessionKeys {
+    let mut salt = b"bitcoin_v2_shared_secret".to_vec();
+    salt.extend_from_slice(&network.magic().to_bytes());
+
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(&salt);
+    engine.input(shared_secret);
+    let prk = hmac::Hmac::from_engine(engine).to_byte_array();
+
+    let garbage_terminators = hkdf_expand(&prk, b"garbage_terminators");
+    let mut initiator_garbage_terminator = [0u8; 16];
+    let mut responder_garbage_terminator = [0u8; 16];
+    initiator_garbage_terminator.copy_from_slice(&garbage_terminators[..16]);
+    responder_garbage_terminator.copy_from_slice(&garbage_terminators[16..]);
+
+    SessionKeys {
+        session_id: hkdf_expand(&prk, b"session_id"),
+        initiator_length_key: hkdf_expand(&prk, b"initiator_L"),
+        initiator_packet_key: hkdf_expand(&prk, b"initiator_P"),
+        responder_length_key: hkdf_expand(&prk, b"responder_L"),
+        responder_packet_key: hkdf_expand(&prk, b"responder_P"),
+        initiator_garbage_terminator,
+        responder_garbage_terminator,
+    }
+}
+
+/// HKDF expand step for a single 32 byte block of output.
+fn hkdf_expand(prk: &[u8; 32], info: &[u8]) -> [u8; 32] {
+    let mut engine = hmac::HmacEngine::<sha256::Hash>::new(prk);
+    engine.input(info);
+    engine.input(&[1]);
+    hmac::Hmac::from_engine(engine).to_byte_array()
+}
+
+/// Length of a garbage terminator.
+const TERMINATOR_LEN: usize = 16;
+
+/// Header bit marking a packet which must be ignored.
+const DECOY_BIT: u8 = 0x80;
+
+/// Commands with a one byte encoding, the id of each is its index plus one.
+const SHORT_IDS: [&str; 28] = [
+    "addr",
+    "block",
+    "blocktxn",
+    "cmpctblock",
+    "feefilter",
+    "filteradd",
+    "filterclear",
+    "filterload",
+    "getblocks",
+    "getblocktxn",
+    "getdata",
+    "getheaders",
+    "headers",
+    "inv",
+    "mempool",
+    "merkleblock",
+    "notfound",
+    "ping",
+    "pong",
+    "sendcmpct",
+    "tx",
+    "getcfilters",
+    "cfilter",
+    "getcfheaders",
+    "cfheaders",
+    "getcfcheckpt",
+    "cfcheckpt",
+    "addrv2",
+];
+
+/// Stages of decoding the v2 packet stream.
+enum V2Stage {
+    /// Searching for the peer's garbage terminator.
+    Garbage,
+    /// Reading the encrypted length of the next packet.
+    Length,
+    /// Reading a packet of `length` content bytes.
+    Packet { length: usize },
+}
+
+/// Decoder for Bitcoin V2 protocol messages
+///
+/// Decodes the bytes the peer sends after its public key, which the
+/// [`V2Handshake`] leaves unconsumed. The peer's garbage is skipped up to its
+/// terminator and the version packet which follows is checked but not returned.
+/// Decoy packets are discarded wherever they appear, so only real messages come
+/// out of the decoder.
+///
+/// The ciphers advance with every packet, so unlike [`V1MessageDecoder`] one
+/// decoder lives for the whole connection. [`Decoder`] is implemented for
+/// `&mut V2MessageDecoder` and each pass yields the next message.
+///
+/// [`V1MessageDecoder`]: crate::V1MessageDecoder
+pub struct V2MessageDecoder {
+    stage: V2Stage,
+    buf: Vec<u8>,
+    length: V2LengthDecoder,
+    packet_cipher: PacketCipher,
+    terminator: [u8; TERMINATOR_LEN],
+    // The peer's garbage, authenticated by the first packet.
+    aad: Vec<u8>,
+    version_received: bool,
+    message: Option<NetworkMessage>,
+}
+
+impl V2MessageDecoder {
+    /// Creates a decoder for the packets sent by the peer of `role`
+    pub fn new(keys: &SessionKeys, role: Role) -> Self {
+        let (length_key, packet_key, terminator) = match role {
+            Role::Initiator => (
+                keys.responder_length_key,
+                keys.responder_packet_key,
+                keys.responder_garbage_terminator,
+            ),
+            Role::Responder => (
+                keys.initiator_length_key,
+                keys.initiator_packet_key,
+                keys.initiator_garbage_terminator,
+            ),
+        };
+        Self {
+            stage: V2Stage::Garbage,
+            buf: Vec::new(),
+            length: V2LengthDecoder::new(length_key),
+            packet_cipher: PacketCipher::new(packet_key),
+            terminator,
+            aad: Vec::new(),
+            version_received: false,
+            message: None,
+        }
+    }
+
+    /// Handles a decrypted packet, keeping it if it is a real message.
+    fn packet(&mut self, contents: &[u8]) -> Result<(), DecodeError> {
+        let (header, contents) = match contents.split_first() {
+            Some(split) => split,
+            None => return Err(DecodeError::IncompletePayload),
+        };
+        if header & DECOY_BIT != 0 {
+            return Ok(());
+        }
+        // The first real packet negotiates the transport version. There are no
+        // versions beyond the first yet, so its contents are ignored.
+        if !self.version_received {
+            self.version_received = true;
+            return Ok(());
+        }
+
+        let (command, payload) = match contents.split_first() {
+            Some((0, rest)) if rest.len() >= 12 => {
+                let (command, payload) = rest.split_at(12);
+                let command = parse_command(command.try_into().expect("split at 12 bytes"))?;
+                (command, payload)
+            }
+            Some((&id, rest)) if id != 0 && usize::from(id) <= SHORT_IDS.len() => {
+                let command = CommandString::try_from_static(SHORT_IDS[usize::from(id) - 1])
+                    .expect("short ids are valid commands");
+                (command, rest)
+            }
+            _ => return Err(DecodeError::InvalidCommand),
+        };
+        let message = match deserialize_payload(&command, payload, None, false) {
+            Ok(Some((message, 0))) => message,
+            Ok(Some((_, trailing))) => return Err(DecodeError::TrailingPayloadBytes(trailing)),
+            Ok(None) => NetworkMessage::Unknown {
+                command,
+                payload: payload.to_vec(),
+            },
+            Err(e) => return Err(DecodeError::InvalidPayload(e)),
+        };
+        self.message = Some(message);
+        Ok(())
+    }
+}
+
+impl Decoder for &mut V2MessageDecoder {
+    type Value = NetworkMessage;
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        while self.message.is_none() && !bytes.is_empty() {
+            match self.stage {
+                V2Stage::Garbage => {
+                    // Bytes are taken one at a time so none past the terminator are consumed.
+                    self.buf.push(bytes[0]);
+                    *bytes = &bytes[1..];
+                    if self.buf.ends_with(&self.terminator) {
+                        self.buf.truncate(self.buf.len() - TERMINATOR_LEN);
+                        self.aad = core::mem::take(&mut self.buf);
+                        self.stage = V2Stage::Length;
+                    } else if self.buf.len() >= MAX_GARBAGE_LEN + TERMINATOR_LEN {
+                        return Err(DecodeError::NoGarbageTerminator);
+                    }
+                }
+                V2Stage::Length => {
+                    (&mut self.length).decode_chunk(bytes)?;
+                    if self.length.is_complete() {
+                        let length = (&mut self.length).end()?;
+                        self.stage = V2Stage::Packet {
+                            length: length as usize,
+                        };
+                    }
+                }
+                V2Stage::Packet { length } => {
+                    // The header byte and tag surround the contents.
+                    if fill(&mut self.buf, 1 + length + TAG_LEN, bytes) {
+                        let mut packet = core::mem::take(&mut self.buf);
+                        let mut tag = [0u8; TAG_LEN];
+                        tag.copy_from_slice(&packet[1 + length..]);
+                        packet.truncate(1 + length);
+                        let aad = core::mem::take(&mut self.aad);
+                        if !self.packet_cipher.decrypt(&aad, &mut packet, tag) {
+                            return Err(DecodeError::DecryptionFailed);
+                        }
+                        self.stage = V2Stage::Length;
+                        self.packet(&packet)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        match (self.message.take(), &self.stage) {
+            (Some(message), _) => Ok(message),
+            (None, V2Stage::Packet { .. }) => Err(DecodeError::IncompletePayload),
+            (None, V2Stage::Length) if self.length.is_idle() => Err(DecodeError::EndOfStream),
+            // The garbage and encrypted length come before any packet contents.
+            (None, _) => Err(DecodeError::IncompleteHeader),
+        }
+    }
+}
+
+/// Moves bytes in to `buf` until it holds `len` of them, returns whether it does.
+fn fill(buf: &mut Vec<u8>, len: usize, bytes: &mut &[u8]) -> bool {
+    let take = (len - buf.len()).min(bytes.len());
+    buf.extend_from_slice(&bytes[..take]);
+    *bytes = &bytes[take..];
+    buf.len() == len
+}
+
+/// Encoder for Bitcoin V2 protocol messages
+///
+/// The counterpart of [`V2MessageDecoder`], it lives for the whole connection
+/// and each call encrypts the next packet. Our garbage terminator is sent ahead
+/// of the first packet and the version packet ahead of the first message.
+pub struct V2MessageEncoder {
+    length_cipher: LengthCipher,
+    packet_cipher: PacketCipher,
+    // Sent and cleared by the first packet.
+    prefix: Vec<u8>,
+    aad: Vec<u8>,
+    version_sent: bool,
+}
+
+impl V2MessageEncoder {
+    /// Creates an encoder for the packets we send as `role`
+    ///
+    /// The `garbage` must be what followed our public key in the
+    /// [`V2Handshake::initial_message`], it is authenticated by the first packet.
+    pub fn new(keys: &SessionKeys, role: Role, garbage: &[u8]) -> Self {
+        let (length_key, packet_key, terminator) = match role {
+            Role::Initiator => (
+                keys.initiator_length_key,
+                keys.initiator_packet_key,
+                keys.initiator_garbage_terminator,
+            ),
+            Role::Responder => (
+                keys.responder_length_key,
+                keys.responder_packet_key,
+                keys.responder_garbage_terminator,
+            ),
+        };
+        Self {
+            length_cipher: LengthCipher::new(length_key),
+            packet_cipher: PacketCipher::new(packet_key),
+            prefix: terminator.to_vec(),
+            aad: garbage.to_vec(),
+            version_sent: false,
+        }
+    }
+
+    /// Encrypts a message in to a packet
+    pub fn encode(&mut self, message: &NetworkMessage) -> BytesEncoder<Vec<u8>> {
+        let command = message.command();
+        let mut contents = match SHORT_IDS.iter().position(|id| *id == command.as_ref()) {
+            Some(index) => vec![index as u8 + 1],
+            None => {
+                let mut contents = vec![0];
+                contents.extend_from_slice(&encode::serialize(&command));
+                contents
+            }
+        };
+        contents.extend_from_slice(&encode::serialize(message));
+
+        let mut bytes = Vec::new();
+        if !self.version_sent {
+            self.version_sent = true;
+            self.packet(0, &[], &mut bytes);
+        }
+        self.packet(0, &contents, &mut bytes);
+        BytesEncoder::new(bytes)
+    }
+
+    /// Encrypts `length` zero bytes in to a decoy packet which the peer ignores
+    pub fn encode_decoy(&mut self, length: usize) -> BytesEncoder<Vec<u8>> {
+        let mut bytes = Vec::new();
+        self.packet(DECOY_BIT, &vec![0; length], &mut bytes);
+        BytesEncoder::new(bytes)
+    }
+
+    fn packet(&mut self, header: u8, contents: &[u8], out: &mut Vec<u8>) {
+        out.append(&mut self.prefix);
+
+        let mut length = [0u8; LENGTH_LEN];
+        length.copy_from_slice(&(contents.len() as u32).to_le_bytes()[..LENGTH_LEN]);
+        self.length_cipher.crypt(&mut length);
+        out.extend_from_slice(&length);
+
+        let start = out.len();
+        out.push(header);
+        out.extend_from_slice(contents);
+        let aad = core::mem::take(&mut self.aad);
+        let tag = self.packet_cipher.encrypt(&aad, &mut out[start..]);
+        out.extend_from_slice(&tag);
+    }
+}
+
+/// Errors that can occur during the v2 handshake.
+#[derive(Debug)]
+pub enum HandshakeError {
+    /// Garbage is longer than [`MAX_GARBAGE_LEN`].
+    GarbageTooLarge { length: usize },
+    /// Input ended before the peer's full public key was received.
+    IncompleteKey,
+}
+
+impl core::fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            HandshakeError::GarbageTooLarge { length } => {
+                write!(
+                    f,
+                    "garbage too large: {length} bytes, maximum is {MAX_GARBAGE_LEN} bytes"
+                )
+            }
+            HandshakeError::IncompleteKey => write!(f, "incomplete public key"),
+        }
+    }
+}
+
+impl From<push_decode::error::UnexpectedEnd> for HandshakeError {
+    fn from(_: push_decode::error::UnexpectedEnd) -> Self {
+        HandshakeError::IncompleteKey
+    }
+}
+
+impl core::error::Error for HandshakeError {}
@@ -0,0 +1,112 @@
+//! Forward secure ciphers of the v2 transport.
+//!
+//! Both ciphers change their key every [`REKEY_INTERVAL`] messages so that a
+//! leaked key does not expose earlier traffic.
+
+use chacha20_poly1305::{chacha20::ChaCha20, ChaCha20Poly1305, Key, Nonce};
+
+/// Number of messages encrypted under a key before it is replaced.
+const REKEY_INTERVAL: u32 = 224;
+
+/// Length of the encrypted packet length prefix.
+pub(crate) const LENGTH_LEN: usize = 3;
+
+/// Length of the authentication tag which ends a packet.
+pub(crate) const TAG_LEN: usize = 16;
+
+/// ChaCha20 keystream for the 3 byte packet lengths.
+///
+/// Lengths are not authenticated on their own, tampering is caught by the packet
+/// cipher since the length determines which bytes it authenticates.
+pub(crate) struct LengthCipher {
+    key: Key,
+    chunk_counter: u32,
+    // Offset in to the keystream of the current key.
+    offset: u32,
+}
+
+impl LengthCipher {
+    pub(crate) fn new(key: [u8; 32]) -> Self {
+        Self {
+            key: Key::new(key),
+            chunk_counter: 0,
+            offset: 0,
+        }
+    }
+
+    /// Encrypts or decrypts a length in place.
+    pub(crate) fn crypt(&mut self, length: &mut [u8; LENGTH_LEN]) {
+        let mut nonce = [0u8; 12];
+        nonce[4..8].copy_from_slice(&(self.chunk_counter / REKEY_INTERVAL).to_le_bytes());
+        let mut cipher = ChaCha20::new(self.key, Nonce::new(nonce), 0);
+        cipher.seek(self.offset);
+        cipher.apply_keystream(length);
+        self.offset += LENGTH_LEN as u32;
+
+        // The next key is the keystream which follows the last length.
+        if (self.chunk_counter + 1) % REKEY_INTERVAL == 0 {
+            let mut key = [0u8; 32];
+            cipher.seek(self.offset);
+            cipher.apply_keystream(&mut key);
+            self.key = Key::new(key);
+            self.offset = 0;
+        }
+        self.chunk_counter += 1;
+    }
+}
+
+/// ChaCha20Poly1305 AEAD for the packet contents.
+pub(crate) struct PacketCipher {
+    key: Key,
+    message_counter: u64,
+}
+
+impl PacketCipher {
+    pub(crate) fn new(key: [u8; 32]) -> Self {
+        Self {
+            key: Key::new(key),
+            message_counter: 0,
+        }
+    }
+
+    /// Encrypts `content` in place and returns its authentication tag.
+    pub(crate) fn encrypt(&mut self, aad: &[u8], content: &mut [u8]) -> [u8; TAG_LEN] {
+        let tag = ChaCha20Poly1305::new(self.key, self.nonce()).encrypt(content, Some(aad));
+        self.advance();
+        tag
+    }
+
+    /// Decrypts `content` in place, returning `false` if the tag does not match.
+    ///
+    /// The cipher only advances on success, a failure ends the session anyway.
+    pub(crate) fn decrypt(&mut self, aad: &[u8], content: &mut [u8], tag: [u8; TAG_LEN]) -> bool {
+        let cipher = ChaCha20Poly1305::new(self.key, self.nonce());
+        if cipher.decrypt(content, tag, Some(aad)).is_err() {
+            return false;
+        }
+        self.advance();
+        true
+    }
+
+    /// Nonce of the current message: its index under the key and the number of rekeys.
+    fn nonce(&self) -> Nonce {
+        let interval = u64::from(REKEY_INTERVAL);
+        let mut nonce = [0u8; 12];
+        nonce[..4].copy_from_slice(&((self.message_counter % interval) as u32).to_le_bytes());
+        nonce[4..].copy_from_slice(&(self.message_counter / interval).to_le_bytes());
+        Nonce::new(nonce)
+    }
+
+    fn advance(&mut self) {
+        let interval = u64::from(REKEY_INTERVAL);
+        if (self.message_counter + 1) % interval == 0 {
+            // The next key is the encryption of 32 zero bytes under a reserved nonce.
+            let mut nonce = [0xff; 12];
+            nonce[4..].copy_from_slice(&(self.message_counter / interval).to_le_bytes());
+            let mut key = [0u8; 32];
+            ChaCha20Poly1305::new(self.key, Nonce::new(nonce)).encrypt(&mut key, None);
+            self.key = Key::new(key);
+        }
+        self.message_counter += 1;
+    }
+}
@@ -0,0 +1,68 @@
+//! Deobfuscating v2 packet lengths on their own.
+
+use push_decode::Decoder;
+
+use super::cipher::{LengthCipher, LENGTH_LEN};
+use crate::DecodeError;
+
+/// Decoder for the encrypted 3 byte length which starts every v2 packet
+///
+/// Only the length cipher is involved, so this layer of BIP-324 can be checked
+/// against its test vectors without decrypting any packet contents. Like the
+/// [`V2MessageDecoder`](crate::V2MessageDecoder) built on top of it the cipher
+/// advances with every length, so one decoder lives for the whole connection.
+/// [`Decoder`] is implemented for `&mut V2LengthDecoder`, each pass consumes
+/// exactly 3 bytes and yields the length of the packet contents which follow,
+/// not counting the header byte and tag.
+pub struct V2LengthDecoder {
+    cipher: LengthCipher,
+    buf: [u8; LENGTH_LEN],
+    received: usize,
+}
+
+impl V2LengthDecoder {
+    /// Creates a decoder for lengths encrypted under the peer's length `key`
+    pub fn new(key: [u8; 32]) -> Self {
+        Self {
+            cipher: LengthCipher::new(key),
+            buf: [0; LENGTH_LEN],
+            received: 0,
+        }
+    }
+
+    /// Whether all bytes of the current length have been received.
+    pub(crate) fn is_complete(&self) -> bool {
+        self.received == LENGTH_LEN
+    }
+
+    /// Whether no byte of the current length has been received yet.
+    pub(crate) fn is_idle(&self) -> bool {
+        self.received == 0
+    }
+}
+
+impl Decoder for &mut V2LengthDecoder {
+    type Value = u32;
+    type Error = DecodeError;
+
+    fn decode_chunk(&mut self, bytes: &mut &[u8]) -> Result<(), Self::Error> {
+        let take = (LENGTH_LEN - self.received).min(bytes.len());
+        self.buf[self.received..self.received + take].copy_from_slice(&bytes[..take]);
+        self.received += take;
+        *bytes = &bytes[take..];
+        Ok(())
+    }
+
+    fn end(self) -> Result<Self::Value, Self::Error> {
+        if self.is_idle() {
+            return Err(DecodeError::EndOfStream);
+        }
+        if !self.is_complete() {
+            return Err(DecodeError::IncompleteHeader);
+        }
+        let mut length = core::mem::take(&mut self.buf);
+        self.received = 0;
+        self.cipher.crypt(&mut length);
+        Ok(u32::from_le_bytes([length[0], length[1], length[2], 0]))
+    }
+}
@@ -0,0 +1,114 @@
+//! Tunnelling V1 messages through WebSocket binary frames.
+
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::Network;
+
+use crate::{DecodeAll, DecodeError, V1MessageDecoder, V1MessageEncoder};
+
+/// Adapter which decodes V1 messages carried in WebSocket binary messages
+///
+/// Tunnels do not align WebSocket messages with bitcoin frames, one binary
+/// message may hold several frames, part of one or both. Each payload is
+/// [`push`](Self::push)ed as it is received and a frame split across messages
+/// is held by the decoder until the rest arrives. The adapter takes the bytes
+/// of the binary messages rather than the types of a WebSocket library, so it
+/// fits tungstenite, a browser's `WebSocket` or anything else, and control or
+/// text messages are left to the caller.
+///
+/// ```
+/// use bitcoin::p2p::message::NetworkMessage;
+/// use bitcoin::Network;
+/// use bitcoin_codecs::V1WebSocketDecoder;
+///
+/// let binary = V1WebSocketDecoder::encode(Network::Bitcoin, &NetworkMessage::Ping(7));
+/// let mut decoder = V1WebSocketDecoder::new(Network::Bitcoin);
+/// // The frame arrives split over two WebSocket messages.
+/// assert_eq!(decoder.push(&binary[..10]).count(), 0);
+/// let messages: Vec<_> = decoder.push(&binary[10..]).collect();
+/// assert_eq!(messages, [Ok(NetworkMessage::Ping(7))]);
+/// assert_eq!(decoder.finish(), Ok(()));
+/// ```
+#[derive(Debug)]
+pub struct V1WebSocketDecoder {
+    decoder: V1MessageDecoder,
+    // Whether a push yielded an error decoding can not carry on from.
+    failed: bool,
+}
+
+impl V1WebSocketDecoder {
+    /// Creates an adapter for messages on `network`
+    pub fn new(network: Network) -> Self {
+        Self::with_decoder(V1MessageDecoder::new(network))
+    }
+
+    /// Creates an adapter around a configured `decoder`
+    pub fn with_decoder(decoder: V1MessageDecoder) -> Self {
+        Self {
+            decoder,
+            failed: false,
+        }
+    }
+
+    /// Feeds the payload of a binary message, yielding the messages it completes
+    ///
+    /// Behaves like [`V1MessageDecoder::decode_all`], a trailing partial frame
+    /// is kept for the next payload.
+    pub fn push<'a>(&'a mut self, payload: &'a [u8]) -> WebSocketMessages<'a> {
+        WebSocketMessages {
+            messages: self.decoder.decode_all(payload),
+            failed: &mut self.failed,
+        }
+    }
+
+    /// Checks that the tunnel closed between frames
+    ///
+    /// Fails with [`DecodeError::IncompleteHeader`] or
+    /// [`DecodeError::IncompletePayload`] if a frame was cut off, the decoder
+    /// is ready for a new tunnel either way. After a [`push`](Self::push)
+    /// yielded an error decoding could not carry on from this only resets, as
+    /// that error has already been reported.
+    pub fn finish(&mut self) -> Result<(), DecodeError> {
+        if core::mem::take(&mut self.failed) {
+            self.decoder.reset();
+            return Ok(());
+        }
+        match self.decoder.inner.end_and_reset() {
+            Err(DecodeError::EndOfStream) => Ok(()),
+            Err(e) => Err(e),
+            Ok(_) => unreachable!("complete messages are returned as they finish"),
+        }
+    }
+
+    /// Frames `message` for `network` as the payload of one binary message.
+    pub fn encode(network: Network, message: &NetworkMessage) -> Vec<u8> {
+        let mut payload = Vec::new();
+        V1MessageEncoder::encode_into(network, message, &mut payload);
+        payload
+    }
+
+    /// Returns the decoder, for example to [`reset`](V1MessageDecoder::reset) it after an error.
+    pub fn decoder_mut(&mut self) -> &mut V1MessageDecoder {
+        &mut self.decoder
+    }
+
+    /// Returns the wrapped decoder.
+    pub fn into_inner(self) -> V1MessageDecoder {
+        self.decoder
+    }
+}
+
+/// Iterator over the messages completed by a payload, created by [`V1WebSocketDecoder::push`].
+pub struct WebSocketMessages<'a> {
+    messages: DecodeAll<'a>,
+    failed: &'a mut bool,
+}
+
+impl Iterator for WebSocketMessages<'_> {
+    type Item = Result<NetworkMessage, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let item = self.messages.next();
+        *self.failed |= self.messages.failed;
+        item
+    }
+}
@@ -0,0 +1,93 @@
+#![cfg(feature = "bytes")]
+
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::Network;
+use bitcoin_codecs::{DecodeError, V1MessageDecoder, V1MessageEncoder};
+use bytes::BytesMut;
+
+#[test]
+fn bytes_round_trip() {
+    let mut buf = BytesMut::new();
+    V1MessageEncoder::encode_bytes(Network::Bitcoin, &NetworkMessage::Verack, &mut buf);
+    V1MessageEncoder::encode_bytes(Network::Bitcoin, &NetworkMessage::Ping(7), &mut buf);
+    let mut expected = Vec::new();
+    V1MessageEncoder::encode_into(Network::Bitcoin, &NetworkMessage::Verack, &mut expected);
+    V1MessageEncoder::encode_into(Network::Bitcoin, &NetworkMessage::Ping(7), &mut expected);
+    assert_eq!(buf[..], expected[..]);
+
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    assert_eq!(
+        decoder.decode_bytes(&mut buf).unwrap(),
+        Some(NetworkMessage::Verack)
+    );
+    assert_eq!(
+        decoder.decode_bytes(&mut buf).unwrap(),
+        Some(NetworkMessage::Ping(7))
+    );
+    assert!(buf.is_empty());
+    assert_eq!(decoder.decode_bytes(&mut buf).unwrap(), None);
+}
+
+#[test]
+fn partial_frame_leaves_buffer_untouched() {
+    let mut frame = Vec::new();
+    V1MessageEncoder::encode_into(Network::Bitcoin, &NetworkMessage::Ping(7), &mut frame);
+
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    let mut buf = BytesMut::new();
+    for i in 0..frame.len() - 1 {
+        buf.extend_from_slice(&frame[i..i + 1]);
+        assert_eq!(decoder.decode_bytes(&mut buf).unwrap(), None);
+        assert_eq!(buf[..], frame[..i + 1]);
+    }
+    buf.extend_from_slice(&frame[frame.len() - 1..]);
+    assert_eq!(
+        decoder.decode_bytes(&mut buf).unwrap(),
+        Some(NetworkMessage::Ping(7))
+    );
+
+    // A header for another network fails without waiting for its payload.
+    let mut buf = BytesMut::from(&frame[..24]);
+    let mut decoder = V1MessageDecoder::new(Network::Testnet);
+    assert!(matches!(
+        decoder.decode_bytes(&mut buf),
+        Err(DecodeError::WrongMagic { .. })
+    ));
+    assert_eq!(buf.len(), 24);
+}
+
+#[test]
+fn drained_frame_is_consumed_as_it_arrives() {
+    let mut ping = Vec::new();
+    V1MessageEncoder::encode_into(Network::Bitcoin, &NetworkMessage::Ping(7), &mut ping);
+    // A header announcing 100 bytes, over the limit of 10.
+    let mut oversized = ping[..24].to_vec();
+    oversized[4..16].copy_from_slice(b"junk\0\0\0\0\0\0\0\0");
+    oversized[16..20].copy_from_slice(&100u32.to_le_bytes());
+
+    let mut decoder = V1MessageDecoder::with_max_payload_drained(Network::Bitcoin, 10);
+    let mut buf = BytesMut::from(&oversized[..]);
+    buf.extend_from_slice(&[0; 60]);
+    assert_eq!(decoder.decode_bytes(&mut buf).unwrap(), None);
+    assert!(buf.is_empty());
+
+    buf.extend_from_slice(&[0; 40]);
+    buf.extend_from_slice(&ping);
+    assert!(matches!(
+        decoder.decode_bytes(&mut buf),
+        Err(DecodeError::PayloadTooLarge { .. })
+    ));
+    assert_eq!(buf[..], ping[..]);
+    assert_eq!(
+        decoder.decode_bytes(&mut buf).unwrap(),
+        Some(NetworkMessage::Ping(7))
+    );
+    assert!(buf.is_empty());
+
+    // Nothing is held back for the largest length a header can announce.
+    oversized[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+    let mut buf = BytesMut::from(&oversized[..]);
+    buf.extend_from_slice(&[0; 1000]);
+    assert_eq!(decoder.decode_bytes(&mut buf).unwrap(), None);
+    assert!(buf.is_empty());
+}
@@ -0,0 +1,91 @@
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::Network;
+use bitcoin_codecs::{
+    CaptureReader, CaptureWriter, DecodeError, DecoderExt, V1MessageDecoder, CAPTURE_SIGNATURE,
+};
+use push_decode::ReadError;
+
+fn capture(messages: &[NetworkMessage]) -> Vec<u8> {
+    let mut writer = CaptureWriter::new(Vec::new(), Network::Signet).unwrap();
+    for message in messages {
+        writer.write(message).unwrap();
+    }
+    writer.into_inner()
+}
+
+#[test]
+fn capture_round_trips() {
+    let messages = [
+        NetworkMessage::Verack,
+        NetworkMessage::Ping(1),
+        NetworkMessage::Pong(1),
+    ];
+    let bytes = capture(&messages);
+    assert_eq!(bytes[..8], CAPTURE_SIGNATURE);
+    assert_eq!(bytes[8..12], Network::Signet.magic().to_bytes());
+
+    let reader = CaptureReader::new(&bytes[..]).unwrap();
+    assert_eq!(reader.network(), Some(Network::Signet));
+    let replayed: Vec<_> = reader.map(Result::unwrap).collect();
+    assert_eq!(replayed, messages);
+
+    // Past the header the frames are plain V1 frames.
+    let mut frames = &bytes[12..];
+    let mut decoder = V1MessageDecoder::new(Network::Signet);
+    assert_eq!(
+        (&mut decoder).decode_sync(&mut frames).unwrap(),
+        NetworkMessage::Verack
+    );
+}
+
+#[test]
+fn truncated_capture_ends_with_error() {
+    let bytes = capture(&[NetworkMessage::Ping(1), NetworkMessage::Ping(2)]);
+    let mut reader = CaptureReader::new(&bytes[..bytes.len() - 1]).unwrap();
+    assert_eq!(reader.next().unwrap().unwrap(), NetworkMessage::Ping(1));
+    assert!(matches!(
+        reader.next(),
+        Some(Err(ReadError::Decode(DecodeError::IncompletePayload)))
+    ));
+    assert!(reader.next().is_none());
+}
+
+#[test]
+fn non_capture_is_rejected() {
+    let mut bytes = capture(&[]);
+    bytes[0] ^= 0xff;
+    let error = CaptureReader::new(&bytes[..]).err().unwrap();
+    assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn gzip_capture_is_replayed() {
+    use std::io::Write;
+
+    let messages = [NetworkMessage::Verack, NetworkMessage::Ping(1)];
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(&capture(&messages)).unwrap();
+    let compressed = encoder.finish().unwrap();
+
+    let reader = CaptureReader::from_gzip(&compressed[..]).unwrap();
+    assert_eq!(reader.network(), Some(Network::Signet));
+    let replayed: Vec<_> = reader.map(Result::unwrap).collect();
+    assert_eq!(replayed, messages);
+
+    // A capture which was never compressed is not mistaken for one.
+    assert!(CaptureReader::from_gzip(&capture(&messages)[..]).is_err());
+}
+
+#[cfg(feature = "zstd")]
+#[test]
+fn zstd_capture_is_replayed() {
+    let messages = [NetworkMessage::Verack, NetworkMessage::Ping(1)];
+    let compressed = zstd::encode_all(&capture(&messages)[..], 0).unwrap();
+
+    let reader = CaptureReader::from_zstd(&compressed[..]).unwrap();
+    let replayed: Vec<_> = reader.map(Result::unwrap).collect();
+    assert_eq!(replayed, messages);
+
+    assert!(CaptureReader::from_zstd(&capture(&messages)[..]).is_err());
+}
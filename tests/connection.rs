@@ -0,0 +1,175 @@
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::Network;
+use bitcoin_codecs::{DecodeError, EncoderExt, V1Connection, V1MessageEncoder};
+use push_decode::ReadError;
+
+#[test]
+fn connection_sends_and_receives() {
+    let mut incoming = Vec::new();
+    for message in [NetworkMessage::Verack, NetworkMessage::Ping(3)] {
+        V1MessageEncoder::new(Network::Regtest, &message)
+            .encode_sync(&mut incoming)
+            .unwrap();
+    }
+
+    let mut connection = V1Connection::new(&incoming[..], Vec::new(), Network::Regtest);
+    assert_eq!(connection.recv().unwrap(), NetworkMessage::Verack);
+    let NetworkMessage::Ping(nonce) = connection.recv().unwrap() else {
+        panic!("expected a ping");
+    };
+    connection.send(&NetworkMessage::Pong(nonce)).unwrap();
+    connection.send(&NetworkMessage::Verack).unwrap();
+    assert!(matches!(
+        connection.recv(),
+        Err(ReadError::Decode(DecodeError::EndOfStream))
+    ));
+
+    let mut expected = Vec::new();
+    for message in [NetworkMessage::Pong(3), NetworkMessage::Verack] {
+        V1MessageEncoder::encode_into(Network::Regtest, &message, &mut expected);
+    }
+    let (_, sent) = connection.into_inner();
+    assert_eq!(sent, expected);
+}
+
+/// A verack for testnet followed by a ping for regtest.
+fn wrong_network_then_ping() -> Vec<u8> {
+    let mut incoming = Vec::new();
+    V1MessageEncoder::encode_into(Network::Testnet, &NetworkMessage::Verack, &mut incoming);
+    V1MessageEncoder::encode_into(Network::Regtest, &NetworkMessage::Ping(3), &mut incoming);
+    incoming
+}
+
+#[test]
+fn connection_recv_after_a_fatal_error_reads_on() {
+    let incoming = wrong_network_then_ping();
+    let mut connection = V1Connection::new(&incoming[..], Vec::new(), Network::Regtest);
+    assert!(matches!(
+        connection.recv(),
+        Err(ReadError::Decode(DecodeError::WrongMagic { .. }))
+    ));
+    assert_eq!(connection.recv().unwrap(), NetworkMessage::Ping(3));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn async_connection_recv_after_a_fatal_error_reads_on() {
+    use bitcoin_codecs::AsyncV1Connection;
+
+    let incoming = std::io::Cursor::new(wrong_network_then_ping());
+    let mut connection = AsyncV1Connection::new(incoming, Vec::new(), Network::Regtest);
+    assert!(matches!(
+        connection.recv().await,
+        Err(ReadError::Decode(DecodeError::WrongMagic { .. }))
+    ));
+    assert_eq!(connection.recv().await.unwrap(), NetworkMessage::Ping(3));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn async_connection_answers_pings() {
+    use bitcoin_codecs::AsyncV1Connection;
+
+    let (local, remote) = tokio::io::duplex(64);
+    let (local_reader, local_writer) = tokio::io::split(local);
+    let (remote_reader, remote_writer) = tokio::io::split(remote);
+    let mut local = AsyncV1Connection::new(local_reader, local_writer, Network::Regtest);
+    let mut remote = AsyncV1Connection::new(remote_reader, remote_writer, Network::Regtest);
+
+    let requester = async {
+        for nonce in 0..3 {
+            local.send(&NetworkMessage::Ping(nonce)).await.unwrap();
+            assert_eq!(local.recv().await.unwrap(), NetworkMessage::Pong(nonce));
+        }
+        drop(local);
+    };
+    let responder = async {
+        loop {
+            match remote.recv().await {
+                Ok(NetworkMessage::Ping(nonce)) => {
+                    remote.send(&NetworkMessage::Pong(nonce)).await.unwrap()
+                }
+                Err(ReadError::Decode(DecodeError::EndOfStream)) => break,
+                other => panic!("unexpected {other:?}"),
+            }
+        }
+    };
+    tokio::join!(requester, responder);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn decode_ahead_sends_messages_and_errors() {
+    use bitcoin::p2p::message::CommandString;
+    use bitcoin_codecs::{DecodeAhead, V1MessageDecoder};
+
+    let mut incoming = Vec::new();
+    let skipped = NetworkMessage::Unknown {
+        command: CommandString::try_from_static("sendtxrcncl").unwrap(),
+        payload: vec![0; 12],
+    };
+    for message in [NetworkMessage::Ping(1), skipped, NetworkMessage::Ping(2)] {
+        V1MessageEncoder::encode_into(Network::Regtest, &message, &mut incoming);
+    }
+    let decoder = V1MessageDecoder::with_commands(Network::Regtest, &["ping"]);
+    let (task, mut messages) = DecodeAhead::new(std::io::Cursor::new(incoming), decoder, 1);
+    let task = tokio::spawn(task.run());
+
+    assert_eq!(
+        messages.recv().await.unwrap().unwrap(),
+        NetworkMessage::Ping(1)
+    );
+    assert!(matches!(
+        messages.recv().await.unwrap(),
+        Err(ReadError::Decode(DecodeError::Skipped { .. }))
+    ));
+    assert_eq!(
+        messages.recv().await.unwrap().unwrap(),
+        NetworkMessage::Ping(2)
+    );
+    // The stream ending between messages closes the channel.
+    assert!(messages.recv().await.is_none());
+    task.await.unwrap();
+
+    // A wrong magic ends decoding after it is reported.
+    let mut incoming = Vec::new();
+    for network in [Network::Bitcoin, Network::Regtest] {
+        V1MessageEncoder::encode_into(network, &NetworkMessage::Verack, &mut incoming);
+    }
+    let decoder = V1MessageDecoder::new(Network::Regtest);
+    let (task, mut messages) = DecodeAhead::new(std::io::Cursor::new(incoming), decoder, 4);
+    tokio::spawn(task.run());
+    assert!(matches!(
+        messages.recv().await.unwrap(),
+        Err(ReadError::Decode(DecodeError::WrongMagic { .. }))
+    ));
+    assert!(messages.recv().await.is_none());
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn decode_ahead_stops_when_the_receiver_is_dropped() {
+    use bitcoin_codecs::{DecodeAhead, V1MessageDecoder};
+
+    // The peer sends half a message and then goes quiet.
+    let (reader, mut writer) = tokio::io::duplex(64);
+    let mut ping = Vec::new();
+    V1MessageEncoder::encode_into(Network::Regtest, &NetworkMessage::Ping(1), &mut ping);
+    tokio::io::AsyncWriteExt::write_all(&mut writer, &ping[..10])
+        .await
+        .unwrap();
+
+    let decoder = V1MessageDecoder::new(Network::Regtest);
+    let (task, messages) = DecodeAhead::new(reader, decoder, 1);
+    let task = tokio::spawn(task.run());
+    tokio::task::yield_now().await;
+    drop(messages);
+    let mut decoder = tokio::time::timeout(std::time::Duration::from_secs(5), task)
+        .await
+        .expect("the task stops")
+        .unwrap();
+    assert_eq!(
+        decoder.feed(&mut &ping[10..]).unwrap(),
+        Some(NetworkMessage::Ping(1))
+    );
+}
@@ -0,0 +1,110 @@
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::Network;
+use bitcoin_codecs::{DecoderExt, EncoderExt, V1MessageDecoder, V1MessageEncoder};
+
+// Verack has an empty payload, ping a non-empty one.
+const MESSAGES: [NetworkMessage; 2] = [NetworkMessage::Verack, NetworkMessage::Ping(7)];
+
+#[test]
+fn encode_sync_round_trip() {
+    for message in &MESSAGES {
+        let mut bytes = Vec::new();
+        V1MessageEncoder::new(Network::Bitcoin, message)
+            .encode_sync(&mut bytes)
+            .unwrap();
+
+        let decoded = V1MessageDecoder::new(Network::Bitcoin)
+            .decode_sync(&mut &bytes[..])
+            .unwrap();
+        assert_eq!(&decoded, message);
+    }
+}
+
+#[test]
+fn decode_sync_counts_frame_bytes() {
+    let mut bytes = Vec::new();
+    for message in &MESSAGES {
+        V1MessageEncoder::encode_into(Network::Bitcoin, message, &mut bytes);
+    }
+
+    // The reader is left on the second frame, whose offset is the first's size.
+    let mut reader = &bytes[..];
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    assert_eq!(
+        (&mut decoder).decode_sync_counted(&mut reader).unwrap(),
+        (NetworkMessage::Verack, 24)
+    );
+    assert_eq!(
+        (&mut decoder).decode_sync_counted(&mut reader).unwrap(),
+        (NetworkMessage::Ping(7), 32)
+    );
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn decode_tokio_counts_frame_bytes() {
+    let mut bytes = Vec::new();
+    V1MessageEncoder::encode_into(Network::Bitcoin, &NetworkMessage::Ping(7), &mut bytes);
+
+    let decoded = V1MessageDecoder::new(Network::Bitcoin)
+        .decode_tokio_counted(&bytes[..])
+        .await
+        .unwrap();
+    assert_eq!(decoded, (NetworkMessage::Ping(7), bytes.len()));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn encode_tokio_round_trip() {
+    for message in &MESSAGES {
+        let (mut writer, reader) = tokio::io::duplex(64);
+        let encode = async move {
+            let result = V1MessageEncoder::new(Network::Bitcoin, message)
+                .encode_tokio(&mut writer)
+                .await;
+            // Closing the pipe lets the decoder see where the message ends.
+            drop(writer);
+            result
+        };
+        let decode =
+            V1MessageDecoder::new(Network::Bitcoin).decode_tokio(tokio::io::BufReader::new(reader));
+
+        let (encoded, decoded) = tokio::join!(encode, decode);
+        encoded.unwrap();
+        assert_eq!(&decoded.unwrap(), message);
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn decode_tokio_timeout_covers_whole_message() {
+    use bitcoin_codecs::DecodeError;
+    use core::time::Duration;
+    use push_decode::ReadError;
+    use tokio::io::AsyncWriteExt;
+
+    let mut bytes = Vec::new();
+    V1MessageEncoder::new(Network::Bitcoin, &NetworkMessage::Ping(7))
+        .encode_sync(&mut bytes)
+        .unwrap();
+
+    // Only part of the message is sent and the pipe is kept open.
+    let (mut writer, reader) = tokio::io::duplex(64);
+    writer.write_all(&bytes[..10]).await.unwrap();
+    let result = V1MessageDecoder::new(Network::Bitcoin)
+        .decode_tokio_timeout(tokio::io::BufReader::new(reader), Duration::from_millis(50))
+        .await;
+    assert!(matches!(
+        result,
+        Err(ReadError::Decode(DecodeError::Timeout))
+    ));
+
+    let (mut writer, reader) = tokio::io::duplex(64);
+    writer.write_all(&bytes).await.unwrap();
+    drop(writer);
+    let decoded = V1MessageDecoder::new(Network::Bitcoin)
+        .decode_tokio_timeout(tokio::io::BufReader::new(reader), Duration::from_secs(5))
+        .await
+        .unwrap();
+    assert_eq!(decoded, NetworkMessage::Ping(7));
+}
@@ -0,0 +1,60 @@
+#![cfg(feature = "tokio-util")]
+
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::Network;
+use bitcoin_codecs::{V1Codec, V1MessageDecoder};
+use bytes::BytesMut;
+use tokio_util::codec::{Decoder, Encoder};
+
+#[test]
+fn decode_partial_frames() {
+    let mut codec = V1Codec::new(V1MessageDecoder::new(Network::Bitcoin));
+    let mut encoded = BytesMut::new();
+    codec
+        .encode(NetworkMessage::Ping(42), &mut encoded)
+        .unwrap();
+    codec.encode(NetworkMessage::Verack, &mut encoded).unwrap();
+
+    let mut src = BytesMut::new();
+    let mut decoded = Vec::new();
+    for byte in encoded.iter() {
+        src.extend_from_slice(&[*byte]);
+        while let Some(message) = codec.decode(&mut src).unwrap() {
+            decoded.push(message);
+        }
+    }
+
+    assert_eq!(
+        decoded,
+        vec![NetworkMessage::Ping(42), NetworkMessage::Verack]
+    );
+    assert!(src.is_empty());
+}
+
+#[test]
+fn decoder_configuration_applies() {
+    let mut codec = V1Codec::new(V1MessageDecoder::with_commands(Network::Regtest, &["pong"]));
+    let mut src = BytesMut::new();
+    codec.encode(NetworkMessage::Ping(1), &mut src).unwrap();
+    codec.encode(NetworkMessage::Pong(2), &mut src).unwrap();
+    // Encoded with the regtest magic the decoder expects.
+    assert_eq!(src[..4], Network::Regtest.magic().to_bytes());
+
+    assert_eq!(
+        codec.decode(&mut src).unwrap(),
+        Some(NetworkMessage::Pong(2))
+    );
+    assert!(src.is_empty());
+}
+
+#[test]
+fn truncated_frame_errors_at_eof() {
+    let mut codec = V1Codec::new(V1MessageDecoder::new(Network::Bitcoin));
+    let mut src = BytesMut::new();
+    codec.encode(NetworkMessage::Ping(1), &mut src).unwrap();
+    src.truncate(src.len() - 1);
+
+    assert!(codec.decode(&mut src).unwrap().is_none());
+    assert!(codec.decode_eof(&mut src).is_err());
+    assert!(codec.decode_eof(&mut src).unwrap().is_none());
+}
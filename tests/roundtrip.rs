@@ -0,0 +1,573 @@
+use bitcoin::absolute::LockTime;
+use bitcoin::bip152::{HeaderAndShortIds, PrefilledTransaction, ShortId};
+use bitcoin::block::{self, Block};
+use bitcoin::consensus::encode;
+use bitcoin::hashes::Hash;
+use bitcoin::hex::FromHex;
+use bitcoin::p2p::address::{AddrV2, AddrV2Message};
+use bitcoin::p2p::message::{CommandString, NetworkMessage};
+use bitcoin::p2p::message_blockdata::{GetBlocksMessage, GetHeadersMessage, Inventory};
+use bitcoin::p2p::message_bloom::{BloomFlags, FilterAdd, FilterLoad};
+use bitcoin::p2p::message_compact_blocks::{CmpctBlock, SendCmpct};
+use bitcoin::p2p::message_network::VersionMessage;
+use bitcoin::p2p::{Address, Magic, ServiceFlags};
+use bitcoin::transaction::{self, OutPoint, Transaction, TxIn, TxOut};
+use bitcoin::{
+    Amount, BlockHash, CompactTarget, MerkleBlock, Network, ScriptBuf, Sequence, TxMerkleNode,
+    Txid, Witness, Wtxid,
+};
+use bitcoin_codecs::{
+    DecoderExt, HeaderDecoder, V1MessageDecoder, V1MessageEncoder, HEADER_SIZE,
+    MAX_BLOOM_FILTER_SIZE, MAX_BLOOM_HASH_FUNCS, MAX_FILTER_ADD_SIZE,
+};
+use proptest::prelude::*;
+use push_decode::{Decoder, Encoder};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+// A version frame captured from a /Satoshi:0.17.1/ mainnet node, as published
+// in rust-bitcoin's test suite.
+const CAPTURED_VERSION: &str = "f9beb4d976657273696f6e000000000066000000be61b8277f1101000d04000000000000f00f4d5c00000000000000000000000000000000000000000000ffff5bf08c80b4bd0d04000000000000000000000000000000000000000000000000faa99559cc68a1c1102f5361746f7368693a302e31372e312f938c080001";
+
+// Payloads captured from a /Satoshi:0.9.99/ node, also from rust-bitcoin's tests.
+// The same locator payload was seen for both `getblocks` and `getheaders`.
+const CAPTURED_VERSION_PAYLOAD: &str = "721101000100000000000000e6e0845300000000010000000000000000000000000000000000ffff0000000000000100000000000000fd87d87eeb4364f22cf54dca59412db7208d47d920cffce83ee8102f5361746f7368693a302e392e39392f2c9f040001";
+const CAPTURED_LOCATOR_PAYLOAD: &str = "72110100014a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b0000000000000000000000000000000000000000000000000000000000000000";
+
+// BIP-152 payloads as published in rust-bitcoin's message tests. The compact
+// block is for a regtest block with a prefilled coinbase, and the block
+// transactions response carries a single coinbase.
+const CAPTURED_CMPCTBLOCK_PAYLOAD: &str = "00000030d923ad36ff2d955abab07f8a0a6e813bc6e066b973e780c5e36674cad5d1cd1f6e265f2a17a0d35cbe701fe9d06e2c6324cfe135f6233e8b767bfa3fb4479b71115dc562ffff7f2006000000000000000000000000010002000000010000000000000000000000000000000000000000000000000000000000000000ffffffff0302ee00ffffffff0100f9029500000000015100000000";
+const CAPTURED_BLOCKTXN_PAYLOAD: &str = "2e93c0cff39ff605020072d96bc3a8d20b8447e294d08092351c8583e08d9b5a01020000000001010000000000000000000000000000000000000000000000000000000000000000ffffffff0402dc0000ffffffff0200f90295000000001976a9142b4569203694fc997e13f2c0a1383b9e16c77a0d88ac0000000000000000266a24aa21a9ede2f61c3f71d1defd3fa999dfa36953755c690689799962b48bebd836974e8cf90120000000000000000000000000000000000000000000000000000000000000000000000000";
+
+// A getblocktxn payload for a zero block hash using the differentially encoded
+// indexes of rust-bitcoin's BIP-152 tests, which request transactions 0, 6, 8
+// and 19.
+const GETBLOCKTXN_PAYLOAD: &str =
+    "0000000000000000000000000000000000000000000000000000000000000000040005010a";
+
+// Frames of the messages the examples send. These are built by hand, not
+// captured, so they only check the framing against an independent encoding.
+const PING: &str = "f9beb4d970696e67000000000000000008000000278dac0aefcdab9078563412";
+const PONG: &str = "f9beb4d9706f6e67000000000000000008000000278dac0aefcdab9078563412";
+const VERACK: &str = "f9beb4d976657261636b000000000000000000005df6e0e2";
+const VERSION: &str = "f9beb4d976657273696f6e00000000006c000000c7f75a0b7f110100000000000000000000f1536500000000000000000000000000000000000000000000ffff7f000001208d000000000000000000000000000000000000ffff000000000000efcdab9078563412162f626974636f696e2d636f646563733a302e312e302f0000000000";
+
+// Address gossip as Bitcoin Core serializes it, with an IPv4 address in its
+// IPv6 mapped form, a plain IPv6 address and a Tor v2 address in OnionCat form,
+// followed by the same three networks in BIP-155 form with Tor v3 instead.
+const ADDR_PAYLOAD: &str = "0361bc6649010000000000000000000000000000000000ffff01020304208d61bc6649090400000000000020010db8000000000000000000000001208d61bc66490000000000000000fd87d87eeb43f1f2f3f4f5f6f7f8f9fa208d";
+const ADDRV2_PAYLOAD: &str = "0361bc664901010401020304208d61bc6649fd0904021020010db8000000000000000000000001208d61bc6649000420606162636465666768696a6b6c6d6e6f707172737475767778797a7b7c7d7e7f208d";
+
+// An IPv4 mapped address under the IPv6 network id, which BIP-155 forbids.
+const ADDRV2_MAPPED_PAYLOAD: &str = "0161bc664901021000000000000000000000ffff01020304208d";
+
+fn encode(message: &NetworkMessage) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    V1MessageEncoder::new(Network::Bitcoin, message).write_to_vec(&mut bytes);
+    bytes
+}
+
+fn decode(bytes: &[u8]) -> NetworkMessage {
+    V1MessageDecoder::new(Network::Bitcoin)
+        .decode_sync(&mut &bytes[..])
+        .unwrap()
+}
+
+fn example_version() -> NetworkMessage {
+    NetworkMessage::Version(VersionMessage {
+        version: 70015,
+        services: ServiceFlags::NONE,
+        timestamp: 1_700_000_000,
+        receiver: Address::new(&"127.0.0.1:8333".parse().unwrap(), ServiceFlags::NONE),
+        sender: Address::new(&"0.0.0.0:0".parse().unwrap(), ServiceFlags::NONE),
+        nonce: 0x1234567890abcdef,
+        user_agent: "/bitcoin-codecs:0.1.0/".to_string(),
+        start_height: 0,
+        relay: false,
+    })
+}
+
+#[test]
+fn captured_version_frame() {
+    let bytes = Vec::from_hex(CAPTURED_VERSION).unwrap();
+    let message = decode(&bytes);
+    match &message {
+        NetworkMessage::Version(version) => {
+            assert_eq!(version.version, 70015);
+            assert_eq!(version.user_agent, "/Satoshi:0.17.1/");
+            assert_eq!(version.start_height, 560275);
+            assert!(version.relay);
+        }
+        other => panic!("unexpected message {other:?}"),
+    }
+    assert_eq!(encode(&message), bytes);
+}
+
+#[test]
+fn captured_payloads() {
+    let version = Vec::from_hex(CAPTURED_VERSION_PAYLOAD).unwrap();
+    let locator = Vec::from_hex(CAPTURED_LOCATOR_PAYLOAD).unwrap();
+    let genesis = bitcoin::blockdata::constants::genesis_block(Network::Bitcoin);
+    let corpus = [
+        NetworkMessage::Version(encode::deserialize(&version).unwrap()),
+        NetworkMessage::GetBlocks(encode::deserialize(&locator).unwrap()),
+        NetworkMessage::GetHeaders(encode::deserialize(&locator).unwrap()),
+        NetworkMessage::Block(genesis),
+    ];
+    for message in corpus {
+        let bytes = encode(&message);
+        assert_eq!(bytes[24..], encode::serialize(&message)[..]);
+        assert_eq!(decode(&bytes), message);
+    }
+}
+
+/// Frames `payload` under `command` exactly as a peer would have sent it.
+fn frame(command: &'static str, payload: Vec<u8>) -> Vec<u8> {
+    encode(&NetworkMessage::Unknown {
+        command: CommandString::try_from_static(command).unwrap(),
+        payload,
+    })
+}
+
+/// Decodes `bytes` whole and a byte at a time, checking both paths agree.
+fn decode_both_ways(bytes: &[u8]) -> NetworkMessage {
+    let whole = decode(bytes);
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    let mut split = None;
+    for byte in bytes.chunks(1) {
+        split = decoder.feed(&mut &byte[..]).unwrap().or(split);
+    }
+    assert_eq!(split.as_ref(), Some(&whole));
+    whole
+}
+
+#[test]
+fn address_gossip() {
+    use std::net::{IpAddr, SocketAddr};
+
+    let bytes = frame("addr", Vec::from_hex(ADDR_PAYLOAD).unwrap());
+    let addresses = match decode_both_ways(&bytes) {
+        NetworkMessage::Addr(addresses) => addresses,
+        other => panic!("unexpected message: {other:?}"),
+    };
+    assert!(addresses.iter().all(|(time, _)| *time == 0x4966bc61));
+    // IPv4 only travels mapped in to IPv6, and comes back out as IPv4.
+    assert_eq!(
+        addresses[0].1.socket_addr().unwrap(),
+        "1.2.3.4:8333".parse::<SocketAddr>().unwrap()
+    );
+    assert_eq!(
+        addresses[1].1.socket_addr().unwrap(),
+        "[2001:db8::1]:8333".parse::<SocketAddr>().unwrap()
+    );
+    assert_eq!(
+        addresses[1].1.services,
+        ServiceFlags::NETWORK | ServiceFlags::WITNESS | ServiceFlags::NETWORK_LIMITED
+    );
+    // A Tor v2 address has no socket address, but its bytes survive as is.
+    assert!(addresses[2].1.socket_addr().is_err());
+    assert_eq!(
+        addresses[2].1.address,
+        [0xfd87, 0xd87e, 0xeb43, 0xf1f2, 0xf3f4, 0xf5f6, 0xf7f8, 0xf9fa]
+    );
+    assert_eq!(encode(&NetworkMessage::Addr(addresses)), bytes);
+
+    let bytes = frame("addrv2", Vec::from_hex(ADDRV2_PAYLOAD).unwrap());
+    let addresses = match decode_both_ways(&bytes) {
+        NetworkMessage::AddrV2(addresses) => addresses,
+        other => panic!("unexpected message: {other:?}"),
+    };
+    assert_eq!(addresses[0].addr, AddrV2::Ipv4(Ipv4Addr::new(1, 2, 3, 4)));
+    assert_eq!(
+        addresses[0].socket_addr().unwrap().ip(),
+        IpAddr::V4(Ipv4Addr::new(1, 2, 3, 4))
+    );
+    assert_eq!(
+        addresses[1].addr,
+        AddrV2::Ipv6("2001:db8::1".parse::<Ipv6Addr>().unwrap())
+    );
+    assert_eq!(
+        addresses[2].addr,
+        AddrV2::TorV3(core::array::from_fn(|i| 0x60 + i as u8))
+    );
+    assert!(addresses[2].socket_addr().is_err());
+    assert!(addresses.iter().all(|address| address.port == 8333));
+    assert_eq!(encode(&NetworkMessage::AddrV2(addresses)), bytes);
+
+    // Bitcoin Core ignores such an address, but `bitcoin` fails the message.
+    let mapped = frame("addrv2", Vec::from_hex(ADDRV2_MAPPED_PAYLOAD).unwrap());
+    assert!(matches!(
+        V1MessageDecoder::new(Network::Bitcoin).decode_sync(&mut &mapped[..]),
+        Err(push_decode::ReadError::Decode(
+            bitcoin_codecs::DecodeError::InvalidPayload(_)
+        ))
+    ));
+}
+
+#[test]
+fn captured_compact_block_relay() {
+    use bitcoin::p2p::message_compact_blocks::{BlockTxn, GetBlockTxn};
+
+    let payload = Vec::from_hex(CAPTURED_CMPCTBLOCK_PAYLOAD).unwrap();
+    let bytes = frame("cmpctblock", payload.clone());
+    let compact = match decode_both_ways(&bytes) {
+        NetworkMessage::CmpctBlock(compact) => compact,
+        other => panic!("unexpected message: {other:?}"),
+    };
+    assert_eq!(
+        compact,
+        encode::deserialize::<CmpctBlock>(&payload).unwrap()
+    );
+    assert_eq!(compact.compact_block.prefilled_txs.len(), 1);
+    assert_eq!(compact.compact_block.prefilled_txs[0].idx, 0);
+    assert!(compact.compact_block.prefilled_txs[0].tx.is_coinbase());
+    assert_eq!(encode(&NetworkMessage::CmpctBlock(compact)), bytes);
+
+    let payload = Vec::from_hex(GETBLOCKTXN_PAYLOAD).unwrap();
+    let bytes = frame("getblocktxn", payload);
+    let request = match decode_both_ways(&bytes) {
+        NetworkMessage::GetBlockTxn(GetBlockTxn { txs_request }) => txs_request,
+        other => panic!("unexpected message: {other:?}"),
+    };
+    assert_eq!(request.block_hash, BlockHash::all_zeros());
+    assert_eq!(request.indexes, [0, 6, 8, 19]);
+    assert_eq!(
+        encode(&NetworkMessage::GetBlockTxn(GetBlockTxn {
+            txs_request: request
+        })),
+        bytes
+    );
+
+    let payload = Vec::from_hex(CAPTURED_BLOCKTXN_PAYLOAD).unwrap();
+    let bytes = frame("blocktxn", payload.clone());
+    let response = match decode_both_ways(&bytes) {
+        NetworkMessage::BlockTxn(BlockTxn {
+            transactions: response,
+        }) => response,
+        other => panic!("unexpected message: {other:?}"),
+    };
+    assert_eq!(
+        BlockTxn {
+            transactions: response.clone()
+        },
+        encode::deserialize::<BlockTxn>(&payload).unwrap()
+    );
+    assert_eq!(response.transactions.len(), 1);
+    assert!(response.transactions[0].is_coinbase());
+    assert_eq!(
+        encode(&NetworkMessage::BlockTxn(BlockTxn {
+            transactions: response
+        })),
+        bytes
+    );
+}
+
+#[test]
+fn example_frames() {
+    let corpus = [
+        (PING, NetworkMessage::Ping(0x1234567890abcdef)),
+        (PONG, NetworkMessage::Pong(0x1234567890abcdef)),
+        (VERACK, NetworkMessage::Verack),
+        (VERSION, example_version()),
+    ];
+    for (hex, message) in corpus {
+        let bytes = Vec::from_hex(hex).unwrap();
+        assert_eq!(decode(&bytes), message);
+        assert_eq!(encode(&message), bytes);
+    }
+}
+
+fn inventory() -> impl Strategy<Value = Inventory> {
+    prop_oneof![
+        any::<[u8; 32]>().prop_map(|h| Inventory::Block(BlockHash::from_byte_array(h))),
+        any::<[u8; 32]>().prop_map(|h| Inventory::Transaction(Txid::from_byte_array(h))),
+        any::<[u8; 32]>().prop_map(|h| Inventory::WTx(Wtxid::from_byte_array(h))),
+        any::<[u8; 32]>().prop_map(|h| Inventory::WitnessBlock(BlockHash::from_byte_array(h))),
+    ]
+}
+
+fn block_header() -> impl Strategy<Value = block::Header> {
+    (
+        any::<i32>(),
+        any::<[u8; 32]>(),
+        any::<[u8; 32]>(),
+        any::<u32>(),
+        any::<u32>(),
+        any::<u32>(),
+    )
+        .prop_map(|(version, prev, merkle, time, bits, nonce)| block::Header {
+            version: block::Version::from_consensus(version),
+            prev_blockhash: BlockHash::from_byte_array(prev),
+            merkle_root: TxMerkleNode::from_byte_array(merkle),
+            time,
+            bits: CompactTarget::from_consensus(bits),
+            nonce,
+        })
+}
+
+fn transaction() -> impl Strategy<Value = Transaction> {
+    let input = (
+        any::<[u8; 32]>(),
+        any::<u32>(),
+        prop::collection::vec(any::<u8>(), 0..64),
+        any::<u32>(),
+        prop::collection::vec(prop::collection::vec(any::<u8>(), 0..64), 0..4),
+    )
+        .prop_map(|(txid, vout, script_sig, sequence, witness)| TxIn {
+            previous_output: OutPoint {
+                txid: Txid::from_byte_array(txid),
+                vout,
+            },
+            script_sig: ScriptBuf::from_bytes(script_sig),
+            sequence: Sequence(sequence),
+            witness: Witness::from_slice(&witness),
+        });
+    let output = (any::<u64>(), prop::collection::vec(any::<u8>(), 0..64)).prop_map(
+        |(value, script_pubkey)| TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: ScriptBuf::from_bytes(script_pubkey),
+        },
+    );
+    (
+        any::<i32>(),
+        // Without inputs the empty input count reads as the segwit marker.
+        prop::collection::vec(input, 1..4),
+        prop::collection::vec(output, 0..4),
+        any::<u32>(),
+    )
+        .prop_map(|(version, input, output, lock_time)| Transaction {
+            version: transaction::Version(version),
+            lock_time: LockTime::from_consensus(lock_time),
+            input,
+            output,
+        })
+}
+
+fn locator() -> impl Strategy<Value = (u32, Vec<BlockHash>, BlockHash)> {
+    (
+        any::<u32>(),
+        prop::collection::vec(
+            any::<[u8; 32]>().prop_map(BlockHash::from_byte_array),
+            0..32,
+        ),
+        any::<[u8; 32]>().prop_map(BlockHash::from_byte_array),
+    )
+}
+
+fn address() -> impl Strategy<Value = Address> {
+    (any::<u64>(), any::<[u16; 8]>(), any::<u16>()).prop_map(|(services, address, port)| Address {
+        services: ServiceFlags::from(services),
+        address,
+        port,
+    })
+}
+
+fn addr_v2() -> impl Strategy<Value = AddrV2> {
+    // IPv6 addresses in the IPv4 mapped, Tor and CJDNS ranges are rejected, so
+    // they are kept in the global unicast range.
+    let ipv6 = any::<[u16; 8]>().prop_map(|mut segments| {
+        segments[0] = 0x2000 | (segments[0] & 0x0fff);
+        Ipv6Addr::from(segments)
+    });
+    let cjdns = any::<[u16; 8]>().prop_map(|mut segments| {
+        segments[0] = 0xfc00 | (segments[0] & 0x00ff);
+        Ipv6Addr::from(segments)
+    });
+    prop_oneof![
+        any::<[u8; 4]>().prop_map(|ip| AddrV2::Ipv4(Ipv4Addr::from(ip))),
+        ipv6.prop_map(AddrV2::Ipv6),
+        any::<[u8; 10]>().prop_map(AddrV2::TorV2),
+        any::<[u8; 32]>().prop_map(AddrV2::TorV3),
+        any::<[u8; 32]>().prop_map(AddrV2::I2p),
+        cjdns.prop_map(AddrV2::Cjdns),
+        (7u8.., prop::collection::vec(any::<u8>(), 0..64))
+            .prop_map(|(id, addr)| AddrV2::Unknown(id, addr)),
+    ]
+}
+
+fn compact_block() -> impl Strategy<Value = CmpctBlock> {
+    (
+        block_header(),
+        any::<u64>(),
+        prop::collection::vec(any::<[u8; 6]>().prop_map(ShortId::from), 0..32),
+        prop::collection::vec(
+            (any::<u16>(), transaction()).prop_map(|(idx, tx)| PrefilledTransaction { idx, tx }),
+            0..3,
+        ),
+    )
+        .prop_map(|(header, nonce, short_ids, prefilled_txs)| CmpctBlock {
+            compact_block: HeaderAndShortIds {
+                header,
+                nonce,
+                short_ids,
+                prefilled_txs,
+            },
+        })
+}
+
+fn version() -> impl Strategy<Value = NetworkMessage> {
+    (
+        any::<u32>(),
+        any::<u64>(),
+        any::<i64>(),
+        any::<u64>(),
+        "[ -~]{0,64}",
+        any::<i32>(),
+        any::<bool>(),
+    )
+        .prop_map(
+            |(version, services, timestamp, nonce, user_agent, start_height, relay)| {
+                let address = Address::new(&"10.0.0.1:8333".parse().unwrap(), ServiceFlags::NONE);
+                NetworkMessage::Version(VersionMessage {
+                    version,
+                    services: ServiceFlags::from(services),
+                    timestamp,
+                    receiver: address.clone(),
+                    sender: address,
+                    nonce,
+                    user_agent,
+                    start_height,
+                    relay,
+                })
+            },
+        )
+}
+
+// Commands outside of those modeled by `NetworkMessage`, which come back as unknown.
+fn unknown() -> impl Strategy<Value = NetworkMessage> {
+    ("x[a-z]{0,11}", prop::collection::vec(any::<u8>(), 0..512)).prop_map(|(command, payload)| {
+        NetworkMessage::Unknown {
+            command: CommandString::try_from(command).unwrap(),
+            payload,
+        }
+    })
+}
+
+fn message() -> impl Strategy<Value = NetworkMessage> {
+    prop_oneof![
+        Just(NetworkMessage::Verack),
+        Just(NetworkMessage::SendHeaders),
+        Just(NetworkMessage::GetAddr),
+        Just(NetworkMessage::MemPool),
+        Just(NetworkMessage::FilterClear),
+        Just(NetworkMessage::WtxidRelay),
+        Just(NetworkMessage::SendAddrV2),
+        any::<u64>().prop_map(NetworkMessage::Ping),
+        any::<u64>().prop_map(NetworkMessage::Pong),
+        (0..=Amount::MAX_MONEY.to_sat() as i64).prop_map(NetworkMessage::FeeFilter),
+        (any::<bool>(), any::<u64>()).prop_map(|(send_compact, version)| {
+            NetworkMessage::SendCmpct(SendCmpct {
+                send_compact,
+                version,
+            })
+        }),
+        prop::collection::vec(inventory(), 0..64).prop_map(NetworkMessage::Inv),
+        prop::collection::vec(inventory(), 0..64).prop_map(NetworkMessage::GetData),
+        prop::collection::vec(inventory(), 0..64).prop_map(NetworkMessage::NotFound),
+        prop::collection::vec(block_header(), 0..16).prop_map(NetworkMessage::Headers),
+        transaction().prop_map(NetworkMessage::Tx),
+        (
+            prop::collection::vec(any::<u8>(), 0..=MAX_BLOOM_FILTER_SIZE),
+            0..=MAX_BLOOM_HASH_FUNCS,
+            any::<u32>(),
+            prop_oneof![
+                Just(BloomFlags::None),
+                Just(BloomFlags::All),
+                Just(BloomFlags::PubkeyOnly)
+            ],
+        )
+            .prop_map(|(filter, hash_funcs, tweak, flags)| {
+                NetworkMessage::FilterLoad(FilterLoad {
+                    filter,
+                    hash_funcs,
+                    tweak,
+                    flags,
+                })
+            }),
+        prop::collection::vec(any::<u8>(), 0..=MAX_FILTER_ADD_SIZE)
+            .prop_map(|data| NetworkMessage::FilterAdd(FilterAdd { data })),
+        (
+            block_header(),
+            prop::collection::vec(transaction(), 1..8),
+            any::<u8>()
+        )
+            .prop_map(|(header, txdata, matches)| {
+                let block = Block { header, txdata };
+                let txids: Vec<_> = block.txdata.iter().map(|tx| tx.compute_txid()).collect();
+                let merkle = MerkleBlock::from_block_with_predicate(&block, |txid| {
+                    let index = txids.iter().position(|t| t == txid).unwrap();
+                    matches & (1 << index) != 0
+                });
+                // The flag bits are padded to whole bytes once serialized.
+                let merkle = encode::deserialize(&encode::serialize(&merkle)).unwrap();
+                NetworkMessage::MerkleBlock(merkle)
+            }),
+        (block_header(), prop::collection::vec(transaction(), 0..4))
+            .prop_map(|(header, txdata)| NetworkMessage::Block(Block { header, txdata })),
+        prop::collection::vec((any::<u32>(), address()), 0..16).prop_map(NetworkMessage::Addr),
+        prop::collection::vec(
+            (any::<u32>(), any::<u64>(), addr_v2(), any::<u16>()).prop_map(
+                |(time, services, addr, port)| AddrV2Message {
+                    time,
+                    services: ServiceFlags::from(services),
+                    addr,
+                    port,
+                }
+            ),
+            0..16
+        )
+        .prop_map(NetworkMessage::AddrV2),
+        locator().prop_map(|(version, locator_hashes, stop_hash)| {
+            NetworkMessage::GetBlocks(GetBlocksMessage {
+                version,
+                locator_hashes,
+                stop_hash,
+            })
+        }),
+        locator().prop_map(|(version, locator_hashes, stop_hash)| {
+            NetworkMessage::GetHeaders(GetHeadersMessage {
+                version,
+                locator_hashes,
+                stop_hash,
+            })
+        }),
+        compact_block().prop_map(NetworkMessage::CmpctBlock),
+        version(),
+        unknown(),
+    ]
+}
+
+proptest! {
+    #[test]
+    fn round_trip(message in message()) {
+        prop_assert_eq!(decode(&encode(&message)), message);
+    }
+
+    #[test]
+    fn round_trip_split(message in message(), split in any::<prop::sample::Index>()) {
+        // The decoder buffers the payload when it arrives in two chunks.
+        let bytes = encode(&message);
+        let split = split.index(bytes.len() + 1);
+        let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+        let mut first = &bytes[..split];
+        let mut second = &bytes[split..];
+        let decoded = match decoder.feed(&mut first).unwrap() {
+            Some(decoded) => decoded,
+            None => decoder.feed(&mut second).unwrap().unwrap(),
+        };
+        prop_assert_eq!(decoded, message);
+    }
+
+    #[test]
+    fn arbitrary_headers_do_not_panic(header in any::<[u8; HEADER_SIZE]>(), payload in prop::collection::vec(any::<u8>(), 0..64)) {
+        // A deterministic slice of the `header` fuzz target, expecting the
+        // header's own magic so every command and length gets checked.
+        let magic = Magic::from_bytes(header[..4].try_into().unwrap());
+        let mut decoder = HeaderDecoder::with_magic(magic);
+        let pushed = decoder.decode_chunk(&mut &header[..]).and_then(|()| decoder.end());
+        prop_assert_eq!(HeaderDecoder::with_magic(magic).decode_array(&header), pushed);
+
+        let frame = [&header[..], &payload].concat();
+        let _ = V1MessageDecoder::with_magic(magic).decode_sync(&mut &frame[..]);
+    }
+}
@@ -0,0 +1,74 @@
+#![cfg(feature = "serde")]
+
+use bitcoin::p2p::message::{CommandString, NetworkMessage};
+use bitcoin::p2p::Magic;
+use bitcoin::Network;
+use bitcoin_codecs::{DecodeError, Header, V1DecoderState, V1MessageDecoder, V1MessageEncoder};
+
+#[test]
+fn header_json_round_trip() {
+    let header = Header {
+        magic: Magic::BITCOIN,
+        command: CommandString::try_from_static("ping").unwrap(),
+        length: 8,
+        checksum: [0xde, 0xad, 0xbe, 0xef],
+    };
+
+    let json = serde_json::to_string(&header).unwrap();
+    assert_eq!(
+        json,
+        r#"{"magic":"f9beb4d9","command":"ping","length":8,"checksum":[222,173,190,239]}"#
+    );
+    assert_eq!(serde_json::from_str::<Header>(&json).unwrap(), header);
+}
+
+fn ping_then_pong() -> Vec<u8> {
+    let mut bytes = Vec::new();
+    V1MessageEncoder::encode_into(Network::Bitcoin, &NetworkMessage::Ping(7), &mut bytes);
+    V1MessageEncoder::encode_into(Network::Bitcoin, &NetworkMessage::Pong(7), &mut bytes);
+    bytes
+}
+
+#[test]
+fn decoder_resumes_from_json_state() {
+    let bytes = ping_then_pong();
+    // Pause at every byte of the first message, including mid header.
+    for cut in 0..32 {
+        let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+        assert_eq!(decoder.feed(&mut &bytes[..cut]).unwrap(), None);
+
+        let json = serde_json::to_string(&decoder.into_state().unwrap()).unwrap();
+        let state = serde_json::from_str::<V1DecoderState>(&json).unwrap();
+        let mut decoder = V1MessageDecoder::from_state(state).unwrap();
+
+        let mut rest = &bytes[cut..];
+        assert_eq!(
+            decoder.feed(&mut rest).unwrap(),
+            Some(NetworkMessage::Ping(7))
+        );
+        assert_eq!(
+            decoder.feed(&mut rest).unwrap(),
+            Some(NetworkMessage::Pong(7))
+        );
+    }
+}
+
+#[test]
+fn resumed_decoder_keeps_its_configuration() {
+    let bytes = ping_then_pong();
+    let mut decoder = V1MessageDecoder::with_commands(Network::Bitcoin, &["pong"]);
+    // Part way through the payload of the filtered out ping.
+    assert_eq!(decoder.feed(&mut &bytes[..28]).unwrap(), None);
+
+    let state = decoder.into_state().unwrap();
+    let mut decoder = V1MessageDecoder::from_state(state).unwrap();
+    let mut rest = &bytes[28..];
+    assert!(matches!(
+        decoder.feed(&mut rest),
+        Err(DecodeError::Skipped { command }) if command.as_ref() == "ping"
+    ));
+    assert_eq!(
+        decoder.feed(&mut rest).unwrap(),
+        Some(NetworkMessage::Pong(7))
+    );
+}
@@ -0,0 +1,48 @@
+#![cfg(feature = "futures")]
+
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::Network;
+use bitcoin_codecs::{DecodeError, V1MessageDecoder, V1MessageEncoder, V1MessageStream};
+use bytes::Bytes;
+use futures::executor::block_on;
+use futures::stream::{self, StreamExt};
+use push_decode::Encoder;
+
+fn encode(messages: &[NetworkMessage]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for message in messages {
+        V1MessageEncoder::new(Network::Bitcoin, message).write_to_vec(&mut bytes);
+    }
+    bytes
+}
+
+fn decode(chunks: Vec<Bytes>) -> Vec<Result<NetworkMessage, DecodeError>> {
+    let decoder = V1MessageDecoder::new(Network::Bitcoin);
+    block_on(V1MessageStream::new(decoder, stream::iter(chunks)).collect())
+}
+
+#[test]
+fn chunks_split_across_messages() {
+    let messages = [
+        NetworkMessage::Ping(1),
+        NetworkMessage::Verack,
+        NetworkMessage::Pong(2),
+    ];
+    let bytes = encode(&messages);
+    for size in [1, 7, 24, 31, bytes.len()] {
+        let chunks = bytes.chunks(size).map(Bytes::copy_from_slice).collect();
+        let decoded: Vec<_> = decode(chunks).into_iter().map(Result::unwrap).collect();
+        assert_eq!(decoded, messages);
+    }
+}
+
+#[test]
+fn truncated_stream_errors() {
+    let mut bytes = encode(&[NetworkMessage::Verack, NetworkMessage::Ping(1)]);
+    bytes.truncate(bytes.len() - 1);
+
+    let decoded = decode(vec![Bytes::from(bytes)]);
+    assert_eq!(decoded.len(), 2);
+    assert_eq!(decoded[0].as_ref().unwrap(), &NetworkMessage::Verack);
+    assert!(matches!(decoded[1], Err(DecodeError::IncompletePayload)));
+}
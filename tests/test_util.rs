@@ -0,0 +1,89 @@
+#![cfg(feature = "test-util")]
+
+use std::io::Write;
+use std::thread;
+
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::Network;
+use bitcoin_codecs::{
+    test_util, DecodeError, DecoderExt, EncoderExt, V1MessageDecoder, V1MessageEncoder,
+};
+use push_decode::ReadError;
+
+#[test]
+fn pushed_messages_decode() {
+    let (mut peer, mut local) = test_util::duplex();
+    let messages = [
+        NetworkMessage::Verack,
+        NetworkMessage::Ping(1),
+        NetworkMessage::Pong(1),
+    ];
+    peer.push_messages(Network::Bitcoin, &messages).unwrap();
+    peer.close();
+
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    for message in &messages {
+        assert_eq!(&(&mut decoder).decode_sync(&mut local).unwrap(), message);
+    }
+    assert_eq!(local.available(), 0);
+    assert!(matches!(
+        (&mut decoder).decode_sync(&mut local),
+        Err(ReadError::Decode(DecodeError::EndOfStream))
+    ));
+}
+
+#[test]
+fn peer_replies_across_threads() {
+    let (mut peer, mut local) = test_util::duplex();
+    let handle = thread::spawn(move || {
+        let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+        while let Ok(message) = (&mut decoder).decode_sync(&mut peer) {
+            if let NetworkMessage::Ping(nonce) = message {
+                peer.push_messages(Network::Bitcoin, &[NetworkMessage::Pong(nonce)])
+                    .unwrap();
+            }
+        }
+    });
+
+    for nonce in 0..3 {
+        V1MessageEncoder::new(Network::Bitcoin, &NetworkMessage::Ping(nonce))
+            .encode_sync(&mut local)
+            .unwrap();
+    }
+    local.close();
+    handle.join().unwrap();
+
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    for nonce in 0..3 {
+        assert_eq!(
+            (&mut decoder).decode_sync(&mut local).unwrap(),
+            NetworkMessage::Pong(nonce)
+        );
+    }
+}
+
+#[test]
+fn write_after_peer_dropped_fails() {
+    let (peer, mut local) = test_util::duplex();
+    drop(peer);
+    let err = local.write(&[0]).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::BrokenPipe);
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn tokio_round_trip() {
+    let (mut peer, mut local) = test_util::duplex();
+    let task = tokio::spawn(async move {
+        V1MessageDecoder::new(Network::Bitcoin)
+            .decode_tokio(&mut local)
+            .await
+    });
+    tokio::task::yield_now().await;
+    V1MessageEncoder::new(Network::Bitcoin, &NetworkMessage::Ping(9))
+        .encode_tokio(&mut peer)
+        .await
+        .unwrap();
+    drop(peer);
+    assert_eq!(task.await.unwrap().unwrap(), NetworkMessage::Ping(9));
+}
@@ -0,0 +1,74 @@
+#![cfg(feature = "tracing")]
+
+use std::sync::{Arc, Mutex};
+
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::Network;
+use bitcoin_codecs::{V1MessageDecoder, V1MessageEncoder};
+use tracing::field::{Field, Visit};
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Metadata, Subscriber};
+
+// Subscriber which keeps the fields of every event as `name=value` strings.
+#[derive(Clone, Default)]
+struct Recorder(Arc<Mutex<Vec<String>>>);
+
+struct Fields(Vec<String>);
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn core::fmt::Debug) {
+        self.0.push(format!("{}={value:?}", field.name()));
+    }
+}
+
+impl Subscriber for Recorder {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.target() == "bitcoin_codecs"
+    }
+
+    fn new_span(&self, _: &Attributes<'_>) -> Id {
+        Id::from_u64(1)
+    }
+
+    fn record(&self, _: &Id, _: &Record<'_>) {}
+
+    fn record_follows_from(&self, _: &Id, _: &Id) {}
+
+    fn event(&self, event: &Event<'_>) {
+        let mut fields = Fields(Vec::new());
+        event.record(&mut fields);
+        self.0.lock().unwrap().push(fields.0.join(" "));
+    }
+
+    fn enter(&self, _: &Id) {}
+
+    fn exit(&self, _: &Id) {}
+}
+
+#[test]
+fn decoding_emits_events_without_payloads() {
+    let mut bytes = Vec::new();
+    V1MessageEncoder::encode_into(Network::Bitcoin, &NetworkMessage::Ping(7), &mut bytes);
+    let mut corrupt = Vec::new();
+    V1MessageEncoder::encode_into(Network::Bitcoin, &NetworkMessage::Pong(7), &mut corrupt);
+    corrupt[20] ^= 1;
+    bytes.extend(corrupt);
+
+    let recorder = Recorder::default();
+    tracing::subscriber::with_default(recorder.clone(), || {
+        let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+        let results: Vec<_> = decoder.decode_all(&bytes).collect();
+        assert_eq!(results.len(), 2);
+    });
+
+    let events = recorder.0.lock().unwrap();
+    assert_eq!(events.len(), 4, "{events:?}");
+    assert_eq!(events[0], "message=header decoded command=ping length=8");
+    assert_eq!(events[1], "message=message decoded command=ping length=8");
+    assert_eq!(events[2], "message=header decoded command=pong length=8");
+    assert!(
+        events[3].starts_with("message=decode failed kind=\"invalid_checksum\""),
+        "{}",
+        events[3]
+    );
+}
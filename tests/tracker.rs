@@ -0,0 +1,183 @@
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::p2p::message_network::VersionMessage;
+use bitcoin::p2p::{Address, ServiceFlags};
+use bitcoin::Network;
+use bitcoin_codecs::{
+    DecodeError, DecoderExt, HandshakeDecoder, HandshakeTracker, HandshakeViolation,
+    V1MessageDecoder, V1MessageEncoder,
+};
+
+fn version() -> NetworkMessage {
+    let address = Address::new(&"127.0.0.1:8333".parse().unwrap(), ServiceFlags::NONE);
+    NetworkMessage::Version(VersionMessage {
+        version: 70016,
+        services: ServiceFlags::NETWORK | ServiceFlags::WITNESS,
+        timestamp: 0,
+        receiver: address.clone(),
+        sender: address,
+        nonce: 1,
+        user_agent: "/test:0.1.0/".to_string(),
+        start_height: 800_000,
+        relay: true,
+    })
+}
+
+#[test]
+fn capabilities_after_verack() {
+    let mut tracker = HandshakeTracker::new();
+    assert_eq!(tracker.observe(&version()), None);
+    assert_eq!(tracker.observe(&NetworkMessage::WtxidRelay), None);
+    assert_eq!(tracker.observe(&NetworkMessage::SendAddrV2), None);
+    assert!(tracker.capabilities().is_none());
+
+    let capabilities = tracker.observe(&NetworkMessage::Verack).unwrap();
+    assert_eq!(capabilities.version, 70016);
+    assert_eq!(capabilities.start_height, 800_000);
+    assert!(capabilities.wtxid_relay);
+    assert!(capabilities.send_addr_v2);
+    assert!(!capabilities.send_headers);
+
+    // Features negotiated after the handshake keep being recorded.
+    tracker.observe(&NetworkMessage::SendHeaders);
+    tracker.observe(&NetworkMessage::FeeFilter(1000));
+    let capabilities = tracker.capabilities().unwrap();
+    assert!(capabilities.send_headers);
+    assert_eq!(capabilities.fee_filter, Some(1000));
+}
+
+#[test]
+fn repeated_version_is_ignored() {
+    let mut tracker = HandshakeTracker::new();
+    tracker.observe(&version());
+    tracker.observe(&NetworkMessage::WtxidRelay);
+    tracker.observe(&NetworkMessage::Verack);
+
+    let mut other = version();
+    if let NetworkMessage::Version(version) = &mut other {
+        version.version = 70015;
+    }
+    assert_eq!(tracker.observe(&other), None);
+    let capabilities = tracker.capabilities().unwrap();
+    assert_eq!(capabilities.version, 70016);
+    assert!(capabilities.wtxid_relay);
+}
+
+#[test]
+fn handshake_only_features_after_verack_are_ignored() {
+    let mut tracker = HandshakeTracker::new();
+    tracker.observe(&version());
+    tracker.observe(&NetworkMessage::Verack);
+    tracker.observe(&NetworkMessage::WtxidRelay);
+    tracker.observe(&NetworkMessage::SendAddrV2);
+
+    let capabilities = tracker.capabilities().unwrap();
+    assert!(!capabilities.wtxid_relay);
+    assert!(!capabilities.send_addr_v2);
+}
+
+#[test]
+fn compliant_handshake_passes_checks() {
+    let mut tracker = HandshakeTracker::new();
+    for message in [
+        version(),
+        NetworkMessage::WtxidRelay,
+        NetworkMessage::SendAddrV2,
+        NetworkMessage::Verack,
+        NetworkMessage::SendHeaders,
+        NetworkMessage::Ping(1),
+    ] {
+        assert_eq!(tracker.check(&message), Ok(()));
+        tracker.observe(&message);
+    }
+}
+
+#[test]
+fn out_of_order_negotiation_is_flagged() {
+    let mut tracker = HandshakeTracker::new();
+    assert_eq!(
+        tracker.check(&NetworkMessage::WtxidRelay),
+        Err(HandshakeViolation::BeforeVersion(
+            NetworkMessage::WtxidRelay.command()
+        ))
+    );
+    assert_eq!(
+        tracker.check(&NetworkMessage::Verack),
+        Err(HandshakeViolation::BeforeVersion(
+            NetworkMessage::Verack.command()
+        ))
+    );
+
+    tracker.observe(&version());
+    assert_eq!(
+        tracker.check(&version()),
+        Err(HandshakeViolation::DuplicateVersion)
+    );
+    tracker.observe(&NetworkMessage::Verack);
+    assert_eq!(
+        tracker.check(&NetworkMessage::Verack),
+        Err(HandshakeViolation::DuplicateVerack)
+    );
+    for message in [NetworkMessage::WtxidRelay, NetworkMessage::SendAddrV2] {
+        assert_eq!(
+            tracker.check(&message),
+            Err(HandshakeViolation::AfterVerack(message.command()))
+        );
+    }
+}
+
+fn encode(messages: &[NetworkMessage]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for message in messages {
+        V1MessageEncoder::encode_into(Network::Bitcoin, message, &mut bytes);
+    }
+    bytes
+}
+
+#[test]
+fn handshake_decoder_delegates_once_complete() {
+    let sendtxrcncl = NetworkMessage::Unknown {
+        command: "sendtxrcncl".try_into().unwrap(),
+        payload: vec![1, 0, 0, 0, 7, 0, 0, 0, 0, 0, 0, 0],
+    };
+    let messages = [
+        version(),
+        NetworkMessage::WtxidRelay,
+        sendtxrcncl,
+        NetworkMessage::SendAddrV2,
+        NetworkMessage::Verack,
+        NetworkMessage::GetData(Vec::new()),
+        NetworkMessage::Ping(1),
+    ];
+    let bytes = encode(&messages);
+
+    let mut decoder = HandshakeDecoder::new(V1MessageDecoder::new(Network::Bitcoin));
+    let mut reader = &bytes[..];
+    for message in &messages {
+        assert_eq!(&(&mut decoder).decode_sync(&mut reader).unwrap(), message);
+    }
+    assert!(decoder.is_complete());
+    assert!(decoder.capabilities().unwrap().wtxid_relay);
+}
+
+#[test]
+fn handshake_decoder_rejects_messages_before_verack() {
+    for messages in [
+        vec![NetworkMessage::Ping(1)],
+        vec![version(), NetworkMessage::GetData(Vec::new())],
+        vec![version(), version()],
+    ] {
+        let bytes = encode(&messages);
+        let mut decoder = HandshakeDecoder::new(V1MessageDecoder::new(Network::Bitcoin));
+        let mut reader = &bytes[..];
+        for _ in 1..messages.len() {
+            (&mut decoder).decode_sync(&mut reader).unwrap();
+        }
+        let last = messages.last().unwrap();
+        assert!(matches!(
+            (&mut decoder).decode_sync(&mut reader),
+            Err(push_decode::ReadError::Decode(DecodeError::UnexpectedDuringHandshake { command }))
+                if command == last.command()
+        ));
+        assert!(!decoder.is_complete());
+    }
+}
@@ -0,0 +1,2173 @@
+use bitcoin::hashes::{sha256, Hash, HashEngine};
+use bitcoin::p2p::message::{CommandString, NetworkMessage, RawNetworkMessage};
+use bitcoin::p2p::Magic;
+use bitcoin::Network;
+use bitcoin_codecs::{
+    decode_header, BlockMessage, BufferPool, ByteBudget, Checksum, CommandMetrics, CommandTimings,
+    DecodeError, DecodeErrorKind, DecoderExt, Dispatcher, EncodeError, EncoderExt, HeaderDecoder,
+    HeaderlessDecoder, LengthLimits, Map, MeteredDecoder, PayloadParser, ProgressDecoder,
+    RateLimiter, RingBuffer, Sha256d, StallGuard, TimedDecoder, V1BlockDecoder, V1MessageDecoder,
+    V1MessageEncoder, V1ParserDecoder, V1StreamingDecoder, HEADER_SIZE, MAX_BLOOM_FILTER_SIZE,
+    MAX_BLOOM_HASH_FUNCS, MAX_FILTER_ADD_SIZE, MAX_INV_SIZE, MAX_PAYLOAD_SIZE,
+};
+use push_decode::{decode_sync_with, Decoder, Encoder, ReadError};
+use std::time::Duration;
+
+fn encode(message: &NetworkMessage) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    V1MessageEncoder::new(Network::Bitcoin, message).write_to_vec(&mut bytes);
+    bytes
+}
+
+fn decode(bytes: &[u8]) -> Result<NetworkMessage, ReadError<DecodeError>> {
+    decode_sync_with(&mut &bytes[..], V1MessageDecoder::new(Network::Bitcoin))
+}
+
+fn unknown(len: usize) -> NetworkMessage {
+    NetworkMessage::Unknown {
+        command: CommandString::try_from_static("bigmsg").unwrap(),
+        payload: vec![0xab; len],
+    }
+}
+
+#[test]
+fn round_trip_ping() {
+    let message = NetworkMessage::Ping(0x1234567890abcdef);
+    let bytes = encode(&message);
+    assert_eq!(bytes.len(), 24 + 8);
+    assert_eq!(decode(&bytes).unwrap(), message);
+}
+
+#[test]
+fn encode_empty_payload_tracked() {
+    // Drives the encoder chunk by chunk, which `write_to_vec` does not.
+    let mut bytes = Vec::new();
+    V1MessageEncoder::new(Network::Bitcoin, &NetworkMessage::Verack)
+        .track_position()
+        .write_all(&mut bytes)
+        .unwrap();
+    assert_eq!(bytes.len(), 24);
+    assert_eq!(decode(&bytes).unwrap(), NetworkMessage::Verack);
+}
+
+#[test]
+fn round_trip_addrv2() {
+    use bitcoin::p2p::address::{AddrV2, AddrV2Message};
+    use bitcoin::p2p::ServiceFlags;
+
+    let addresses = [
+        AddrV2::Ipv4("1.2.3.4".parse().unwrap()),
+        AddrV2::Ipv6("2001:db8::1".parse().unwrap()),
+        AddrV2::TorV3([0x53; 32]),
+        AddrV2::I2p([0xa2; 32]),
+        AddrV2::Cjdns("fc00:1:2:3:4:5:6:7".parse().unwrap()),
+        // Network ids beyond BIP-155 are kept raw.
+        AddrV2::Unknown(170, vec![1, 2, 3, 4]),
+    ];
+    let message = NetworkMessage::AddrV2(
+        addresses
+            .into_iter()
+            .map(|addr| AddrV2Message {
+                time: 0x4966bc61,
+                services: ServiceFlags::NETWORK | ServiceFlags::WITNESS,
+                addr,
+                port: 8333,
+            })
+            .collect(),
+    );
+    let bytes = encode(&message);
+    assert_eq!(decode(&bytes).unwrap(), message);
+
+    // The buffered path must agree with decoding in place.
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    let mut decoded = None;
+    for byte in bytes.chunks(1) {
+        decoded = decoder.feed(&mut &byte[..]).unwrap().or(decoded);
+    }
+    assert_eq!(decoded, Some(message));
+}
+
+#[test]
+fn addrv2_wrong_address_length_is_rejected() {
+    use bitcoin::p2p::address::{AddrV2, AddrV2Message};
+    use bitcoin::p2p::ServiceFlags;
+
+    let message = NetworkMessage::AddrV2(vec![AddrV2Message {
+        time: 0,
+        services: ServiceFlags::NETWORK,
+        addr: AddrV2::TorV3([0x53; 32]),
+        port: 8333,
+    }]);
+    let mut bytes = encode(&message);
+    // count, time, services, network id, then the address length.
+    assert_eq!(bytes[24 + 7], 32);
+    bytes[24 + 7] = 31;
+    let result = decode_sync_with(
+        &mut &bytes[..],
+        V1MessageDecoder::without_checksum(Network::Bitcoin),
+    );
+    assert!(matches!(
+        result,
+        Err(ReadError::Decode(DecodeError::InvalidPayload(_)))
+    ));
+}
+
+#[test]
+fn round_trip_headers() {
+    let genesis = bitcoin::blockdata::constants::genesis_block(Network::Bitcoin).header;
+    let message = NetworkMessage::Headers(vec![genesis; 3]);
+    assert_eq!(decode(&encode(&message)).unwrap(), message);
+}
+
+#[test]
+fn round_trip_empty_payload() {
+    let bytes = encode(&NetworkMessage::Verack);
+    assert_eq!(bytes.len(), HEADER_SIZE);
+    assert_eq!(decode(&bytes).unwrap(), NetworkMessage::Verack);
+}
+
+// The first four bytes of sha256d of an empty payload.
+const EMPTY_CHECKSUM: [u8; 4] = [0x5d, 0xf6, 0xe0, 0xe2];
+
+#[test]
+fn empty_payload_has_canonical_checksum() {
+    let bytes = encode(&NetworkMessage::Verack);
+    assert_eq!(bytes[16..20], [0; 4]);
+    assert_eq!(bytes[20..24], EMPTY_CHECKSUM);
+    assert_eq!(Sha256d::checksum(&[]), EMPTY_CHECKSUM);
+}
+
+#[test]
+fn empty_payload_with_wrong_checksum_fails() {
+    let mut bytes = encode(&NetworkMessage::Verack);
+    bytes[23] ^= 0x01;
+    match decode(&bytes) {
+        Err(ReadError::Decode(DecodeError::InvalidChecksum {
+            expected, computed, ..
+        })) => {
+            assert_eq!(expected, bytes[20..24]);
+            assert_eq!(computed, EMPTY_CHECKSUM);
+        }
+        other => panic!("unexpected result: {other:?}"),
+    }
+
+    // Likewise when the header arrives a byte at a time.
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    for byte in bytes[..23].chunks(1) {
+        assert_eq!(decoder.feed(&mut &byte[..]).unwrap(), None);
+    }
+    assert!(matches!(
+        decoder.feed(&mut &bytes[23..]),
+        Err(DecodeError::InvalidChecksum { .. })
+    ));
+}
+
+#[test]
+fn empty_payload_leaves_next_header() {
+    let ping = encode(&NetworkMessage::Ping(5));
+    let mut bytes = encode(&NetworkMessage::Verack);
+    bytes.extend_from_slice(&ping);
+
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    let mut chunk = &bytes[..];
+    assert_eq!(
+        decoder.feed(&mut chunk).unwrap(),
+        Some(NetworkMessage::Verack)
+    );
+    assert_eq!(chunk, &ping[..]);
+
+    // The blocking driver stops at the same place.
+    let mut reader = &bytes[..];
+    assert_eq!(
+        (&mut decoder).decode_sync(&mut reader).unwrap(),
+        NetworkMessage::Verack
+    );
+    assert_eq!(reader, &ping[..]);
+    assert_eq!(
+        (&mut decoder).decode_sync(&mut reader).unwrap(),
+        NetworkMessage::Ping(5)
+    );
+}
+
+#[test]
+fn frame_keeps_raw_command() {
+    let message = NetworkMessage::Unknown {
+        command: CommandString::try_from_static("sendtxrcncl").unwrap(),
+        payload: vec![0x01, 0x00, 0x00, 0x00, 0x2a, 0, 0, 0, 0, 0, 0, 0],
+    };
+    let bytes = encode(&message);
+
+    let frame = V1MessageDecoder::new(Network::Bitcoin)
+        .into_frame_decoder()
+        .decode_sync(&mut &bytes[..])
+        .unwrap();
+    assert_eq!(frame.header.command.as_ref(), "sendtxrcncl");
+    assert_eq!(frame.message.cmd(), "unknown");
+    assert_eq!(frame.message, message);
+}
+
+#[test]
+fn wire_decoder_returns_frames_as_received() {
+    let ping = encode(&NetworkMessage::Ping(1));
+    let pong = encode(&NetworkMessage::Pong(2));
+    let verack = encode(&NetworkMessage::Verack);
+    let bytes = [&ping[..], &pong, &verack].concat();
+
+    let mut decoder =
+        V1MessageDecoder::with_commands(Network::Bitcoin, &["pong", "verack"]).into_wire_decoder();
+    for capacity in [1, 5, bytes.len()] {
+        let mut reader = std::io::BufReader::with_capacity(capacity, &bytes[..]);
+        // The filtered ping is not kept for the pong which follows it.
+        assert!(matches!(
+            decode_sync_with(&mut reader, &mut decoder),
+            Err(ReadError::Decode(DecodeError::Skipped { .. }))
+        ));
+        let received = decode_sync_with(&mut reader, &mut decoder).unwrap();
+        assert_eq!(received, (NetworkMessage::Pong(2), pong.clone()));
+        let received = decode_sync_with(&mut reader, &mut decoder).unwrap();
+        assert_eq!(received, (NetworkMessage::Verack, verack.clone()));
+    }
+}
+
+#[test]
+fn split_payloads_reuse_pooled_buffers() {
+    use std::sync::Arc;
+
+    let pool = Arc::new(BufferPool::new(1, 1024));
+    let mut decoder = V1MessageDecoder::builder()
+        .buffer_source(pool.clone())
+        .build();
+    let ping = encode(&NetworkMessage::Ping(7));
+    let large = unknown(2048);
+    let bytes = [&ping[..], &encode(&unknown(100)), &ping, &encode(&large)].concat();
+
+    let mut messages = Vec::new();
+    for chunk in bytes.chunks(10) {
+        let mut chunk = chunk;
+        while let Some(message) = decoder.feed(&mut chunk).unwrap() {
+            // Each split payload hands back the one pooled buffer.
+            messages.push((message, pool.available()));
+            if chunk.is_empty() {
+                break;
+            }
+        }
+    }
+    assert_eq!(
+        messages,
+        [
+            (NetworkMessage::Ping(7), 1),
+            (unknown(100), 1),
+            (NetworkMessage::Ping(7), 1),
+            // The buffer which grew past the cap is dropped rather than pooled.
+            (large, 0),
+        ]
+    );
+}
+
+#[test]
+fn iter_slice_reports_frame_offsets() {
+    let ping = encode(&NetworkMessage::Ping(1));
+    let pong = encode(&NetworkMessage::Pong(1));
+    let verack = encode(&NetworkMessage::Verack);
+    let bytes = [&ping[..], &pong, &verack[..10]].concat();
+
+    let mut frames = V1MessageDecoder::iter_slice(Network::Bitcoin, &bytes);
+    let mut items = Vec::new();
+    while let Some(item) = frames.next() {
+        items.push((frames.offset(), item));
+    }
+    assert_eq!(
+        items,
+        [
+            (0, Ok(NetworkMessage::Ping(1))),
+            (32, Ok(NetworkMessage::Pong(1))),
+            (64, Err(DecodeError::IncompleteHeader)),
+        ]
+    );
+
+    // A corrupt frame ends the walk at its offset.
+    let mut corrupt = pong.clone();
+    corrupt[HEADER_SIZE] ^= 1;
+    let bytes = [&ping[..], &corrupt, &verack].concat();
+    let mut frames = V1MessageDecoder::iter_slice(Network::Bitcoin, &bytes);
+    assert_eq!(frames.next(), Some(Ok(NetworkMessage::Ping(1))));
+    assert!(matches!(
+        frames.next(),
+        Some(Err(DecodeError::InvalidChecksum { .. }))
+    ));
+    assert_eq!(frames.offset(), 32);
+    assert_eq!(frames.next(), None);
+    assert_eq!(
+        V1MessageDecoder::iter_slice(Network::Bitcoin, &[]).count(),
+        0
+    );
+}
+
+#[test]
+fn command_enum_maps_every_modeled_command() {
+    use bitcoin_codecs::Command;
+
+    let messages = [
+        NetworkMessage::Verack,
+        NetworkMessage::Ping(1),
+        NetworkMessage::Pong(1),
+        NetworkMessage::GetAddr,
+        NetworkMessage::MemPool,
+        NetworkMessage::SendHeaders,
+        NetworkMessage::FilterClear,
+        NetworkMessage::WtxidRelay,
+        NetworkMessage::SendAddrV2,
+        NetworkMessage::Inv(Vec::new()),
+        NetworkMessage::Headers(Vec::new()),
+    ];
+    for message in &messages {
+        let command = Command::from(message);
+        assert!(command.is_known(), "{command}");
+        assert_eq!(command.to_command_string(), message.command());
+    }
+    assert_eq!(Command::from(&NetworkMessage::Ping(1)), Command::Ping);
+
+    // Commands which are not modeled keep the raw string.
+    let raw = CommandString::try_from_static("sendtxrcncl").unwrap();
+    let unknown = NetworkMessage::Unknown {
+        command: raw.clone(),
+        payload: Vec::new(),
+    };
+    let command = Command::from(&unknown);
+    assert_eq!(command, Command::Unknown(raw.clone()));
+    assert!(!command.is_known());
+    assert_eq!(command.as_str(), "sendtxrcncl");
+    assert_eq!(CommandString::from(command), raw);
+    for name in ["version", "cmpctblock", "getcfcheckpt", "addrv2", "alert"] {
+        let command = Command::from_command_string(&CommandString::try_from(name).unwrap());
+        assert!(command.is_known());
+        assert_eq!(command.to_string(), name);
+    }
+}
+
+#[test]
+fn checksum_matches_framed_headers() {
+    // The well known checksum of an empty payload, as in every verack.
+    assert_eq!(bitcoin_codecs::checksum(&[]), [0x5d, 0xf6, 0xe0, 0xe2]);
+    let bytes = encode(&NetworkMessage::Ping(7));
+    assert_eq!(
+        bitcoin_codecs::checksum(&bytes[HEADER_SIZE..]),
+        bytes[20..24]
+    );
+    assert_eq!(
+        bitcoin_codecs::checksum(&bytes[HEADER_SIZE..]),
+        Sha256d::checksum(&bytes[HEADER_SIZE..])
+    );
+}
+
+#[test]
+fn round_trip_max_payload() {
+    let message = unknown(MAX_PAYLOAD_SIZE);
+    assert_eq!(decode(&encode(&message)).unwrap(), message);
+}
+
+#[test]
+fn oversized_payload_rejected() {
+    let bytes = encode(&unknown(MAX_PAYLOAD_SIZE + 1));
+    assert!(matches!(
+        decode(&bytes),
+        Err(ReadError::Decode(DecodeError::PayloadTooLarge { .. }))
+    ));
+}
+
+#[test]
+fn announced_length_of_u32_max() {
+    let mut header = encode(&unknown(0));
+    header[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+    // Announcing the most a header can must not overflow on the way to the
+    // limit check, where `u32::MAX` is also `usize::MAX` on 32-bit targets.
+    assert!(matches!(
+        decode(&header),
+        Err(ReadError::Decode(DecodeError::PayloadTooLarge {
+            length,
+            max: MAX_PAYLOAD_SIZE,
+        })) if length == u32::MAX as usize
+    ));
+
+    // Lifted limits let such a payload start, and it is buffered as it
+    // arrives rather than reserved from the header.
+    let mut decoder = V1MessageDecoder::with_max_payload(Network::Bitcoin, usize::MAX);
+    let payload = [0; 1024];
+    assert_eq!(decoder.feed(&mut &header[..]).unwrap(), None);
+    assert_eq!(decoder.feed(&mut &payload[..]).unwrap(), None);
+    assert_eq!(
+        decoder.bytes_needed(),
+        Some(u32::MAX as usize - payload.len())
+    );
+
+    let mut decoder =
+        V1MessageDecoder::with_max_payload_drained(Network::Bitcoin, MAX_PAYLOAD_SIZE);
+    assert_eq!(decoder.feed(&mut &header[..]).unwrap(), None);
+    assert_eq!(decoder.feed(&mut &payload[..]).unwrap(), None);
+    assert_eq!(
+        decoder.bytes_needed(),
+        Some(u32::MAX as usize - payload.len())
+    );
+}
+
+#[test]
+fn streaming_payload_to_sink() {
+    let message = unknown(1024);
+    let bytes = encode(&message);
+
+    let mut streamed = Vec::new();
+    let header = V1StreamingDecoder::new(Network::Bitcoin, |chunk: &[u8]| {
+        streamed.extend_from_slice(chunk)
+    })
+    .decode_sync(&mut &bytes[..])
+    .unwrap();
+
+    assert_eq!(header.length, 1024);
+    assert_eq!(streamed, bytes[24..]);
+}
+
+#[test]
+fn streaming_checks_header_without_further_bytes() {
+    let bytes = encode(&NetworkMessage::Ping(1));
+    let mut decoder = V1StreamingDecoder::new(Network::Testnet, |_: &[u8]| {});
+    assert!(matches!(
+        decoder.decode_chunk(&mut &bytes[..HEADER_SIZE]),
+        Err(DecodeError::WrongMagic { .. })
+    ));
+
+    let decoder = V1StreamingDecoder::new(Network::Bitcoin, |_: &[u8]| {});
+    assert!(matches!(decoder.end(), Err(DecodeError::EndOfStream)));
+    let mut decoder = V1StreamingDecoder::new(Network::Bitcoin, |_: &[u8]| {});
+    decoder.decode_chunk(&mut &bytes[..10]).unwrap();
+    assert!(matches!(decoder.end(), Err(DecodeError::IncompleteHeader)));
+}
+
+#[test]
+fn decoder_reused_across_messages() {
+    let messages = [
+        NetworkMessage::Ping(1),
+        NetworkMessage::Verack,
+        NetworkMessage::Pong(1),
+    ];
+    let mut bytes = Vec::new();
+    for message in &messages {
+        bytes.extend_from_slice(&encode(message));
+    }
+
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    let mut reader = &bytes[..];
+    for message in &messages {
+        assert_eq!(&(&mut decoder).decode_sync(&mut reader).unwrap(), message);
+    }
+}
+
+#[test]
+fn reset_discards_partial_message() {
+    let bytes = encode(&NetworkMessage::Ping(1));
+
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    decoder.decode_chunk(&mut &bytes[..10]).unwrap();
+    decoder.reset();
+    assert_eq!(
+        decoder.decode_sync(&mut &bytes[..]).unwrap(),
+        NetworkMessage::Ping(1)
+    );
+}
+
+#[test]
+fn feed_byte_by_byte() {
+    let messages = [NetworkMessage::Verack, NetworkMessage::Ping(7)];
+    let mut bytes = Vec::new();
+    for message in &messages {
+        bytes.extend_from_slice(&encode(message));
+    }
+
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    let mut decoded = Vec::new();
+    for byte in bytes.chunks(1) {
+        let mut chunk = byte;
+        if let Some(message) = decoder.feed(&mut chunk).unwrap() {
+            decoded.push(message);
+        }
+        assert!(chunk.is_empty());
+    }
+    assert_eq!(decoded, messages);
+}
+
+#[test]
+fn feed_leaves_following_message() {
+    let mut bytes = encode(&NetworkMessage::Ping(1));
+    bytes.extend_from_slice(&encode(&NetworkMessage::Pong(1)));
+
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    let mut chunk = &bytes[..];
+    assert_eq!(
+        decoder.feed(&mut chunk).unwrap(),
+        Some(NetworkMessage::Ping(1))
+    );
+    assert_eq!(chunk.len(), 24 + 8);
+    assert_eq!(
+        decoder.feed(&mut chunk).unwrap(),
+        Some(NetworkMessage::Pong(1))
+    );
+    assert!(chunk.is_empty());
+}
+
+#[test]
+fn invalid_checksum_reports_details() {
+    let mut bytes = encode(&NetworkMessage::Ping(1));
+    bytes[20] ^= 0xff;
+
+    match decode(&bytes) {
+        Err(ReadError::Decode(DecodeError::InvalidChecksum {
+            command,
+            expected,
+            computed,
+        })) => {
+            assert_eq!(command.as_ref(), "ping");
+            assert_eq!(expected, bytes[20..24]);
+            assert_eq!(expected[0] ^ 0xff, computed[0]);
+        }
+        other => panic!("unexpected result: {other:?}"),
+    }
+}
+
+#[test]
+fn decode_all_keeps_partial_message() {
+    let messages = [NetworkMessage::Ping(1), NetworkMessage::Verack, unknown(50)];
+    let bytes: Vec<u8> = messages.iter().flat_map(encode).collect();
+    let (first, second) = bytes.split_at(bytes.len() - 10);
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+
+    let decoded: Vec<_> = decoder.decode_all(first).map(Result::unwrap).collect();
+    assert_eq!(decoded, messages[..2]);
+    let decoded: Vec<_> = decoder.decode_all(second).map(Result::unwrap).collect();
+    assert_eq!(decoded, messages[2..]);
+}
+
+#[test]
+fn header_available_before_payload() {
+    let message = unknown(100);
+    let bytes = encode(&message);
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+
+    assert!(decoder.feed(&mut &bytes[..23]).unwrap().is_none());
+    assert!(decoder.header().is_none());
+    assert!(decoder.feed(&mut &bytes[23..24]).unwrap().is_none());
+    let header = decoder.header().unwrap();
+    assert_eq!(header.command.as_ref(), "bigmsg");
+    assert_eq!(header.length, 100);
+
+    assert_eq!(decoder.feed(&mut &bytes[24..]).unwrap(), Some(message));
+    assert!(decoder.header().is_none());
+}
+
+#[test]
+fn oversized_payload_is_drained() {
+    let mut bytes = encode(&unknown(100));
+    bytes.extend(encode(&NetworkMessage::Ping(1)));
+    let mut decoder = V1MessageDecoder::with_max_payload_drained(Network::Bitcoin, 99);
+
+    let mut reader = &bytes[..];
+    assert_eq!(
+        decoder.feed(&mut reader),
+        Err(DecodeError::PayloadTooLarge {
+            length: 100,
+            max: 99
+        })
+    );
+    assert_eq!(
+        decoder.feed(&mut reader).unwrap(),
+        Some(NetworkMessage::Ping(1))
+    );
+}
+
+#[test]
+fn recovery_steps_over_invalid_messages() {
+    let mut bytes = encode(&NetworkMessage::Ping(1));
+    let mut bad_command = encode(&NetworkMessage::Ping(2));
+    bad_command[9] = b'x';
+    bytes.extend(bad_command);
+    let mut bad_checksum = encode(&NetworkMessage::Ping(3));
+    bad_checksum[20] ^= 0xff;
+    bytes.extend(bad_checksum);
+    bytes.extend(encode(&NetworkMessage::Unknown {
+        command: CommandString::try_from_static("ping").unwrap(),
+        payload: vec![1, 2, 3],
+    }));
+    bytes.extend(encode(&NetworkMessage::Ping(4)));
+    let mut wrong_magic = Vec::new();
+    V1MessageEncoder::new(Network::Testnet, &NetworkMessage::Ping(5))
+        .write_to_vec(&mut wrong_magic);
+    bytes.extend(wrong_magic);
+    bytes.extend(encode(&NetworkMessage::Ping(6)));
+
+    // In one chunk payloads fail as they are consumed, byte by byte they fail
+    // once complete, and both leave the stream aligned.
+    for chunk_size in [bytes.len(), 1] {
+        let mut decoder = V1MessageDecoder::with_recovery(Network::Bitcoin);
+        let mut messages = Vec::new();
+        let mut warnings = Vec::new();
+        let mut error = None;
+        for chunk in bytes.chunks(chunk_size) {
+            let recovered = decoder.decode_resilient(chunk);
+            messages.extend(recovered.messages);
+            warnings.extend(recovered.warnings);
+            if recovered.error.is_some() {
+                error = recovered.error;
+                break;
+            }
+        }
+        assert_eq!(messages, [NetworkMessage::Ping(1), NetworkMessage::Ping(4)]);
+        assert!(matches!(
+            warnings[..],
+            [
+                DecodeError::CommandNotNullPadded { .. },
+                DecodeError::InvalidChecksum { .. },
+                DecodeError::InvalidPayload(_),
+            ]
+        ));
+        assert!(matches!(error, Some(DecodeError::WrongMagic { .. })));
+    }
+
+    // Without recovery the first invalid message stops decoding.
+    let recovered = V1MessageDecoder::new(Network::Bitcoin).decode_resilient(&bytes);
+    assert_eq!(recovered.messages, [NetworkMessage::Ping(1)]);
+    assert!(recovered.warnings.is_empty());
+    assert!(matches!(
+        recovered.error,
+        Some(DecodeError::CommandNotNullPadded { .. })
+    ));
+}
+
+// Single SHA256 checksum taken from the end of the digest, standing in for an
+// alt-chain's algorithm.
+struct Sha256Tail;
+
+impl Checksum for Sha256Tail {
+    type Engine = sha256::HashEngine;
+    type Digest = sha256::Hash;
+
+    fn engine() -> Self::Engine {
+        sha256::Hash::engine()
+    }
+
+    fn input(engine: &mut Self::Engine, bytes: &[u8]) {
+        engine.input(bytes);
+    }
+
+    fn finalize(engine: Self::Engine) -> Self::Digest {
+        sha256::Hash::from_engine(engine)
+    }
+
+    fn truncate(digest: &Self::Digest) -> [u8; 4] {
+        digest[28..].try_into().unwrap()
+    }
+}
+
+#[test]
+fn custom_checksum_round_trips() {
+    let magic = Magic::from_bytes([0xfb, 0xc0, 0xb6, 0xdb]);
+    let message = NetworkMessage::Ping(9);
+    let mut bytes = Vec::new();
+    V1MessageEncoder::with_checksum::<Sha256Tail>(magic, &message).write_to_vec(&mut bytes);
+    assert_eq!(bytes[20..24], Sha256Tail::checksum(&bytes[24..]));
+
+    let decoder = V1MessageDecoder::with_checksum::<Sha256Tail>(magic);
+    assert_eq!(decoder.decode_sync(&mut &bytes[..]).unwrap(), message);
+
+    // Fed byte by byte the payload is hashed as it arrives.
+    let mut decoder = V1MessageDecoder::with_checksum::<Sha256Tail>(magic);
+    let mut decoded = None;
+    for byte in bytes.chunks(1) {
+        decoded = decoder.feed(&mut &byte[..]).unwrap();
+    }
+    assert_eq!(decoded, Some(message));
+
+    // Bitcoin's checksum does not match.
+    assert!(matches!(
+        V1MessageDecoder::with_magic(magic).decode_sync(&mut &bytes[..]),
+        Err(ReadError::Decode(DecodeError::InvalidChecksum { .. }))
+    ));
+}
+
+#[test]
+fn without_checksum_accepts_bad_checksum() {
+    let message = NetworkMessage::Ping(1);
+    let mut bytes = encode(&message);
+    bytes[20] ^= 0xff;
+
+    let decoder = V1MessageDecoder::without_checksum(Network::Bitcoin);
+    assert_eq!(decoder.decode_sync(&mut &bytes[..]).unwrap(), message);
+}
+
+#[test]
+fn incremental_allocation_decodes_split_payload() {
+    let message = unknown(100_000);
+    let bytes = encode(&message);
+    for chunk_size in [1, 7, 4096, bytes.len() - 1] {
+        let mut decoder = V1MessageDecoder::with_incremental_allocation(Network::Bitcoin);
+        let mut decoded = None;
+        for mut chunk in bytes.chunks(chunk_size) {
+            decoded = decoder.feed(&mut chunk).unwrap().or(decoded);
+        }
+        assert_eq!(decoded.as_ref(), Some(&message));
+    }
+
+    // A header announcing far more than is sent still only fails at the end.
+    let truncated = &bytes[..1000];
+    let decoder = V1MessageDecoder::with_incremental_allocation(Network::Bitcoin);
+    assert!(matches!(
+        decoder.decode_sync(&mut &truncated[..]),
+        Err(ReadError::Decode(DecodeError::IncompletePayload))
+    ));
+}
+
+#[test]
+fn bytes_needed_tracks_header_and_payload() {
+    let bytes = encode(&unknown(100));
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    assert_eq!(decoder.bytes_needed(), Some(24));
+
+    let mut rest = &bytes[..];
+    while let Some(needed) = decoder.bytes_needed() {
+        let mut chunk = &rest[..needed.min(10)];
+        rest = &rest[needed.min(10)..];
+        if let Some(message) = decoder.feed(&mut chunk).unwrap() {
+            assert_eq!(message, unknown(100));
+            break;
+        }
+        assert!(chunk.is_empty());
+        match bytes.len() - rest.len() {
+            read @ 0..=23 => assert_eq!(decoder.bytes_needed(), Some(24 - read)),
+            read => assert_eq!(decoder.bytes_needed(), Some(bytes.len() - read)),
+        }
+    }
+    assert!(rest.is_empty());
+    assert_eq!(decoder.bytes_needed(), Some(24));
+
+    let mut bad_magic = encode(&NetworkMessage::Verack);
+    bad_magic[0] ^= 0xff;
+    assert!(decoder.feed(&mut &bad_magic[..]).is_err());
+    assert_eq!(decoder.bytes_needed(), None);
+    decoder.reset();
+    assert_eq!(decoder.bytes_needed(), Some(24));
+}
+
+#[test]
+fn cloned_decoder_keeps_configuration_but_not_progress() {
+    let template = V1MessageDecoder::with_commands(Network::Bitcoin, &["pong"]);
+    let mut bytes = encode(&NetworkMessage::Ping(1));
+    bytes.extend(encode(&NetworkMessage::Pong(2)));
+
+    let mut original = template.clone();
+    assert_eq!(original.feed(&mut &bytes[..10]).unwrap(), None);
+    let mut clone = original.clone();
+    assert!(clone.header().is_none());
+    assert_eq!(clone.bytes_needed(), Some(HEADER_SIZE));
+
+    // The clone filters like the template, the original picks up where it was.
+    let mut rest = &bytes[..];
+    assert!(matches!(
+        clone.feed(&mut rest),
+        Err(DecodeError::Skipped { .. })
+    ));
+    assert_eq!(
+        clone.feed(&mut rest).unwrap(),
+        Some(NetworkMessage::Pong(2))
+    );
+    let mut rest = &bytes[10..];
+    assert!(matches!(
+        original.feed(&mut rest),
+        Err(DecodeError::Skipped { .. })
+    ));
+    assert_eq!(
+        original.feed(&mut rest).unwrap(),
+        Some(NetworkMessage::Pong(2))
+    );
+}
+
+#[test]
+fn decoder_converts_from_network_and_magic() {
+    fn decode_with<D: Into<V1MessageDecoder>>(decoder: D, bytes: &[u8]) -> NetworkMessage {
+        decoder.into().decode_sync(&mut &bytes[..]).unwrap()
+    }
+
+    let bytes = encode(&NetworkMessage::Ping(3));
+    assert_eq!(
+        decode_with(Network::Bitcoin, &bytes),
+        NetworkMessage::Ping(3)
+    );
+    let networks = [Network::Bitcoin];
+    for network in networks.iter() {
+        assert_eq!(decode_with(network, &bytes), NetworkMessage::Ping(3));
+    }
+    assert_eq!(
+        decode_with(Network::Bitcoin.magic(), &bytes),
+        NetworkMessage::Ping(3)
+    );
+}
+
+// Debug formatted results of decoding a mix of valid and invalid frames.
+fn outcomes(mut decoder: V1MessageDecoder) -> Vec<String> {
+    let mut bytes = encode(&NetworkMessage::Ping(1));
+    let mut bad_checksum = encode(&NetworkMessage::Ping(2));
+    bad_checksum[20] ^= 0xff;
+    bytes.extend(bad_checksum);
+    bytes.extend(encode(&unknown(100)));
+    bytes.extend(encode(&NetworkMessage::Verack));
+
+    let mut results = Vec::new();
+    let mut reader = &bytes[..];
+    while !reader.is_empty() {
+        let result = decoder.feed(&mut reader);
+        if result.is_err() {
+            decoder.reset();
+        }
+        results.push(format!("{result:?}"));
+    }
+    results.push(format!("{decoder:?}"));
+    results
+}
+
+#[test]
+fn builder_matches_constructors() {
+    let network = Network::Testnet;
+    let pairs = [
+        (
+            V1MessageDecoder::builder().network(network),
+            V1MessageDecoder::new(network),
+        ),
+        (
+            V1MessageDecoder::builder().network(network).max_payload(99),
+            V1MessageDecoder::with_max_payload(network, 99),
+        ),
+        (
+            V1MessageDecoder::builder()
+                .network(network)
+                .verify_checksum(false),
+            V1MessageDecoder::without_checksum(network),
+        ),
+        (
+            V1MessageDecoder::builder()
+                .network(network)
+                .allowed_commands(&["ping"]),
+            V1MessageDecoder::with_commands(network, &["ping"]),
+        ),
+        (
+            V1MessageDecoder::builder()
+                .network(network)
+                .max_payload(99)
+                .drain_oversized(true),
+            V1MessageDecoder::with_max_payload_drained(network, 99),
+        ),
+        (
+            V1MessageDecoder::builder().network(network).recovery(true),
+            V1MessageDecoder::with_recovery(network),
+        ),
+    ];
+    for (builder, decoder) in pairs {
+        assert_eq!(outcomes(builder.build()), outcomes(decoder));
+    }
+    assert_eq!(
+        outcomes(V1MessageDecoder::builder().build()),
+        outcomes(V1MessageDecoder::new(Network::Bitcoin))
+    );
+}
+
+#[test]
+fn builder_combines_options() {
+    let decoder = V1MessageDecoder::builder()
+        .max_payload(99)
+        .verify_checksum(false)
+        .allowed_commands(&["ping"])
+        .build();
+    assert_eq!(
+        outcomes(decoder)[..3],
+        [
+            "Ok(Some(Ping(1)))",
+            "Ok(Some(Ping(2)))",
+            "Err(PayloadTooLarge { length: 100, max: 99 })",
+        ]
+    );
+}
+
+#[test]
+fn decoder_debug_shows_magic_and_stage() {
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    let debug = format!("{decoder:?}");
+    assert!(debug.starts_with("V1MessageDecoder {"));
+    assert!(debug.contains(&format!("{:?}", Network::Bitcoin.magic())));
+    assert!(debug.contains("stage: \"header\""));
+
+    let bytes = encode(&unknown(100));
+    assert_eq!(decoder.feed(&mut &bytes[..30]).unwrap(), None);
+    assert!(format!("{decoder:?}").contains("stage: \"payload\""));
+    assert!(format!("{:?}", decoder.into_frame_decoder()).starts_with("V1FrameDecoder {"));
+}
+
+#[test]
+fn each_network_accepts_only_its_magic() {
+    let networks = [
+        Network::Bitcoin,
+        Network::Testnet,
+        Network::Testnet4,
+        Network::Signet,
+        Network::Regtest,
+    ];
+    for sent in networks {
+        let mut bytes = Vec::new();
+        V1MessageEncoder::new(sent, &NetworkMessage::Verack).write_to_vec(&mut bytes);
+        for expected in networks {
+            let result = V1MessageDecoder::new(expected).decode_sync(&mut &bytes[..]);
+            if sent == expected {
+                assert_eq!(result.unwrap(), NetworkMessage::Verack);
+            } else {
+                match result {
+                    Err(ReadError::Decode(DecodeError::WrongMagic {
+                        expected: magic,
+                        actual,
+                        network,
+                    })) => {
+                        assert_eq!(magic, expected.magic());
+                        assert_eq!(actual, sent.magic());
+                        assert_eq!(network, Some(sent));
+                    }
+                    other => panic!("unexpected result: {other:?}"),
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn length_limits_reject_impossible_lengths() {
+    // A verack announcing a payload, rejected from the header alone.
+    let mut verack = encode(&NetworkMessage::Verack);
+    verack[16] = 1;
+    let mut decoder =
+        V1MessageDecoder::with_length_limits(Network::Bitcoin, LengthLimits::default());
+    match decoder.feed(&mut &verack[..]) {
+        Err(DecodeError::UnexpectedLength { command, length }) => {
+            assert_eq!(command.as_ref(), "verack");
+            assert_eq!(length, 1);
+        }
+        other => panic!("unexpected result: {other:?}"),
+    }
+
+    let pong = NetworkMessage::Unknown {
+        command: CommandString::try_from_static("pong").unwrap(),
+        payload: vec![0; 4],
+    };
+    let result = V1MessageDecoder::with_length_limits(Network::Bitcoin, LengthLimits::default())
+        .decode_sync(&mut &encode(&pong)[..]);
+    assert!(matches!(
+        result,
+        Err(ReadError::Decode(DecodeError::UnexpectedLength {
+            length: 4,
+            ..
+        }))
+    ));
+    assert!(!LengthLimits::default().allows("headers", 3 + 2001 * 81));
+
+    // Valid messages and commands without an entry are unaffected.
+    let mut bytes = encode(&NetworkMessage::Ping(1));
+    bytes.extend(encode(&unknown(10)));
+    let mut limits = LengthLimits::default();
+    limits.set("bigmsg", 0..=10).remove("ping");
+    let mut decoder = V1MessageDecoder::with_length_limits(Network::Bitcoin, limits);
+    let decoded: Vec<_> = decoder.decode_all(&bytes).map(Result::unwrap).collect();
+    assert_eq!(decoded, [NetworkMessage::Ping(1), unknown(10)]);
+}
+
+#[test]
+fn ping_claiming_nine_bytes_is_rejected_from_its_header() {
+    let ping = NetworkMessage::Unknown {
+        command: CommandString::try_from_static("ping").unwrap(),
+        payload: vec![0; 9],
+    };
+    let bytes = encode(&ping);
+    let mut decoder = V1MessageDecoder::builder()
+        .length_limits(LengthLimits::default())
+        .build();
+    // None of the payload has to arrive for the error.
+    let mut header = &bytes[..HEADER_SIZE];
+    match decoder.feed(&mut header) {
+        Err(DecodeError::UnexpectedLength { command, length }) => {
+            assert_eq!(command.as_ref(), "ping");
+            assert_eq!(length, 9);
+        }
+        other => panic!("unexpected result: {other:?}"),
+    }
+}
+
+// Reader which fails the test if it is asked for bytes once it is drained.
+struct NoWaitReader<'a>(&'a [u8]);
+
+impl std::io::Read for NoWaitReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl std::io::BufRead for NoWaitReader<'_> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        assert!(!self.0.is_empty(), "read past the end of the frame");
+        Ok(self.0)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.0 = &self.0[amt..];
+    }
+}
+
+#[test]
+fn read_message_stops_at_frame() {
+    let mut bytes = encode(&NetworkMessage::Ping(1));
+    bytes.extend(encode(&NetworkMessage::Verack));
+    bytes.extend_from_slice(b"not a frame");
+
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    let mut reader = &bytes[..];
+    assert_eq!(
+        decoder.read_message(&mut reader).unwrap(),
+        NetworkMessage::Ping(1)
+    );
+    assert_eq!(
+        decoder.read_message(&mut reader).unwrap(),
+        NetworkMessage::Verack
+    );
+    assert_eq!(reader, b"not a frame");
+
+    // Nothing past the frame is requested, so a live connection never blocks.
+    let bytes = encode(&unknown(100));
+    let mut reader = std::io::BufReader::with_capacity(7, NoWaitReader(&bytes));
+    assert_eq!(decoder.read_message(&mut reader).unwrap(), unknown(100));
+
+    // Running out part way through reports which part was cut short.
+    let mut reader = &bytes[..50];
+    assert!(matches!(
+        decoder.read_message(&mut reader),
+        Err(ReadError::Decode(DecodeError::IncompletePayload))
+    ));
+    assert!(matches!(
+        decoder.read_message(&mut &bytes[..10]),
+        Err(ReadError::Decode(DecodeError::IncompleteHeader))
+    ));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn read_message_tokio_stops_at_frame() {
+    use tokio::io::AsyncWriteExt;
+
+    let mut bytes = encode(&NetworkMessage::Ping(1));
+    bytes.extend(encode(&NetworkMessage::Verack));
+    // The writer stays open, so waiting for more bytes would hang.
+    let (mut writer, reader) = tokio::io::duplex(1024);
+    writer.write_all(&bytes).await.unwrap();
+    let mut reader = tokio::io::BufReader::new(reader);
+
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    assert_eq!(
+        decoder.read_message_tokio(&mut reader).await.unwrap(),
+        NetworkMessage::Ping(1)
+    );
+    assert_eq!(
+        decoder.read_message_tokio(&mut reader).await.unwrap(),
+        NetworkMessage::Verack
+    );
+    drop(writer);
+}
+
+#[test]
+fn trailing_payload_bytes_are_reported() {
+    let ping = NetworkMessage::Unknown {
+        command: CommandString::try_from_static("ping").unwrap(),
+        payload: vec![1, 0, 0, 0, 0, 0, 0, 0, 0xaa, 0xbb],
+    };
+    let bytes = encode(&ping);
+    assert!(matches!(
+        decode(&bytes),
+        Err(ReadError::Decode(DecodeError::TrailingPayloadBytes(2)))
+    ));
+
+    let decoder = V1MessageDecoder::with_trailing_bytes_ignored(Network::Bitcoin);
+    assert_eq!(
+        decoder.decode_sync(&mut &bytes[..]).unwrap(),
+        NetworkMessage::Ping(1)
+    );
+
+    // The fallback still hands back the raw payload.
+    let decoder = V1MessageDecoder::with_unknown_fallback(Network::Bitcoin);
+    assert_eq!(decoder.decode_sync(&mut &bytes[..]).unwrap(), ping);
+}
+
+#[test]
+fn raw_message_keeps_foreign_magic() {
+    let magic = Magic::from_bytes([0x0a, 0x03, 0xcf, 0x40]);
+    let raw = RawNetworkMessage::new(magic, NetworkMessage::Ping(11));
+    let mut bytes = Vec::new();
+    V1MessageEncoder::from_raw(&raw)
+        .encode_sync(&mut bytes)
+        .unwrap();
+    assert_eq!(bytes[..4], magic.to_bytes());
+
+    let frame = V1MessageDecoder::with_magic(magic)
+        .into_frame_decoder()
+        .decode_sync(&mut &bytes[..])
+        .unwrap();
+    assert_eq!(frame.header.magic, magic);
+    let relayed = RawNetworkMessage::from(frame);
+    assert_eq!(relayed, raw);
+
+    let mut reencoded = Vec::new();
+    V1MessageEncoder::from_raw(&relayed).write_to_vec(&mut reencoded);
+    assert_eq!(reencoded, bytes);
+}
+
+#[test]
+fn wrong_magic_names_the_likely_network() {
+    let mut bytes = encode(&NetworkMessage::Verack);
+    bytes[..4].copy_from_slice(&Network::Testnet.magic().to_bytes());
+    match decode(&bytes) {
+        Err(ReadError::Decode(err)) => {
+            assert!(err.to_string().ends_with("(testnet magic)"), "{err}")
+        }
+        other => panic!("unexpected result: {other:?}"),
+    }
+
+    bytes[..4].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+    match decode(&bytes) {
+        Err(ReadError::Decode(DecodeError::WrongMagic {
+            actual, network, ..
+        })) => {
+            assert_eq!(actual.to_bytes(), [0xde, 0xad, 0xbe, 0xef]);
+            assert_eq!(network, None);
+        }
+        other => panic!("unexpected result: {other:?}"),
+    }
+}
+
+#[test]
+fn foreign_protocol_is_told_apart_from_wrong_network() {
+    let detecting = || {
+        V1MessageDecoder::builder()
+            .detect_foreign_protocol(true)
+            .build()
+    };
+    let request = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+    let error = detecting().feed(&mut &request[..]).unwrap_err();
+    assert_eq!(error, DecodeError::NotBitcoinProtocol { bytes: *b"GET " });
+    assert_eq!(error.kind().as_str(), "not_bitcoin_protocol");
+    assert_eq!(
+        error.to_string(),
+        "not a bitcoin stream, first bytes 47455420"
+    );
+
+    let mut testnet = encode(&NetworkMessage::Verack);
+    testnet[..4].copy_from_slice(&Network::Testnet.magic().to_bytes());
+    assert!(matches!(
+        detecting().feed(&mut &testnet[..]),
+        Err(DecodeError::WrongMagic {
+            network: Some(Network::Testnet),
+            ..
+        })
+    ));
+
+    // Past the first header a bad magic is lost framing, not another protocol.
+    let mut decoder = detecting();
+    let verack = encode(&NetworkMessage::Verack);
+    assert_eq!(
+        decoder.feed(&mut &verack[..]).unwrap(),
+        Some(NetworkMessage::Verack)
+    );
+    assert!(matches!(
+        decoder.feed(&mut &request[..]),
+        Err(DecodeError::WrongMagic { network: None, .. })
+    ));
+}
+
+#[test]
+fn errors_box_as_core_error() {
+    fn decode_boxed(bytes: &[u8]) -> Result<NetworkMessage, Box<dyn core::error::Error>> {
+        let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+        Ok(decoder.feed(&mut &bytes[..])?.ok_or("incomplete")?)
+    }
+
+    let mut bytes = encode(&NetworkMessage::Ping(1));
+    bytes[20] ^= 0xff;
+    let error = decode_boxed(&bytes).unwrap_err();
+    assert!(error
+        .to_string()
+        .starts_with("checksum verification failed"));
+}
+
+#[test]
+fn command_errors_are_distinguished() {
+    let mut bytes = encode(&NetworkMessage::Ping(1));
+    bytes[9] = b'x';
+    assert!(matches!(
+        decode(&bytes),
+        Err(ReadError::Decode(DecodeError::CommandNotNullPadded { .. }))
+    ));
+
+    let mut bytes = encode(&NetworkMessage::Ping(1));
+    bytes[5] = 0xe9;
+    assert!(matches!(
+        decode(&bytes),
+        Err(ReadError::Decode(DecodeError::CommandNotAscii { .. }))
+    ));
+}
+
+#[test]
+fn headerless_skips_magic() {
+    let message = NetworkMessage::Ping(42);
+    // A regtest frame shows the magic is not checked once it is stripped.
+    let mut bytes = Vec::new();
+    V1MessageEncoder::new(Network::Regtest, &message).write_to_vec(&mut bytes);
+
+    let decoded = HeaderlessDecoder::new()
+        .decode_sync(&mut &bytes[4..])
+        .unwrap();
+    assert_eq!(decoded, message);
+}
+
+#[test]
+fn headerless_checks_header_without_further_bytes() {
+    let bytes = encode(&NetworkMessage::Ping(1));
+    let mut decoder = HeaderlessDecoder::with_max_payload(4);
+    assert!(matches!(
+        decoder.decode_chunk(&mut &bytes[4..HEADER_SIZE]),
+        Err(DecodeError::PayloadTooLarge { length: 8, max: 4 })
+    ));
+
+    assert!(matches!(
+        HeaderlessDecoder::new().end(),
+        Err(DecodeError::EndOfStream)
+    ));
+    let mut decoder = HeaderlessDecoder::new();
+    decoder.decode_chunk(&mut &bytes[4..10]).unwrap();
+    assert!(matches!(decoder.end(), Err(DecodeError::IncompleteHeader)));
+}
+
+#[test]
+fn clean_close_is_end_of_stream() {
+    assert!(matches!(
+        decode(&[]),
+        Err(ReadError::Decode(DecodeError::EndOfStream))
+    ));
+
+    let mut bytes = encode(&NetworkMessage::Ping(1));
+    bytes.extend(encode(&NetworkMessage::Verack));
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    let mut reader = &bytes[..];
+    let mut messages = Vec::new();
+    let end = loop {
+        match (&mut decoder).decode_sync(&mut reader) {
+            Ok(message) => messages.push(message),
+            Err(e) => break e,
+        }
+    };
+    assert_eq!(messages, [NetworkMessage::Ping(1), NetworkMessage::Verack]);
+    assert!(matches!(end, ReadError::Decode(DecodeError::EndOfStream)));
+
+    // The same boundary is seen by read_message.
+    assert!(matches!(
+        decoder.read_message(&mut &[][..]),
+        Err(ReadError::Decode(DecodeError::EndOfStream))
+    ));
+}
+
+#[test]
+fn truncated_header_and_payload_are_distinguished() {
+    let bytes = encode(&NetworkMessage::Ping(1));
+    assert!(matches!(
+        decode(&bytes[..10]),
+        Err(ReadError::Decode(DecodeError::IncompleteHeader))
+    ));
+    assert!(matches!(
+        decode(&bytes[..28]),
+        Err(ReadError::Decode(DecodeError::IncompletePayload))
+    ));
+}
+
+#[test]
+fn filtered_commands_are_skipped() {
+    let genesis = bitcoin::blockdata::constants::genesis_block(Network::Bitcoin).header;
+    let mut bytes = encode(&NetworkMessage::Ping(1));
+    bytes.extend_from_slice(&encode(&NetworkMessage::Headers(vec![genesis])));
+
+    let mut decoder = V1MessageDecoder::with_commands(Network::Bitcoin, &["headers", "inv"]);
+    let mut reader = &bytes[..];
+    match (&mut decoder).decode_sync(&mut reader) {
+        Err(ReadError::Decode(DecodeError::Skipped { command })) => {
+            assert_eq!(command.as_ref(), "ping")
+        }
+        other => panic!("unexpected result: {other:?}"),
+    }
+    assert_eq!(
+        (&mut decoder).decode_sync(&mut reader).unwrap(),
+        NetworkMessage::Headers(vec![genesis])
+    );
+}
+
+#[test]
+fn metered_counts_per_command() {
+    let mut bytes = encode(&NetworkMessage::Ping(1));
+    bytes.extend_from_slice(&encode(&NetworkMessage::Ping(2)));
+    bytes.extend_from_slice(&encode(&NetworkMessage::Verack));
+
+    let mut decoder = MeteredDecoder::new(V1MessageDecoder::new(Network::Bitcoin));
+    let mut reader = &bytes[..];
+    for _ in 0..3 {
+        (&mut decoder).decode_sync(&mut reader).unwrap();
+    }
+
+    let metrics = decoder.snapshot();
+    assert_eq!(metrics.bytes, bytes.len() as u64);
+    assert_eq!(
+        metrics.commands["ping"],
+        CommandMetrics {
+            messages: 2,
+            bytes: 2 * (24 + 8)
+        }
+    );
+    assert_eq!(
+        metrics.commands["verack"],
+        CommandMetrics {
+            messages: 1,
+            bytes: 24
+        }
+    );
+}
+
+#[test]
+fn timed_records_first_byte_to_end() {
+    let now = core::cell::Cell::new(Duration::ZERO);
+    let mut decoder = TimedDecoder::new(V1MessageDecoder::new(Network::Bitcoin), || now.get());
+    for (message, millis) in [
+        (NetworkMessage::Ping(1), 5),
+        (NetworkMessage::Ping(2), 1),
+        (NetworkMessage::Verack, 3),
+    ] {
+        let bytes = encode(&message);
+        let (first, second) = bytes.split_at(10);
+        (&mut decoder).decode_chunk(&mut &first[..]).unwrap();
+        now.set(now.get() + Duration::from_millis(millis));
+        (&mut decoder).decode_chunk(&mut &second[..]).unwrap();
+        assert_eq!((&mut decoder).end().unwrap(), message);
+        // Idle time between messages is not counted.
+        now.set(now.get() + Duration::from_secs(1));
+    }
+
+    let timings = decoder.snapshot();
+    assert_eq!(
+        timings.commands["ping"],
+        CommandTimings {
+            messages: 2,
+            min: Duration::from_millis(1),
+            max: Duration::from_millis(5),
+            total: Duration::from_millis(6),
+        }
+    );
+    assert_eq!(timings.commands["ping"].average(), Duration::from_millis(3));
+    assert_eq!(timings.commands["verack"].messages, 1);
+}
+
+#[test]
+fn stalled_message_is_cut_off() {
+    let bytes = encode(&unknown(100));
+    let mut seen = Vec::new();
+    // Stands in for a clock, each message may take at most three chunks.
+    let mut chunks = 0;
+    let mut guard = StallGuard::new(V1MessageDecoder::new(Network::Bitcoin), |received| {
+        seen.push(received);
+        if received == 0 {
+            chunks = 0;
+        }
+        chunks += 1;
+        chunks <= 3
+    });
+
+    let mut decoder = &mut guard;
+    decoder.decode_chunk(&mut &bytes[..10]).unwrap();
+    decoder.decode_chunk(&mut &bytes[10..20]).unwrap();
+    decoder.decode_chunk(&mut &bytes[20..30]).unwrap();
+    match decoder.decode_chunk(&mut &bytes[30..40]) {
+        Err(DecodeError::Stalled { received }) => assert_eq!(received, 30),
+        other => panic!("unexpected result: {other:?}"),
+    }
+    drop(guard);
+    assert_eq!(seen, [0, 10, 20, 30]);
+}
+
+#[test]
+fn rate_limiter_enforces_quotas_per_window() {
+    let mut bytes = Vec::new();
+    for message in [
+        NetworkMessage::Ping(1),
+        NetworkMessage::Ping(2),
+        NetworkMessage::Verack,
+        NetworkMessage::Pong(1),
+        NetworkMessage::Ping(3),
+    ] {
+        bytes.extend(encode(&message));
+    }
+    // Stands in for a clock, advanced by the test between messages.
+    let window = std::cell::Cell::new(0);
+    let mut limiter = RateLimiter::new(V1MessageDecoder::new(Network::Bitcoin), 3, || window.get());
+    limiter.limit("ping", 1);
+
+    let mut reader = &bytes[..];
+    assert_eq!(
+        (&mut limiter).decode_sync(&mut reader).unwrap(),
+        NetworkMessage::Ping(1)
+    );
+    match (&mut limiter).decode_sync(&mut reader) {
+        Err(ReadError::Decode(DecodeError::RateLimited { command })) => {
+            assert_eq!(command.as_ref(), "ping")
+        }
+        other => panic!("unexpected result: {other:?}"),
+    }
+    // The rejected message still counts towards the overall maximum.
+    assert!(matches!(
+        (&mut limiter).decode_sync(&mut reader),
+        Ok(NetworkMessage::Verack)
+    ));
+    assert!(matches!(
+        (&mut limiter).decode_sync(&mut reader),
+        Err(ReadError::Decode(DecodeError::RateLimited { .. }))
+    ));
+
+    window.set(1);
+    assert_eq!(
+        (&mut limiter).decode_sync(&mut reader).unwrap(),
+        NetworkMessage::Ping(3)
+    );
+}
+
+#[test]
+fn byte_budget_trips_within_window() {
+    // Each ping takes 32 bytes, so two fit in the budget of a window.
+    let ping = encode(&NetworkMessage::Ping(1));
+    let window = std::cell::Cell::new(0);
+    let mut budget = ByteBudget::new(V1MessageDecoder::new(Network::Bitcoin), 64, || window.get());
+
+    for current in 0..2 {
+        window.set(current);
+        for _ in 0..2 {
+            assert_eq!(
+                feed_message(&mut budget, &ping, 8).unwrap(),
+                NetworkMessage::Ping(1)
+            );
+        }
+        assert_eq!(budget.received(), 64);
+    }
+    // The budget trips on the first chunk over it, part way through the message.
+    let mut chunk = &ping[..8];
+    assert_eq!(
+        (&mut budget).decode_chunk(&mut chunk),
+        Err(DecodeError::ByteBudgetExceeded { budget: 64 })
+    );
+}
+
+#[test]
+fn progress_reported_per_chunk() {
+    let message = unknown(1000);
+    let bytes = encode(&message);
+    let mut reports = Vec::new();
+    let mut decoder = ProgressDecoder::new(
+        V1MessageDecoder::new(Network::Bitcoin),
+        |received, total| reports.push((received, total)),
+    );
+
+    let mut reader = std::io::BufReader::with_capacity(300, &bytes[..]);
+    assert_eq!((&mut decoder).decode_sync(&mut reader).unwrap(), message);
+    drop(decoder);
+    // The header fills the first read, then every chunk is reported.
+    assert_eq!(
+        reports,
+        [(276, 1000), (576, 1000), (876, 1000), (1000, 1000)]
+    );
+
+    // A message arriving in one chunk is still reported once, complete.
+    reports.clear();
+    let mut decoder = ProgressDecoder::new(
+        V1MessageDecoder::new(Network::Bitcoin),
+        |received, total| reports.push((received, total)),
+    );
+    assert_eq!(
+        (&mut decoder).decode_sync(&mut &bytes[..]).unwrap(),
+        message
+    );
+    drop(decoder);
+    assert_eq!(reports, [(1000, 1000)]);
+}
+
+#[test]
+fn dispatcher_routes_by_command() {
+    let mut bytes = encode(&NetworkMessage::Ping(1));
+    bytes.extend(encode(&unknown(3)));
+    bytes.extend(encode(&NetworkMessage::Verack));
+
+    let mut pings = Vec::new();
+    let mut unknown_commands = Vec::new();
+    let mut other = Vec::new();
+    {
+        let mut dispatcher = Dispatcher::new(V1MessageDecoder::new(Network::Bitcoin), |message| {
+            other.push(message)
+        });
+        dispatcher
+            .on("ping", |message| pings.push(message))
+            .on("bigmsg", |message| unknown_commands.push(message));
+        let mut reader = &bytes[..];
+        for _ in 0..3 {
+            dispatcher.dispatch(&mut reader).unwrap();
+        }
+    }
+
+    assert_eq!(pings, [NetworkMessage::Ping(1)]);
+    assert_eq!(unknown_commands, [unknown(3)]);
+    assert_eq!(other, [NetworkMessage::Verack]);
+}
+
+#[test]
+fn ring_buffer_payload_wraps() {
+    let messages = [unknown(300), NetworkMessage::Ping(3)];
+    let mut bytes = Vec::new();
+    for message in &messages {
+        bytes.extend_from_slice(&encode(message));
+    }
+
+    let mut ring = RingBuffer::<32>::new();
+    let mut decoder = V1MessageDecoder::new(Network::Bitcoin);
+    let mut input = &bytes[..];
+    let mut decoded = Vec::new();
+    while !input.is_empty() {
+        let written = ring.write(&input[..input.len().min(5)]);
+        input = &input[written..];
+        while let Some(message) = ring.decode(&mut decoder).unwrap() {
+            decoded.push(message);
+        }
+    }
+    assert_eq!(decoded, messages);
+    assert!(ring.is_empty());
+}
+
+#[test]
+fn encode_into_appends_frames() {
+    let messages = [
+        NetworkMessage::Ping(7),
+        NetworkMessage::Verack,
+        unknown(100),
+    ];
+    let mut out = vec![0xff];
+    for message in &messages {
+        V1MessageEncoder::encode_into(Network::Bitcoin, message, &mut out);
+    }
+
+    let expected: Vec<u8> = core::iter::once(0xff)
+        .chain(messages.iter().flat_map(encode))
+        .collect();
+    assert_eq!(out, expected);
+}
+
+#[test]
+fn encode_to_slice_checks_length() {
+    let message = NetworkMessage::Ping(7);
+    let mut out = [0u8; 32];
+    let length = V1MessageEncoder::encode_to_slice(Network::Bitcoin, &message, &mut out).unwrap();
+    assert_eq!(&out[..length], &encode(&message)[..]);
+
+    for size in [0, 24, 31] {
+        match V1MessageEncoder::encode_to_slice(Network::Bitcoin, &message, &mut out[..size]) {
+            Err(EncodeError::BufferTooSmall { needed, available }) => {
+                assert_eq!((needed, available), (32, size));
+            }
+            other => panic!("unexpected result {other:?}"),
+        }
+    }
+}
+
+#[test]
+fn map_filters_into_caller_type() {
+    #[derive(Debug, PartialEq)]
+    enum Event {
+        Ping(u64),
+        Pong(u64),
+    }
+
+    let mut bytes = Vec::new();
+    for message in [
+        NetworkMessage::Ping(1),
+        NetworkMessage::Verack,
+        NetworkMessage::Pong(2),
+    ] {
+        bytes.extend(encode(&message));
+    }
+    let mut corrupt = encode(&NetworkMessage::Ping(3));
+    corrupt[20] ^= 0xff;
+    bytes.extend(corrupt);
+
+    let mut decoder = Map::new(
+        V1MessageDecoder::new(Network::Bitcoin),
+        |message| match message {
+            NetworkMessage::Ping(nonce) => Some(Event::Ping(nonce)),
+            NetworkMessage::Pong(nonce) => Some(Event::Pong(nonce)),
+            _ => None,
+        },
+    );
+    let mut reader = &bytes[..];
+    let mut events = Vec::new();
+    for _ in 0..3 {
+        events.extend((&mut decoder).decode_sync(&mut reader).unwrap());
+    }
+    assert_eq!(events, [Event::Ping(1), Event::Pong(2)]);
+
+    // Errors of the wrapped decoder are not mapped.
+    assert!(matches!(
+        (&mut decoder).decode_sync(&mut reader),
+        Err(ReadError::Decode(DecodeError::InvalidChecksum { .. }))
+    ));
+}
+
+#[test]
+fn auto_detect_locks_on_first_magic() {
+    let mut bytes = Vec::new();
+    for message in [NetworkMessage::Ping(1), NetworkMessage::Ping(2)] {
+        V1MessageEncoder::new(Network::Testnet, &message).write_to_vec(&mut bytes);
+    }
+    bytes.extend(encode(&NetworkMessage::Ping(3)));
+
+    let mut reader = &bytes[..];
+    let mut detected = V1MessageDecoder::auto_detect()
+        .decode_sync(&mut reader)
+        .unwrap();
+    assert_eq!(detected.network, Network::Testnet);
+    assert_eq!(detected.message, NetworkMessage::Ping(1));
+    assert_eq!(
+        (&mut detected.decoder).decode_sync(&mut reader).unwrap(),
+        NetworkMessage::Ping(2)
+    );
+    assert!(matches!(
+        (&mut detected.decoder).decode_sync(&mut reader),
+        Err(ReadError::Decode(DecodeError::WrongMagic { .. }))
+    ));
+
+    // The magic may arrive split.
+    let mut decoder = V1MessageDecoder::auto_detect();
+    for byte in bytes[..HEADER_SIZE + 8].chunks(1) {
+        decoder.decode_chunk(&mut &byte[..]).unwrap();
+    }
+    assert_eq!(decoder.end().unwrap().network, Network::Testnet);
+
+    bytes[..4].copy_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+    assert!(matches!(
+        V1MessageDecoder::auto_detect().decode_sync(&mut &bytes[..]),
+        Err(ReadError::Decode(DecodeError::UnknownMagic(magic)))
+            if magic == Magic::from_bytes([0xde, 0xad, 0xbe, 0xef])
+    ));
+}
+
+#[test]
+fn decode_errors_compare_and_hash() {
+    let mut bad_checksum = encode(&NetworkMessage::Ping(1));
+    bad_checksum[20] ^= 0xff;
+    let truncated_ping = encode(&NetworkMessage::Unknown {
+        command: CommandString::try_from_static("ping").unwrap(),
+        payload: vec![1, 2, 3],
+    });
+    let errors = [
+        &bad_checksum,
+        &bad_checksum,
+        &truncated_ping,
+        &truncated_ping,
+        &encode(&NetworkMessage::Ping(1))[..10].to_vec(),
+    ]
+    .map(|bytes| match decode(bytes) {
+        Err(ReadError::Decode(e)) => e,
+        other => panic!("unexpected result: {other:?}"),
+    });
+
+    assert_eq!(errors[0], errors[1]);
+    assert!(matches!(errors[0], DecodeError::InvalidChecksum { .. }));
+    // Payload errors are equal when their messages are.
+    assert_eq!(errors[2], errors[3]);
+    assert_ne!(errors[0], errors[2]);
+    assert_eq!(errors[4], DecodeError::IncompleteHeader);
+    assert_ne!(errors[4], DecodeError::IncompletePayload);
+    let parse_failed = |reason| {
+        DecodeError::InvalidPayload(bitcoin::consensus::encode::Error::ParseFailed(reason))
+    };
+    assert_ne!(parse_failed("one"), parse_failed("two"));
+    assert_ne!(
+        DecodeError::NotBitcoinProtocol { bytes: *b"GET " },
+        DecodeError::NotBitcoinProtocol { bytes: *b"POST" }
+    );
+    assert_eq!(DecodeError::Timeout, DecodeError::Timeout);
+
+    let unique: std::collections::HashSet<_> = errors.into_iter().collect();
+    assert_eq!(unique.len(), 3);
+}
+
+#[test]
+fn decode_error_kinds_label_errors() {
+    let mut bad_checksum = encode(&NetworkMessage::Ping(1));
+    bad_checksum[20] ^= 0xff;
+    let Err(ReadError::Decode(error)) = decode(&bad_checksum) else {
+        panic!("expected a decode error");
+    };
+    assert_eq!(error.kind(), DecodeErrorKind::InvalidChecksum);
+    assert_eq!(error.kind().as_str(), "invalid_checksum");
+    assert_eq!(DecodeError::EndOfStream.kind().to_string(), "end_of_stream");
+    assert_eq!(
+        DecodeError::TrailingPayloadBytes(3).kind(),
+        DecodeError::TrailingPayloadBytes(1).kind()
+    );
+}
+
+#[test]
+fn header_computed_from_message_matches_encoder() {
+    let message = NetworkMessage::Ping(42);
+    let bytes = encode(&message);
+
+    let header = bitcoin_codecs::Header::from_message(Network::Bitcoin, &message);
+    assert_eq!(header.to_bytes(), bytes[..HEADER_SIZE]);
+    assert_eq!(header.length as usize, bytes.len() - HEADER_SIZE);
+    assert_eq!(
+        decode_header(&header.to_bytes(), Network::Bitcoin).unwrap(),
+        header
+    );
+
+    let raw = RawNetworkMessage::new(Network::Signet.magic(), message.clone());
+    let frame = bitcoin_codecs::Frame::from(raw.clone());
+    assert_eq!(frame.header, bitcoin_codecs::Header::from(&raw));
+    assert_eq!(frame.header.magic, Network::Signet.magic());
+    assert_eq!(frame.header.checksum, header.checksum);
+    assert_eq!(frame.message, message);
+    assert_eq!(RawNetworkMessage::from(frame), raw);
+}
+
+#[test]
+fn header_verifies_payload_held_elsewhere() {
+    let message = NetworkMessage::Ping(42);
+    let bytes = encode(&message);
+    let (header, payload) = bytes.split_at(HEADER_SIZE);
+    let header = HeaderDecoder::new(Network::Bitcoin)
+        .decode_sync(&mut &header[..])
+        .unwrap();
+
+    assert_eq!(header.verify_payload(payload).unwrap(), message);
+    assert_eq!(
+        header.verify_payload(&payload[..4]),
+        Err(DecodeError::IncompletePayload)
+    );
+    assert_eq!(
+        header.verify_payload(&[payload, &[0]].concat()),
+        Err(DecodeError::TrailingPayloadBytes(1))
+    );
+    let mut corrupt = payload.to_vec();
+    corrupt[0] ^= 1;
+    assert!(matches!(
+        header.verify_payload(&corrupt),
+        Err(DecodeError::InvalidChecksum { .. })
+    ));
+}
+
+fn block_with_transactions(count: u32) -> bitcoin::Block {
+    let mut block = bitcoin::blockdata::constants::genesis_block(Network::Bitcoin);
+    let coinbase = block.txdata[0].clone();
+    for i in 1..count {
+        let mut tx = coinbase.clone();
+        tx.lock_time = bitcoin::absolute::LockTime::from_consensus(i);
+        block.txdata.push(tx);
+    }
+    block
+}
+
+/// Feeds one encoded message to `decoder` in chunks of `chunk_size`.
+fn feed_message<D: Decoder>(
+    mut decoder: D,
+    bytes: &[u8],
+    chunk_size: usize,
+) -> Result<D::Value, D::Error> {
+    for mut chunk in bytes.chunks(chunk_size) {
+        decoder.decode_chunk(&mut chunk)?;
+        assert!(chunk.is_empty());
+    }
+    decoder.end()
+}
+
+#[test]
+fn block_encoder_streams_transactions() {
+    let block = block_with_transactions(5);
+    let expected = encode(&NetworkMessage::Block(block.clone()));
+
+    let mut encoder = bitcoin_codecs::V1BlockEncoder::new(Network::Bitcoin, &block);
+    let mut chunks = vec![encoder.encoded_chunk().to_vec()];
+    while encoder.next() {
+        chunks.push(encoder.encoded_chunk().to_vec());
+    }
+    // The frame up to the first transaction, then one chunk per transaction.
+    assert_eq!(chunks.len(), 6);
+    assert_eq!(chunks[1], bitcoin::consensus::serialize(&block.txdata[0]));
+    assert_eq!(chunks.concat(), expected);
+
+    let mut bytes = Vec::new();
+    bitcoin_codecs::V1BlockEncoder::new(Network::Bitcoin, &block)
+        .encode_sync(&mut bytes)
+        .unwrap();
+    assert_eq!(bytes, expected);
+}
+
+#[test]
+fn block_decoder_streams_transactions() {
+    let block = block_with_transactions(5);
+    let bytes = encode(&NetworkMessage::Block(block.clone()));
+    for chunk_size in [1, 7, 100, bytes.len()] {
+        let mut transactions = Vec::new();
+        let mut decoder = V1BlockDecoder::new(Network::Bitcoin, |tx| transactions.push(tx));
+        assert_eq!(
+            feed_message(&mut decoder, &bytes, chunk_size).unwrap(),
+            BlockMessage::Block {
+                header: block.header,
+                transactions: 5
+            }
+        );
+
+        // Other commands are decoded in full by the same decoder.
+        let ping = encode(&NetworkMessage::Ping(9));
+        assert_eq!(
+            feed_message(&mut decoder, &ping, chunk_size).unwrap(),
+            BlockMessage::Message(NetworkMessage::Ping(9))
+        );
+        drop(decoder);
+        assert_eq!(transactions, block.txdata);
+    }
+}
+
+#[test]
+fn block_decoder_rejects_malformed_blocks() {
+    let block = block_with_transactions(2);
+    let frame = |payload: Vec<u8>| {
+        encode(&NetworkMessage::Unknown {
+            command: CommandString::try_from("block").unwrap(),
+            payload,
+        })
+    };
+    let decode = |bytes: &[u8]| {
+        let mut decoder = V1BlockDecoder::new(Network::Bitcoin, |_| {});
+        feed_message(&mut decoder, bytes, 50)
+    };
+
+    let mut trailing = bitcoin::consensus::serialize(&block);
+    trailing.push(0);
+    assert_eq!(
+        decode(&frame(trailing)),
+        Err(DecodeError::TrailingPayloadBytes(1))
+    );
+
+    let mut truncated = bitcoin::consensus::serialize(&block);
+    truncated.truncate(truncated.len() - 1);
+    assert!(matches!(
+        decode(&frame(truncated)),
+        Err(DecodeError::InvalidPayload(_))
+    ));
+
+    let mut corrupt = encode(&NetworkMessage::Block(block));
+    *corrupt.last_mut().unwrap() ^= 1;
+    assert!(matches!(
+        decode(&corrupt),
+        Err(DecodeError::InvalidChecksum { .. })
+    ));
+
+    let mut decoder = V1BlockDecoder::new(Network::Bitcoin, |_| {});
+    assert_eq!((&mut decoder).end(), Err(DecodeError::EndOfStream));
+}
+
+#[test]
+fn bloom_filters_over_bip37_limits_are_rejected() {
+    use bitcoin::p2p::message_bloom::{BloomFlags, FilterAdd, FilterLoad};
+
+    let filter_load = |size: usize, hash_funcs: u32| {
+        NetworkMessage::FilterLoad(FilterLoad {
+            filter: vec![0xff; size],
+            hash_funcs,
+            tweak: 7,
+            flags: BloomFlags::All,
+        })
+    };
+    let largest = filter_load(MAX_BLOOM_FILTER_SIZE, MAX_BLOOM_HASH_FUNCS);
+    assert_eq!(decode(&encode(&largest)).unwrap(), largest);
+    for message in [
+        filter_load(MAX_BLOOM_FILTER_SIZE + 1, 1),
+        filter_load(1, MAX_BLOOM_HASH_FUNCS + 1),
+        NetworkMessage::FilterAdd(FilterAdd {
+            data: vec![0; MAX_FILTER_ADD_SIZE + 1],
+        }),
+    ] {
+        assert!(matches!(
+            decode(&encode(&message)),
+            Err(ReadError::Decode(DecodeError::InvalidPayload(_)))
+        ));
+    }
+}
+
+#[test]
+fn inventories_over_max_inv_size_are_rejected() {
+    use bitcoin::consensus::encode::VarInt;
+    use bitcoin::p2p::message_blockdata::Inventory;
+
+    let entry = Inventory::Transaction(bitcoin::Txid::all_zeros());
+    let largest = NetworkMessage::Inv(vec![entry; MAX_INV_SIZE as usize]);
+    assert_eq!(decode(&encode(&largest)).unwrap(), largest);
+
+    // The count alone is enough to reject the payload, no entries follow it.
+    for command in ["inv", "getdata", "notfound"] {
+        let count = bitcoin::consensus::serialize(&VarInt(MAX_INV_SIZE + 1));
+        let message = NetworkMessage::Unknown {
+            command: CommandString::try_from_static(command).unwrap(),
+            payload: count,
+        };
+        assert!(matches!(
+            decode(&encode(&message)),
+            Err(ReadError::Decode(DecodeError::InvalidPayload(_)))
+        ));
+    }
+}
+
+#[test]
+fn witness_inventory_types_survive_a_round_trip() {
+    use bitcoin::p2p::message_blockdata::Inventory;
+    use bitcoin::{BlockHash, Txid, Wtxid};
+
+    // Entries built from the raw type flags, as a peer would send them.
+    let mut payload = vec![3];
+    for (flag, hash) in [
+        (5u32, [1u8; 32]),
+        (0x4000_0001, [2; 32]),
+        (0x4000_0002, [3; 32]),
+    ] {
+        payload.extend(flag.to_le_bytes());
+        payload.extend(hash);
+    }
+    let expected = vec![
+        Inventory::WTx(Wtxid::from_byte_array([1; 32])),
+        Inventory::WitnessTransaction(Txid::from_byte_array([2; 32])),
+        Inventory::WitnessBlock(BlockHash::from_byte_array([3; 32])),
+    ];
+    for (command, message) in [
+        ("inv", NetworkMessage::Inv(expected.clone())),
+        ("getdata", NetworkMessage::GetData(expected.clone())),
+        ("notfound", NetworkMessage::NotFound(expected.clone())),
+    ] {
+        let raw = NetworkMessage::Unknown {
+            command: CommandString::try_from_static(command).unwrap(),
+            payload: payload.clone(),
+        };
+        assert_eq!(decode(&encode(&raw)).unwrap(), message);
+        // Encoding the typed entries gives back the same flags.
+        assert_eq!(encode(&message), encode(&raw));
+    }
+}
+
+#[test]
+fn parser_decoder_reuses_framing() {
+    use std::borrow::Cow;
+
+    #[derive(Clone)]
+    struct Pings;
+
+    impl PayloadParser for Pings {
+        type Message = Option<(u64, bool)>;
+
+        fn parse(
+            &self,
+            header: &bitcoin_codecs::Header,
+            payload: Cow<'_, [u8]>,
+        ) -> Result<Self::Message, DecodeError> {
+            if header.command.as_ref() != "ping" {
+                return Ok(None);
+            }
+            let nonce = payload[..]
+                .try_into()
+                .map_err(|_| DecodeError::TrailingPayloadBytes(0))?;
+            Ok(Some((
+                u64::from_le_bytes(nonce),
+                matches!(payload, Cow::Owned(_)),
+            )))
+        }
+    }
+
+    let mut bytes = encode(&NetworkMessage::Ping(5));
+    bytes.extend_from_slice(&encode(&NetworkMessage::Verack));
+    let mut decoder = V1ParserDecoder::new(Network::Bitcoin, Pings);
+    let mut reader = &bytes[..];
+    assert_eq!(
+        (&mut decoder).decode_sync(&mut reader).unwrap(),
+        Some((5, false))
+    );
+    assert_eq!((&mut decoder).decode_sync(&mut reader).unwrap(), None);
+
+    // A payload split across chunks is handed over owned.
+    let ping = encode(&NetworkMessage::Ping(6));
+    assert_eq!(
+        feed_message(&mut decoder, &ping, 30).unwrap(),
+        Some((6, true))
+    );
+
+    let mut corrupt = encode(&NetworkMessage::Ping(7));
+    corrupt[HEADER_SIZE] ^= 1;
+    assert!(matches!(
+        feed_message(&mut decoder, &corrupt, corrupt.len()),
+        Err(DecodeError::InvalidChecksum { .. })
+    ));
+    decoder.reset();
+    assert_eq!((&mut decoder).end(), Err(DecodeError::EndOfStream));
+}
+
+#[test]
+fn old_protocol_versions_decode_their_payloads() {
+    use bitcoin::consensus::serialize;
+    use bitcoin::p2p::message_network::VersionMessage;
+    use bitcoin::p2p::{Address, ServiceFlags};
+
+    let frame = |command: &'static str, payload: Vec<u8>| {
+        encode(&NetworkMessage::Unknown {
+            command: CommandString::try_from_static(command).unwrap(),
+            payload,
+        })
+    };
+    let address = Address::new(&"10.0.0.1:8333".parse().unwrap(), ServiceFlags::NETWORK);
+    let version = VersionMessage {
+        version: 60002,
+        services: ServiceFlags::NETWORK,
+        timestamp: 1_300_000_000,
+        receiver: address.clone(),
+        sender: address.clone(),
+        nonce: 3,
+        user_agent: "/Satoshi:0.6.0/".to_string(),
+        start_height: 200_000,
+        relay: true,
+    };
+    let mut version_payload = serialize(&version);
+    version_payload.pop();
+    let mut addr_payload = vec![2];
+    for _ in 0..2 {
+        addr_payload.extend_from_slice(&serialize(&address));
+    }
+    let old_version = frame("version", version_payload);
+    let untimed_addr = frame("addr", addr_payload);
+
+    // Without a version the latest serialization is expected.
+    for bytes in [&old_version, &untimed_addr] {
+        assert!(matches!(
+            decode(bytes),
+            Err(ReadError::Decode(DecodeError::InvalidPayload(_)))
+        ));
+    }
+
+    let mut decoder = V1MessageDecoder::builder().protocol_version(60002).build();
+    assert_eq!(
+        decoder.feed(&mut &old_version[..]).unwrap(),
+        Some(NetworkMessage::Version(version))
+    );
+    decoder.set_protocol_version(31401);
+    assert_eq!(
+        decoder.feed(&mut &untimed_addr[..]).unwrap(),
+        Some(NetworkMessage::Addr(vec![
+            (0, address.clone()),
+            (0, address)
+        ]))
+    );
+
+    // Current peers keep their timestamps.
+    decoder.set_protocol_version(70016);
+    let timed = NetworkMessage::Addr(vec![(
+        7,
+        Address::new(&"10.0.0.2:8333".parse().unwrap(), ServiceFlags::NONE),
+    )]);
+    assert_eq!(decoder.feed(&mut &encode(&timed)[..]).unwrap(), Some(timed));
+}
+
+#[test]
+fn lenient_version_fills_in_missing_trailing_fields() {
+    use bitcoin::consensus::serialize;
+    use bitcoin::p2p::message_network::VersionMessage;
+    use bitcoin::p2p::{Address, ServiceFlags};
+
+    let address = Address::new(&"10.0.0.1:8333".parse().unwrap(), ServiceFlags::NETWORK);
+    let full = VersionMessage {
+        version: 70016,
+        services: ServiceFlags::NETWORK,
+        timestamp: 1_700_000_000,
+        receiver: address.clone(),
+        sender: address.clone(),
+        nonce: 3,
+        user_agent: "/odd:0.1/".to_string(),
+        start_height: 800_000,
+        relay: false,
+    };
+    let payload = serialize(&full);
+    let version = |len: usize| {
+        encode(&NetworkMessage::Unknown {
+            command: CommandString::try_from_static("version").unwrap(),
+            payload: payload[..len].to_vec(),
+        })
+    };
+    // Payloads ending right after the receiver's address and after the user agent.
+    let receiver_end = 4 + 8 + 8 + 26;
+    let user_agent_end = payload.len() - 5;
+
+    // Strict decoding wants every field.
+    assert!(matches!(
+        decode(&version(user_agent_end)),
+        Err(ReadError::Decode(DecodeError::InvalidPayload(_)))
+    ));
+
+    let mut decoder = V1MessageDecoder::builder().lenient_version(true).build();
+    let mut decode_lenient = |len| match decoder.feed(&mut &version(len)[..]) {
+        Ok(Some(NetworkMessage::Version(version))) => version,
+        other => panic!("unexpected result: {other:?}"),
+    };
+    assert_eq!(decode_lenient(payload.len()), full);
+    let truncated = decode_lenient(user_agent_end);
+    assert_eq!((truncated.start_height, truncated.relay), (0, true));
+    assert_eq!(truncated.user_agent, full.user_agent);
+    let minimal = decode_lenient(receiver_end);
+    assert_eq!(minimal.receiver, full.receiver);
+    assert_eq!((minimal.nonce, minimal.sender.port), (0, 0));
+    assert!(minimal.user_agent.is_empty());
+
+    // A field cut in half is still malformed.
+    assert!(matches!(
+        decoder.feed(&mut &version(receiver_end + 10)[..]),
+        Err(DecodeError::InvalidPayload(_))
+    ));
+}
+
+#[test]
+fn buffered_header_decodes_like_the_decoder() {
+    let bytes = encode(&NetworkMessage::Ping(1));
+    let header: &[u8; HEADER_SIZE] = bytes[..HEADER_SIZE].try_into().unwrap();
+    let decoded = HeaderDecoder::new(Network::Bitcoin)
+        .decode_sync(&mut &header[..])
+        .unwrap();
+    assert_eq!(decode_header(header, Network::Bitcoin).unwrap(), decoded);
+
+    assert!(matches!(
+        decode_header(header, Network::Testnet),
+        Err(DecodeError::WrongMagic { .. })
+    ));
+    assert_eq!(
+        HeaderDecoder::with_max_payload(Network::Bitcoin, 4).decode_array(header),
+        Err(DecodeError::PayloadTooLarge { length: 8, max: 4 })
+    );
+    let mut padded = *header;
+    padded[4 + 5] = b'x';
+    assert!(matches!(
+        decode_header(&padded, Network::Bitcoin),
+        Err(DecodeError::CommandNotNullPadded { .. })
+    ));
+}
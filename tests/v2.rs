@@ -0,0 +1,210 @@
+use bitcoin::hex::FromHex;
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::secp256k1::SecretKey;
+use bitcoin::Network;
+use bitcoin_codecs::{
+    DecodeError, DecoderExt, Role, SessionKeys, V2Handshake, V2MessageDecoder, V2MessageEncoder,
+};
+use push_decode::{Encoder, ReadError};
+
+fn hex<const N: usize>(s: &str) -> [u8; N] {
+    <[u8; N]>::from_hex(s).unwrap()
+}
+
+fn secret_key(s: &str) -> SecretKey {
+    SecretKey::from_slice(&hex::<32>(s)).unwrap()
+}
+
+// BIP-324 test vector 1, we are the initiator.
+#[test]
+fn handshake_initiator_vector() {
+    let handshake = V2Handshake::with_ellswift(
+        Network::Bitcoin,
+        Role::Initiator,
+        secret_key("61062ea5071d800bbfd59e2e8b53d47d194b095ae5a4df04936b49772ef0d4d7"),
+        hex("ec0adff257bbfe500c188c80b4fdd640f6b45a482bbc15fc7cef5931deff0aa186f6eb9bba7b85dc4dcc28b28722de1e3d9108b985e2967045668f66098e475b"),
+    );
+    let garbage = [0x42; 10];
+    let mut received = hex::<64>("a4a94dfce69b4a2a0a099313d10f9f7e7d649d60501c9e1d274c300e0d89aafaffffffffffffffffffffffffffffffffffffffffffffffffffffffff8faf88d5").to_vec();
+    received.extend_from_slice(&garbage);
+
+    let mut reader = &received[..];
+    let keys = handshake.decode_sync(&mut reader).unwrap();
+    // The peer's garbage is left for the caller.
+    assert_eq!(reader, &garbage[..]);
+
+    assert_eq!(
+        keys.initiator_length_key,
+        hex("9a6478b5fbab1f4dd2f78994b774c03211c78312786e602da75a0d1767fb55cf")
+    );
+    assert_eq!(
+        keys.initiator_packet_key,
+        hex("7d0c7820ba6a4d29ce40baf2caa6035e04f1e1cefd59f3e7e59e9e5af84f1f51")
+    );
+    assert_eq!(
+        keys.responder_length_key,
+        hex("17bc726421e4054ac6a1d54915085aaa766f4d3cf67bbd168e6080eac289d15e")
+    );
+    assert_eq!(
+        keys.responder_packet_key,
+        hex("9f0fc1c0e85fd9a8eee07e6fc41dba2ff54c7729068a239ac97c37c524cca1c0")
+    );
+    assert_eq!(
+        keys.initiator_garbage_terminator,
+        hex("faef555dfcdb936425d84aba524758f3")
+    );
+    assert_eq!(
+        keys.responder_garbage_terminator,
+        hex("02cb8ff24307a6e27de3b4e7ea3fa65b")
+    );
+}
+
+// BIP-324 test vector 2, we are the responder.
+#[test]
+fn handshake_responder_vector() {
+    let handshake = V2Handshake::with_ellswift(
+        Network::Bitcoin,
+        Role::Responder,
+        secret_key("1f9c581b35231838f0f17cf0c979835baccb7f3abbbb96ffcc318ab71e6e126f"),
+        hex("a1855e10e94e00baa23041d916e259f7044e491da6171269694763f018c7e63693d29575dcb464ac816baa1be353ba12e3876cba7628bd0bd8e755e721eb0140"),
+    );
+    let received = hex::<64>("fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f0000000000000000000000000000000000000000000000000000000000000000");
+
+    let keys = handshake.decode_sync(&mut &received[..]).unwrap();
+    assert_eq!(
+        keys.session_id,
+        hex("9267c54560607de73f18c563b76a2442718879c52dd39852885d4a3c9912c9ea")
+    );
+}
+
+// Lengths of the BIP-324 packet encoding vectors 1 and 2, which are the second
+// and the thousandth packet each side sends.
+#[cfg(feature = "test-util")]
+#[test]
+fn length_decoder_vectors() {
+    use bitcoin_codecs::test_util::V2LengthDecoder;
+    use push_decode::Decoder;
+
+    let mut lengths = V2LengthDecoder::new(hex(
+        "9a6478b5fbab1f4dd2f78994b774c03211c78312786e602da75a0d1767fb55cf",
+    ));
+    (&mut lengths).bytes_received(&[0; 3]).unwrap();
+    (&mut lengths).end().unwrap();
+    let ciphertext = hex::<21>("7530d2a18720162ac09c25329a60d75adf36eda3c3");
+    let mut reader = &ciphertext[..];
+    assert_eq!((&mut lengths).decode_sync(&mut reader).unwrap(), 1);
+    // Only the length is consumed, the packet follows.
+    assert_eq!(reader, &ciphertext[3..]);
+
+    // The keys of the responder in vector 2, whose lengths cross a rekey.
+    let handshake = V2Handshake::with_ellswift(
+        Network::Bitcoin,
+        Role::Responder,
+        secret_key("1f9c581b35231838f0f17cf0c979835baccb7f3abbbb96ffcc318ab71e6e126f"),
+        hex("a1855e10e94e00baa23041d916e259f7044e491da6171269694763f018c7e63693d29575dcb464ac816baa1be353ba12e3876cba7628bd0bd8e755e721eb0140"),
+    );
+    let received = hex::<64>("fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f0000000000000000000000000000000000000000000000000000000000000000");
+    let keys = handshake.decode_sync(&mut &received[..]).unwrap();
+    let mut lengths = V2LengthDecoder::new(keys.responder_length_key);
+    for _ in 0..999 {
+        (&mut lengths).bytes_received(&[0; 3]).unwrap();
+        (&mut lengths).end().unwrap();
+    }
+    let ciphertext = hex::<3>("1da1bc");
+    assert_eq!(
+        (&mut lengths).decode_sync(&mut &ciphertext[..]).unwrap(),
+        17
+    );
+
+    // A partial length is cut off.
+    (&mut lengths).bytes_received(&[0; 2]).unwrap();
+    assert!(matches!(
+        (&mut lengths).end(),
+        Err(DecodeError::IncompleteHeader)
+    ));
+}
+
+fn handshakes() -> (V2Handshake, V2Handshake) {
+    let initiator = V2Handshake::new(
+        Network::Signet,
+        Role::Initiator,
+        SecretKey::from_slice(&[1; 32]).unwrap(),
+        [2; 32],
+    );
+    let responder = V2Handshake::new(
+        Network::Signet,
+        Role::Responder,
+        SecretKey::from_slice(&[3; 32]).unwrap(),
+        [4; 32],
+    );
+    (initiator, responder)
+}
+
+#[test]
+fn handshake_both_sides_agree() {
+    let (initiator, responder) = handshakes();
+    let to_responder = initiator.initial_message(&[]).unwrap();
+    let to_initiator = responder.initial_message(&[0xff; 32]).unwrap();
+
+    let initiator_keys = initiator.decode_sync(&mut &to_initiator[..]).unwrap();
+    let responder_keys = responder.decode_sync(&mut &to_responder[..]).unwrap();
+    assert_eq!(initiator_keys, responder_keys);
+}
+
+#[cfg(feature = "rand")]
+#[test]
+fn generated_handshakes_agree() {
+    let initiator = V2Handshake::generate(Network::Bitcoin, Role::Initiator);
+    let responder = V2Handshake::generate(Network::Bitcoin, Role::Responder);
+    assert_ne!(initiator.public_key(), responder.public_key());
+
+    let to_responder = initiator.initial_message(&[]).unwrap();
+    let to_initiator = responder.initial_message(&[]).unwrap();
+    let initiator_keys = initiator.decode_sync(&mut &to_initiator[..]).unwrap();
+    let responder_keys = responder.decode_sync(&mut &to_responder[..]).unwrap();
+    assert_eq!(initiator_keys, responder_keys);
+}
+
+// Keys shared by both sides and the bytes the initiator sent after its public key.
+fn session(garbage: &[u8]) -> (SessionKeys, Vec<u8>) {
+    let (initiator, responder) = handshakes();
+    let to_responder = initiator.initial_message(garbage).unwrap();
+    let to_initiator = responder.initial_message(&[]).unwrap();
+    let keys = initiator.decode_sync(&mut &to_initiator[..]).unwrap();
+
+    let mut reader = &to_responder[..];
+    assert_eq!(responder.decode_sync(&mut reader).unwrap(), keys);
+    (keys, reader.to_vec())
+}
+
+#[test]
+fn decoys_are_skipped() {
+    let garbage = [0x99; 100];
+    let (keys, mut stream) = session(&garbage);
+    let messages = [
+        NetworkMessage::Ping(1),
+        NetworkMessage::Verack,
+        NetworkMessage::Pong(2),
+    ];
+
+    let mut encoder = V2MessageEncoder::new(&keys, Role::Initiator, &garbage);
+    encoder.encode_decoy(10).write_to_vec(&mut stream);
+    for message in &messages {
+        encoder.encode(message).write_to_vec(&mut stream);
+        encoder.encode_decoy(0).write_to_vec(&mut stream);
+        encoder.encode_decoy(300).write_to_vec(&mut stream);
+    }
+
+    let mut decoder = V2MessageDecoder::new(&keys, Role::Responder);
+    let mut reader = &stream[..];
+    for message in &messages {
+        assert_eq!(&(&mut decoder).decode_sync(&mut reader).unwrap(), message);
+    }
+    // Only the trailing decoys are left, they never produce a message and the
+    // stream then ends cleanly on a packet boundary.
+    assert!(matches!(
+        (&mut decoder).decode_sync(&mut reader),
+        Err(ReadError::Decode(DecodeError::EndOfStream))
+    ));
+    assert!(reader.is_empty());
+}
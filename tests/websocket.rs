@@ -0,0 +1,75 @@
+#![cfg(feature = "websocket")]
+
+use bitcoin::p2p::message::NetworkMessage;
+use bitcoin::Network;
+use bitcoin_codecs::{DecodeError, V1WebSocketDecoder};
+
+#[test]
+fn frames_are_buffered_across_websocket_messages() {
+    let mut stream = Vec::new();
+    for message in [
+        NetworkMessage::Verack,
+        NetworkMessage::Ping(1),
+        NetworkMessage::Pong(1),
+    ] {
+        stream.extend(V1WebSocketDecoder::encode(Network::Regtest, &message));
+    }
+
+    // WebSocket messages which split the frames at arbitrary points, one of
+    // them holding the end of a frame, a whole frame and the start of another.
+    let mut decoder = V1WebSocketDecoder::new(Network::Regtest);
+    let mut decoded = Vec::new();
+    for payload in [&stream[..5], &stream[5..30], &stream[30..70], &stream[70..]] {
+        decoded.extend(decoder.push(payload).map(Result::unwrap));
+    }
+    assert_eq!(
+        decoded,
+        [
+            NetworkMessage::Verack,
+            NetworkMessage::Ping(1),
+            NetworkMessage::Pong(1),
+        ]
+    );
+    assert_eq!(decoder.finish(), Ok(()));
+}
+
+#[test]
+fn cut_off_frame_is_reported_on_finish() {
+    let ping = V1WebSocketDecoder::encode(Network::Regtest, &NetworkMessage::Ping(1));
+    let mut decoder = V1WebSocketDecoder::new(Network::Regtest);
+    assert_eq!(decoder.push(&ping[..30]).count(), 0);
+    assert_eq!(decoder.finish(), Err(DecodeError::IncompletePayload));
+
+    // The decoder starts over for the next tunnel.
+    let messages: Vec<_> = decoder.push(&ping).collect();
+    assert_eq!(messages, [Ok(NetworkMessage::Ping(1))]);
+}
+
+#[test]
+fn finish_after_a_fatal_error_resets() {
+    let ping = V1WebSocketDecoder::encode(Network::Regtest, &NetworkMessage::Ping(1));
+
+    // A header for another network.
+    let mut decoder = V1WebSocketDecoder::new(Network::Bitcoin);
+    let messages: Vec<_> = decoder.push(&ping).collect();
+    assert!(matches!(
+        messages[..],
+        [Err(DecodeError::WrongMagic { .. })]
+    ));
+    assert_eq!(decoder.finish(), Ok(()));
+    let ping = V1WebSocketDecoder::encode(Network::Bitcoin, &NetworkMessage::Ping(1));
+    let messages: Vec<_> = decoder.push(&ping).collect();
+    assert_eq!(messages, [Ok(NetworkMessage::Ping(1))]);
+
+    // A payload which does not match its checksum.
+    let mut corrupt = ping.clone();
+    corrupt[24] ^= 1;
+    let messages: Vec<_> = decoder.push(&corrupt).collect();
+    assert!(matches!(
+        messages[..],
+        [Err(DecodeError::InvalidChecksum { .. })]
+    ));
+    assert_eq!(decoder.finish(), Ok(()));
+    let messages: Vec<_> = decoder.push(&ping).collect();
+    assert_eq!(messages, [Ok(NetworkMessage::Ping(1))]);
+}